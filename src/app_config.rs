@@ -1,4 +1,5 @@
 use crate::file_config::{FileConfig, FileDevice};
+use crate::ui::LayoutBbox;
 
 
 #[derive(Debug)]
@@ -10,9 +11,21 @@ pub struct DeviceTile {
    pub height: u16
 }
 
+impl DeviceTile {
+    pub fn bbox(&self) -> LayoutBbox {
+        LayoutBbox {
+            top: self.row,
+            left: self.col,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AppConfig {
    pub name: String,
+   #[allow(dead_code)] // TODO pass symbol to calculate_progress_bar
    pub symbol: String,
    pub devices: Vec<FileDevice>, 
    pub tiles: Vec<DeviceTile> 
@@ -20,8 +33,7 @@ pub struct AppConfig {
 
 
 impl AppConfig {
-    pub fn new(config_name: String, screen_width: u16, screen_height: u16) -> Self {
-        let file_config = FileConfig::new(config_name);
+    pub fn from_file_config(file_config: FileConfig, screen_width: u16, screen_height: u16) -> Self {
         let device_tiles = Self::get_device_tiles(&file_config.devices, screen_width, screen_height);
         AppConfig{
              name: file_config.name,
@@ -35,14 +47,14 @@ impl AppConfig {
         self.tiles = Self::get_device_tiles(&self.devices, new_w, new_h);
     }
 
-    fn get_device_tiles(devices: &Vec<FileDevice>, new_w: u16, new_h: u16) -> Vec<DeviceTile> {
+    fn get_device_tiles(devices: &[FileDevice], new_w: u16, new_h: u16) -> Vec<DeviceTile> {
         let mut tiles: Vec<DeviceTile> = Vec::new();
         
         if Self::check_device_tiles_overlap(devices) {
             panic!("Device overlaped. Check config");
         }
 
-        let (col_scale, row_scale) = Self::get_tile_scale(&devices.clone(), new_w, new_h);
+        let (col_scale, row_scale) = Self::get_tile_scale(devices, new_w, new_h);
         for device in devices {
             tiles.push(
                 DeviceTile {    
@@ -57,24 +69,20 @@ impl AppConfig {
         tiles
     }
 
-    fn check_device_tiles_overlap(devices: &Vec<FileDevice>) -> bool {
-        for ind in 0..(devices.len() - 1) {
-            println!("Curent number of devices {ind}");
-            
-            if devices[ind].col >= devices[ind + 1].col + devices[ind + 1].width ||
-                devices[ind + 1].col >= devices[ind].col + devices[ind].width {
-                    return false;
-            }
-    
-            if devices[ind].row >= devices[ind + 1].row + devices[ind + 1].height ||
-                devices[ind + 1].row >= devices[ind].row + devices[ind].height {
-                    return false;
+    fn check_device_tiles_overlap(devices: &[FileDevice]) -> bool {
+        for (ind, device) in devices.iter().enumerate() {
+            for other in &devices[ind + 1..] {
+                let cols_overlap = device.col < other.col + other.width && other.col < device.col + device.width;
+                let rows_overlap = device.row < other.row + other.height && other.row < device.row + device.height;
+                if cols_overlap && rows_overlap {
+                    return true;
+                }
             }
         }
-        return true;
+        false
     }
 
-    fn get_tile_scale(devices: &Vec<FileDevice>, term_width: u16, term_height:u16) -> (f32, f32)  {
+    fn get_tile_scale(devices: &[FileDevice], term_width: u16, term_height:u16) -> (f32, f32)  {
         // get grid
        let mut max_w= 1;
        let mut max_h= 1;
@@ -0,0 +1,220 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_config::BatteryTileOptions;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+use crate::sysfs::{read_number, read_trimmed};
+
+pub struct BatteryInfo {
+    pub name: String,
+    // Charging, Discharging, Full, Not charging
+    pub status: String,
+    // 0.0..1.0
+    pub capacity: f64,
+    pub power: Option<f64>,
+    pub time_remaining: Option<f64>,
+    // full charge capacity against design capacity
+    pub health: Option<f64>,
+}
+
+pub struct AdapterInfo {
+    pub name: String,
+    pub online: bool,
+}
+
+pub struct PowerSupplyInfo {
+    sysfs_root: PathBuf,
+    pub batteries: Vec<BatteryInfo>,
+    pub adapters: Vec<AdapterInfo>,
+}
+
+impl PowerSupplyInfo {
+    pub fn new(options: &BatteryTileOptions) -> Self {
+        let mut power_supply_info = PowerSupplyInfo {
+            sysfs_root: PathBuf::from(&options.sysfs_root),
+            batteries: Vec::new(),
+            adapters: Vec::new(),
+        };
+        power_supply_info.update();
+        power_supply_info
+    }
+
+    // filled by `replay`
+    pub fn for_replay() -> Self {
+        PowerSupplyInfo {
+            sysfs_root: PathBuf::new(),
+            batteries: Vec::new(),
+            adapters: Vec::new(),
+        }
+    }
+
+    // adapters, time remaining and health are not recorded
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("battery", tile);
+        self.batteries = sample
+            .get_instances(&collector)
+            .into_iter()
+            .map(|name| BatteryInfo {
+                name: name.to_string(),
+                status: sample.get_text(&format!("{collector}.{name}.status")).unwrap_or("Unknown").to_string(),
+                capacity: sample.get_number(&format!("{collector}.{name}.capacity_percent")).unwrap_or(0.0) / 100.0,
+                power: sample.get_number(&format!("{collector}.{name}.power_watts")),
+                time_remaining: None,
+                health: None,
+            })
+            .collect();
+    }
+
+    pub fn update(&mut self) {
+        self.batteries.clear();
+        self.adapters.clear();
+
+        let mut supply_dirs: Vec<PathBuf> = match fs::read_dir(self.sysfs_root.join("class/power_supply")) {
+            Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+            Err(_err) => Vec::new(),
+        };
+        supply_dirs.sort();
+
+        for supply_dir in supply_dirs {
+            let name = supply_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+            match read_trimmed(&supply_dir.join("type")).as_deref() {
+                Some("Battery") => {
+                    // peripherals like wireless mouse are reported as batteries too
+                    if read_trimmed(&supply_dir.join("scope")).as_deref() == Some("Device") {
+                        continue;
+                    }
+                    self.batteries.push(read_battery(name, &supply_dir));
+                }
+                Some("Mains") | Some("USB") => self.adapters.push(AdapterInfo {
+                    name,
+                    online: read_number(&supply_dir.join("online")) == Some(1.0),
+                }),
+                _ => (),
+            }
+        }
+    }
+}
+
+// Batteries report either energy (µWh, µW) or charge (µAh, µA) values
+fn read_battery(name: String, supply_dir: &Path) -> BatteryInfo {
+    let value = |file: &str| read_number(&supply_dir.join(file));
+
+    let status = read_trimmed(&supply_dir.join("status")).unwrap_or_else(|| "Unknown".to_string());
+    let voltage = value("voltage_now").map(|voltage| voltage / 1e6);
+
+    // rate is taken from the same family as the capacity, current_now is signed on some drivers
+    let (now, full, design, rate, power) = match value("energy_now") {
+        Some(energy_now) => {
+            let rate = value("power_now").map(f64::abs);
+            (
+                Some(energy_now),
+                value("energy_full"),
+                value("energy_full_design"),
+                rate,
+                rate.map(|power| power / 1e6),
+            )
+        }
+        None => {
+            let rate = value("current_now").map(f64::abs);
+            let power = match (rate, voltage) {
+                (Some(current), Some(voltage)) => Some(current / 1e6 * voltage),
+                _ => None,
+            };
+            (value("charge_now"), value("charge_full"), value("charge_full_design"), rate, power)
+        }
+    };
+
+    let capacity = match (value("capacity"), now, full) {
+        (Some(capacity), _, _) => capacity / 100.0,
+        (None, Some(now), Some(full)) if full > 0.0 => now / full,
+        _ => 0.0,
+    };
+
+    // rate is per hour, both energy and charge based values give hours here
+    let time_remaining = match (status.as_str(), now, full, rate) {
+        (_, _, _, Some(rate)) if rate <= 0.0 => None,
+        ("Discharging", Some(now), _, Some(rate)) => Some(now / rate * 3600.0),
+        ("Charging", Some(now), Some(full), Some(rate)) => Some((full - now).max(0.0) / rate * 3600.0),
+        _ => None,
+    };
+
+    let health = match (full, design) {
+        (Some(full), Some(design)) if design > 0.0 => Some(full / design),
+        _ => None,
+    };
+
+    BatteryInfo {
+        name,
+        status,
+        capacity: capacity.clamp(0.0, 1.0),
+        power,
+        time_remaining,
+        health,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+
+    fn read_supplies(fixture: &Fixture) -> PowerSupplyInfo {
+        PowerSupplyInfo::new(&BatteryTileOptions { sysfs_root: fixture.root() })
+    }
+
+    #[test]
+    fn energy_battery_takes_power_from_power_now() {
+        let fixture = Fixture::new();
+        fixture
+            .write("class/power_supply/BAT0/type", "Battery\n")
+            .write("class/power_supply/BAT0/status", "Discharging\n")
+            .write("class/power_supply/BAT0/energy_now", "25000000\n")
+            .write("class/power_supply/BAT0/energy_full", "50000000\n")
+            .write("class/power_supply/BAT0/energy_full_design", "62500000\n")
+            .write("class/power_supply/BAT0/power_now", "12500000\n")
+            .write("class/power_supply/AC/type", "Mains\n")
+            .write("class/power_supply/AC/online", "0\n")
+            .write("class/power_supply/hidpp_battery_0/type", "Battery\n")
+            .write("class/power_supply/hidpp_battery_0/scope", "Device\n");
+
+        let power_supply_info = read_supplies(&fixture);
+        assert_eq!(power_supply_info.batteries.len(), 1);
+        let battery = &power_supply_info.batteries[0];
+        assert_eq!((battery.name.as_str(), battery.status.as_str()), ("BAT0", "Discharging"));
+        assert!((battery.capacity - 0.5).abs() < 1e-9);
+        assert!((battery.power.unwrap() - 12.5).abs() < 1e-9);
+        assert!((battery.time_remaining.unwrap() - 7200.0).abs() < 1e-9);
+        assert!((battery.health.unwrap() - 0.8).abs() < 1e-9);
+        assert_eq!(power_supply_info.adapters.len(), 1);
+        assert!(!power_supply_info.adapters[0].online);
+    }
+
+    #[test]
+    fn charge_battery_takes_power_from_current_and_voltage() {
+        let fixture = Fixture::new();
+        // power_now without energy_now must not be mixed with the charge values
+        fixture
+            .write("class/power_supply/BAT1/type", "Battery\n")
+            .write("class/power_supply/BAT1/status", "Charging\n")
+            .write("class/power_supply/BAT1/capacity", "40\n")
+            .write("class/power_supply/BAT1/charge_now", "2000000\n")
+            .write("class/power_supply/BAT1/charge_full", "4000000\n")
+            .write("class/power_supply/BAT1/current_now", "-1000000\n")
+            .write("class/power_supply/BAT1/voltage_now", "12000000\n")
+            .write("class/power_supply/BAT1/power_now", "1000000\n");
+
+        let battery = &read_supplies(&fixture).batteries[0];
+        assert!((battery.capacity - 0.4).abs() < 1e-9);
+        assert!((battery.power.unwrap() - 12.0).abs() < 1e-9);
+        assert!((battery.time_remaining.unwrap() - 7200.0).abs() < 1e-9);
+        assert_eq!(battery.health, None);
+    }
+
+    #[test]
+    fn missing_power_supply_class_gives_no_supplies() {
+        let fixture = Fixture::new();
+        let power_supply_info = read_supplies(&fixture);
+        assert!(power_supply_info.batteries.is_empty() && power_supply_info.adapters.is_empty());
+    }
+}
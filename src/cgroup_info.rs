@@ -0,0 +1,362 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::cpu_topology::parse_cpu_list;
+use crate::file_config::CgroupsTileOptions;
+use crate::patterns::is_included;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+use crate::sysfs::{read_number, read_trimmed};
+
+// counters from cpu.stat and io.stat
+#[derive(Default, Clone, Copy)]
+pub struct CgroupCounters {
+    pub usage_usec: u64,
+    pub nr_periods: u64,
+    pub nr_throttled: u64,
+    pub throttled_usec: u64,
+    pub io_read_bytes: u64,
+    pub io_write_bytes: u64,
+}
+
+pub struct Cgroup {
+    path: PathBuf,
+    last_update: Instant,
+    // totals since the cgroup was created, None in replay
+    pub counters: Option<CgroupCounters>,
+    pub name: String,
+    // cores used since previous update
+    pub cpu_usage: f64,
+    // cores allowed by cpu.max
+    pub cpu_limit: Option<f64>,
+    // cpus allowed by cpuset.cpus.effective
+    pub cpuset: Option<Vec<usize>>,
+    pub memory_current: u64,
+    pub memory_max: Option<u64>,
+    // share of periods throttled since previous update, 0.0..1.0
+    pub throttled: f64,
+    pub nr_throttled: u64,
+    pub throttled_time: f64,
+    // per second
+    pub io_read: f64,
+    pub io_write: f64,
+    // io.max lines like "8:0 rbps=1048576 wbps=max riops=max wiops=max"
+    pub io_limits: Vec<String>,
+}
+
+impl Cgroup {
+    pub fn new(path: PathBuf) -> Self {
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| "/".to_string());
+        let mut cgroup = Cgroup {
+            path,
+            last_update: Instant::now(),
+            counters: None,
+            name,
+            cpu_usage: 0.0,
+            cpu_limit: None,
+            cpuset: None,
+            memory_current: 0,
+            memory_max: None,
+            throttled: 0.0,
+            nr_throttled: 0,
+            throttled_time: 0.0,
+            io_read: 0.0,
+            io_write: 0.0,
+            io_limits: Vec::new(),
+        };
+        cgroup.update();
+        cgroup
+    }
+
+    // limits and throttling counters are not recorded
+    fn from_sample(name: &str, sample: &RecordedSample, collector: &str) -> Self {
+        let get_number = |field: &str| sample.get_number(&format!("{collector}.{name}.{field}")).unwrap_or(0.0);
+        Cgroup {
+            path: PathBuf::new(),
+            last_update: Instant::now(),
+            counters: None,
+            name: name.to_string(),
+            cpu_usage: get_number("cpu_cores"),
+            cpu_limit: None,
+            cpuset: None,
+            memory_current: get_number("memory_bytes") as u64,
+            memory_max: None,
+            throttled: get_number("throttled_percent") / 100.0,
+            nr_throttled: 0,
+            throttled_time: 0.0,
+            io_read: get_number("io_read_bytes_per_second"),
+            io_write: get_number("io_write_bytes_per_second"),
+            io_limits: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        // "max 100000" or "200000 100000", quota and period in usec
+        self.cpu_limit = read_trimmed(&self.path.join("cpu.max")).and_then(|cpu_max| {
+            let (quota, period) = cpu_max.split_once(' ')?;
+            Some(quota.parse::<f64>().ok()? / period.parse::<f64>().ok()?)
+        });
+        self.cpuset = read_trimmed(&self.path.join("cpuset.cpus.effective"))
+            .map(|list| parse_cpu_list(&list))
+            .filter(|cpus| !cpus.is_empty());
+        self.memory_current = read_number(&self.path.join("memory.current")).unwrap_or(0.0) as u64;
+        self.memory_max = read_number(&self.path.join("memory.max")).map(|max| max as u64);
+        self.io_limits = fs::read_to_string(self.path.join("io.max"))
+            .map(|content| content.lines().map(|line| line.to_string()).collect())
+            .unwrap_or_default();
+
+        let counters = self.read_counters();
+        if let Some(previous) = self.counters {
+            if elapsed > 0.0 {
+                let delta = |current: u64, previous: u64| current.saturating_sub(previous) as f64;
+                self.cpu_usage = delta(counters.usage_usec, previous.usage_usec) / 1e6 / elapsed;
+                let periods = delta(counters.nr_periods, previous.nr_periods);
+                self.throttled = if periods > 0.0 {
+                    delta(counters.nr_throttled, previous.nr_throttled) / periods
+                } else {
+                    0.0
+                };
+                self.io_read = delta(counters.io_read_bytes, previous.io_read_bytes) / elapsed;
+                self.io_write = delta(counters.io_write_bytes, previous.io_write_bytes) / elapsed;
+            }
+        }
+        self.nr_throttled = counters.nr_throttled;
+        self.throttled_time = counters.throttled_usec as f64 / 1e6;
+        self.counters = Some(counters);
+    }
+
+    pub fn get_children(&self) -> Vec<PathBuf> {
+        let mut children: Vec<PathBuf> = match fs::read_dir(&self.path) {
+            Ok(entries) => entries
+                .flatten()
+                .filter(|entry| entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false))
+                .map(|entry| entry.path())
+                .collect(),
+            Err(_err) => Vec::new(),
+        };
+        children.sort();
+        children
+    }
+
+    fn read_counters(&self) -> CgroupCounters {
+        let mut counters = CgroupCounters::default();
+
+        let cpu_stat = fs::read_to_string(self.path.join("cpu.stat")).unwrap_or_default();
+        for line in cpu_stat.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+            let value = value.trim().parse().unwrap_or(0);
+            match key {
+                "usage_usec" => counters.usage_usec = value,
+                "nr_periods" => counters.nr_periods = value,
+                "nr_throttled" => counters.nr_throttled = value,
+                "throttled_usec" => counters.throttled_usec = value,
+                _ => (),
+            }
+        }
+
+        // "8:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0"
+        let io_stat = fs::read_to_string(self.path.join("io.stat")).unwrap_or_default();
+        for field in io_stat.split_whitespace() {
+            match field.split_once('=') {
+                Some(("rbytes", value)) => counters.io_read_bytes += value.parse().unwrap_or(0),
+                Some(("wbytes", value)) => counters.io_write_bytes += value.parse().unwrap_or(0),
+                _ => (),
+            }
+        }
+        counters
+    }
+}
+
+// cgroup v2 hierarchy is mounted either at the root or at "unified" on hybrid setups
+pub fn get_cgroupfs_v2_root(cgroupfs_root: &Path) -> Option<PathBuf> {
+    [cgroupfs_root.to_path_buf(), cgroupfs_root.join("unified")]
+        .into_iter()
+        .find(|root| root.join("cgroup.controllers").exists() || root.join("cgroup.procs").exists())
+}
+
+// "0::/system.slice/slurmstepd.scope" in /proc/self/cgroup is the v2 cgroup of tsm
+pub fn detect_own_cgroup(procfs_root: &Path, cgroupfs_root: &Path) -> Option<PathBuf> {
+    let v2_root = get_cgroupfs_v2_root(cgroupfs_root)?;
+    let content = fs::read_to_string(procfs_root.join("self/cgroup")).ok()?;
+    let relative_path = content.lines().find_map(|line| line.strip_prefix("0::"))?;
+
+    let path = v2_root.join(relative_path.trim_start_matches('/'));
+    // inside of a cgroup namespace the path can point outside of the mounted tree
+    if path.exists() {
+        Some(path)
+    } else {
+        Some(v2_root)
+    }
+}
+
+// child cgroups of one parent for the cgroups tile
+pub struct CgroupsInfo {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    pub parent: Option<Cgroup>,
+    pub children: Vec<Cgroup>,
+}
+
+impl CgroupsInfo {
+    pub fn new(options: &CgroupsTileOptions) -> Self {
+        let cgroupfs_root = PathBuf::from(&options.cgroupfs_root);
+        let parent_path = if options.path.is_empty() {
+            detect_own_cgroup(&PathBuf::from(&options.procfs_root), &cgroupfs_root)
+        } else {
+            get_cgroupfs_v2_root(&cgroupfs_root).map(|root| root.join(options.path.trim_start_matches('/')))
+        };
+
+        let mut cgroups_info = CgroupsInfo {
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            parent: parent_path.map(Cgroup::new),
+            children: Vec::new(),
+        };
+        cgroups_info.update();
+        cgroups_info
+    }
+
+    // filled by `replay`, include and exclude patterns still apply
+    pub fn for_replay(options: &CgroupsTileOptions) -> Self {
+        CgroupsInfo {
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+
+    // the parent is recorded before its children
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("cgroup", tile);
+        let mut cgroups = sample
+            .get_instances(&collector)
+            .into_iter()
+            .map(|name| Cgroup::from_sample(name, sample, &collector));
+        self.parent = cgroups.next();
+        self.children = cgroups.filter(|child| is_included(&child.name, &self.include, &self.exclude)).collect();
+    }
+
+    pub fn update(&mut self) {
+        let Some(parent) = &mut self.parent else {
+            return;
+        };
+        parent.update();
+
+        let mut children: Vec<Cgroup> = Vec::new();
+        for child_path in parent.get_children() {
+            // keep previous counters to calculate rates
+            let child = match self.children.iter().position(|child| child.path == child_path) {
+                Some(position) => {
+                    let mut child = self.children.swap_remove(position);
+                    child.update();
+                    child
+                }
+                None => Cgroup::new(child_path),
+            };
+            if is_included(&child.name, &self.include, &self.exclude) {
+                children.push(child);
+            }
+        }
+        children.sort_by(|first, second| second.cpu_usage.total_cmp(&first.cpu_usage));
+        self.children = children;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::time::Duration;
+
+    // throttled for 1ms in every throttled period, bytes are read from two devices
+    fn write_counters(fixture: &Fixture, path: &str, usage_usec: u64, nr_periods: u64, nr_throttled: u64, bytes: u64) {
+        fixture
+            .write(
+                &format!("{path}/cpu.stat"),
+                &format!(
+                    "usage_usec {usage_usec}\nuser_usec 0\nsystem_usec 0\nnr_periods {nr_periods}\n\
+                     nr_throttled {nr_throttled}\nthrottled_usec {}\n",
+                    nr_throttled * 1000
+                ),
+            )
+            .write(
+                &format!("{path}/io.stat"),
+                &format!("8:0 rbytes={bytes} wbytes={} rios=1 wios=1\n259:0 rbytes={bytes} wbytes=0\n", bytes * 2),
+            );
+    }
+
+    #[test]
+    fn finds_the_own_cgroup_of_the_hierarchy() {
+        let fixture = Fixture::new();
+        fixture
+            .write("proc/self/cgroup", "1:cpu,cpuacct:/user.slice\n0::/system.slice/job.scope\n")
+            .write("cgroup/unified/cgroup.controllers", "cpu io memory\n")
+            .write("cgroup/unified/system.slice/job.scope/cgroup.procs", "1\n");
+        let procfs_root = fixture.path().join("proc");
+        let cgroupfs_root = fixture.path().join("cgroup");
+
+        assert_eq!(
+            detect_own_cgroup(&procfs_root, &cgroupfs_root),
+            Some(cgroupfs_root.join("unified/system.slice/job.scope"))
+        );
+        // in a cgroup namespace the path may be outside of the mounted tree
+        fixture.write("proc/self/cgroup", "0::/../other.scope\n");
+        assert_eq!(detect_own_cgroup(&procfs_root, &cgroupfs_root), Some(cgroupfs_root.join("unified")));
+        assert_eq!(detect_own_cgroup(&procfs_root, &fixture.path().join("missing")), None);
+    }
+
+    #[test]
+    fn reads_limits_and_counters_of_child_cgroups() {
+        let fixture = Fixture::new();
+        fixture
+            .write("cgroup.controllers", "cpu io memory\n")
+            .write("system.slice/memory.current", "4096\n")
+            .write("system.slice/web.service/cpu.max", "200000 100000\n")
+            .write("system.slice/web.service/cpuset.cpus.effective", "0-2,5\n")
+            .write("system.slice/web.service/memory.current", "1048576\n")
+            .write("system.slice/web.service/memory.max", "max\n")
+            .write("system.slice/web.service/io.max", "8:0 rbps=1048576 wbps=max riops=max wiops=max\n")
+            .write("system.slice/db.service/cpu.max", "max 100000\n")
+            .write("system.slice/db.service/memory.max", "2097152\n");
+        write_counters(&fixture, "system.slice/web.service", 1_000_000, 100, 10, 1000);
+        write_counters(&fixture, "system.slice/db.service", 0, 0, 0, 0);
+
+        let mut cgroups_info = CgroupsInfo::new(&CgroupsTileOptions {
+            path: "/system.slice".to_string(),
+            cgroupfs_root: fixture.root(),
+            ..CgroupsTileOptions::default()
+        });
+        assert_eq!(cgroups_info.parent.as_ref().map(|parent| parent.memory_current), Some(4096));
+        let names: Vec<&str> = cgroups_info.children.iter().map(|child| child.name.as_str()).collect();
+        assert_eq!(names, ["db.service", "web.service"]);
+        let db = &cgroups_info.children[0];
+        assert_eq!((db.cpu_limit, db.memory_max, db.cpuset.as_ref()), (None, Some(2097152), None));
+        let web = &cgroups_info.children[1];
+        assert_eq!(web.cpu_limit, Some(2.0));
+        assert_eq!(web.cpuset, Some(vec![0, 1, 2, 5]));
+        assert_eq!((web.memory_current, web.memory_max), (1048576, None));
+        assert_eq!(web.io_limits, ["8:0 rbps=1048576 wbps=max riops=max wiops=max"]);
+        assert_eq!((web.nr_throttled, web.throttled_time), (10, 0.01));
+
+        // two seconds later the web service used 1.5 cores and was throttled in a quarter of the periods
+        write_counters(&fixture, "system.slice/web.service", 4_000_000, 140, 20, 5000);
+        for child in &mut cgroups_info.children {
+            child.last_update -= Duration::from_secs(2);
+        }
+        cgroups_info.update();
+        let web = &cgroups_info.children[0];
+        assert_eq!(web.name, "web.service");
+        assert!((web.cpu_usage - 1.5).abs() < 0.01);
+        assert!((web.throttled - 0.25).abs() < 1e-9);
+        // both devices count
+        assert!((web.io_read - 4000.0).abs() < 30.0);
+        assert!((web.io_write - 4000.0).abs() < 30.0);
+    }
+}
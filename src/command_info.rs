@@ -0,0 +1,272 @@
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use crate::file_config::{CommandFormat, CommandTileOptions};
+use crate::history::History;
+
+pub enum CommandOutput {
+    // nothing finished yet
+    Pending,
+    Text(String),
+    // value and share of the bar, 0.0..1.0 when known
+    Value { value: f64, total: Option<f64>, share: Option<f64> },
+    Error(String),
+}
+
+// command started by the tile, stdout and stderr are read in threads so a chatty command can't block on a full pipe
+struct RunningCommand {
+    child: Child,
+    started: Instant,
+    status: Option<ExitStatus>,
+    // a background process of the command can keep the pipes open after the shell exited
+    stdout: PipeOutput,
+    stderr: PipeOutput,
+}
+
+struct PipeOutput {
+    receiver: Receiver<String>,
+    output: Option<String>,
+}
+
+impl PipeOutput {
+    fn new(mut pipe: impl Read + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut output = String::new();
+            pipe.read_to_string(&mut output).ok();
+            sender.send(output).ok();
+        });
+        PipeOutput { receiver, output: None }
+    }
+
+    // true when the pipe was closed
+    fn poll(&mut self) -> bool {
+        if self.output.is_none() {
+            match self.receiver.try_recv() {
+                Ok(output) => self.output = Some(output),
+                Err(TryRecvError::Disconnected) => self.output = Some(String::new()),
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+        self.output.is_some()
+    }
+}
+
+pub struct CommandInfo {
+    command: String,
+    format: CommandFormat,
+    max: Option<f64>,
+    interval: Duration,
+    timeout: Duration,
+    last_start: Option<Instant>,
+    running: Option<RunningCommand>,
+    pub output: CommandOutput,
+    // shares for bar formats, raw values for numbers
+    pub history: History,
+}
+
+impl CommandInfo {
+    pub fn new(options: &CommandTileOptions) -> Self {
+        let mut command_info = CommandInfo {
+            command: options.command.clone(),
+            format: options.format,
+            max: options.max,
+            interval: Duration::from_secs(options.interval),
+            timeout: Duration::from_secs(options.timeout),
+            last_start: None,
+            running: None,
+            output: CommandOutput::Pending,
+            history: History::default(),
+        };
+        command_info.update();
+        command_info
+    }
+
+    // never waits for the command, finished output is picked up on the next update
+    pub fn update(&mut self) {
+        if self.command.is_empty() {
+            self.output = CommandOutput::Error("No command configured".to_string());
+            return;
+        }
+
+        if let Some(mut running) = self.running.take() {
+            match self.poll_command(&mut running) {
+                Some(output) => self.output = output,
+                None => {
+                    self.running = Some(running);
+                    return;
+                }
+            }
+        }
+
+        if self.last_start.is_some_and(|last_start| last_start.elapsed() < self.interval) {
+            return;
+        }
+        self.last_start = Some(Instant::now());
+        match self.spawn() {
+            Ok(running) => self.running = Some(running),
+            Err(err) => self.output = CommandOutput::Error(format!("Can't run command: {err}")),
+        }
+    }
+
+    // None while the command runs or a process it started still holds its output
+    fn poll_command(&mut self, running: &mut RunningCommand) -> Option<CommandOutput> {
+        if running.status.is_none() {
+            match running.child.try_wait() {
+                Ok(status) => running.status = status,
+                Err(err) => {
+                    kill_command(&mut running.child);
+                    return Some(CommandOutput::Error(format!("Can't wait for command: {err}")));
+                }
+            }
+        }
+        let closed = running.stdout.poll() & running.stderr.poll();
+        match running.status {
+            Some(status) if closed => {
+                let stdout = running.stdout.output.take().unwrap_or_default();
+                let stderr = running.stderr.output.take().unwrap_or_default();
+                if status.success() {
+                    Some(self.parse_output(&stdout))
+                } else {
+                    let code = status.code().map(|code| code.to_string()).unwrap_or_else(|| "signal".to_string());
+                    let message = stderr.lines().chain(stdout.lines()).find(|line| !line.trim().is_empty());
+                    Some(CommandOutput::Error(format!("exit code {code}: {}", message.unwrap_or_default())))
+                }
+            }
+            _ if running.started.elapsed() > self.timeout => {
+                kill_command(&mut running.child);
+                Some(CommandOutput::Error(format!("timed out after {}s", self.timeout.as_secs())))
+            }
+            _ => None,
+        }
+    }
+
+    fn spawn(&self) -> std::io::Result<RunningCommand> {
+        let mut child = shell_command(&self.command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        Ok(RunningCommand {
+            child,
+            started: Instant::now(),
+            status: None,
+            stdout: PipeOutput::new(stdout),
+            stderr: PipeOutput::new(stderr),
+        })
+    }
+
+    fn parse_output(&mut self, stdout: &str) -> CommandOutput {
+        // numbers are taken from the first non-empty line, like "42", "42%" or "12/50"
+        let line = stdout.lines().find(|line| !line.trim().is_empty()).unwrap_or_default().trim();
+        let parse_number = |text: &str| text.trim().parse::<f64>().ok();
+
+        let output = match self.format {
+            CommandFormat::Text => return CommandOutput::Text(stdout.to_string()),
+            CommandFormat::Number => parse_number(line).map(|value| CommandOutput::Value {
+                value,
+                total: self.max,
+                share: self.max.filter(|max| *max > 0.0).map(|max| value / max),
+            }),
+            CommandFormat::Percent => parse_number(line.trim_end_matches('%')).map(|value| CommandOutput::Value {
+                value,
+                total: None,
+                share: Some(value / 100.0),
+            }),
+            CommandFormat::Ratio => line.split_once('/').and_then(|(used, total)| {
+                let (used, total) = (parse_number(used)?, parse_number(total)?);
+                Some(CommandOutput::Value {
+                    value: used,
+                    total: Some(total),
+                    share: (total > 0.0).then(|| used / total),
+                })
+            }),
+        };
+
+        match output {
+            Some(CommandOutput::Value { value, total, share }) => {
+                self.history.push(share.unwrap_or(value));
+                CommandOutput::Value { value, total, share }
+            }
+            _ => CommandOutput::Error(format!("Can't parse \"{line}\"")),
+        }
+    }
+}
+
+// `sh -c` in its own process group, so kill_command stops the children of the shell too
+pub fn shell_command(command: &str) -> Command {
+    #[cfg(target_os = "windows")]
+    {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::process::CommandExt;
+
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command).process_group(0);
+        shell
+    }
+}
+
+// the shell and everything it started, the reader threads end when the pipes close
+pub fn kill_command(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(child.id() as i32), libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    child.kill().ok();
+    // reaped at once after SIGKILL, or already exited
+    child.wait().ok();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn run(command: &str, format: CommandFormat, timeout: u64) -> CommandInfo {
+        let mut command_info = CommandInfo::new(&CommandTileOptions {
+            command: command.to_string(),
+            format,
+            timeout,
+            interval: 60,
+            ..CommandTileOptions::default()
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while matches!(command_info.output, CommandOutput::Pending) && Instant::now() < deadline {
+            let started = Instant::now();
+            command_info.update();
+            assert!(started.elapsed() < Duration::from_millis(500), "update blocked");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        command_info
+    }
+
+    #[test]
+    fn parses_ratio_output() {
+        let command_info = run("echo ' 12/50 '", CommandFormat::Ratio, 5);
+        assert!(matches!(
+            command_info.output,
+            CommandOutput::Value { value: 12.0, total: Some(50.0), share: Some(share) } if share == 0.24
+        ));
+    }
+
+    #[test]
+    fn reports_failures_with_stderr() {
+        let command_info = run("echo broken >&2; exit 3", CommandFormat::Percent, 5);
+        assert!(matches!(&command_info.output, CommandOutput::Error(message) if message == "exit code 3: broken"));
+    }
+
+    #[test]
+    fn background_process_holding_the_pipe_does_not_block() {
+        let command_info = run("echo 42; sleep 30 &", CommandFormat::Number, 1);
+        assert!(matches!(&command_info.output, CommandOutput::Error(message) if message == "timed out after 1s"));
+    }
+}
@@ -0,0 +1,394 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::file_config::ContainersTileOptions;
+use crate::http::HttpResponse;
+
+// engine requests run on a worker thread, a slow engine delays only the container list
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+// stop and restart wait up to 10 seconds for the container to exit before killing it
+const ACTION_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ContainerAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+impl ContainerAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContainerAction::Start => "start",
+            ContainerAction::Stop => "stop",
+            ContainerAction::Restart => "restart",
+        }
+    }
+}
+
+// cumulative values from the stats endpoint
+#[derive(Default, Clone, Copy)]
+struct ContainerCounters {
+    cpu_total: u64,
+    system_total: u64,
+    net_rx: u64,
+    net_tx: u64,
+    block_read: u64,
+    block_write: u64,
+}
+
+#[derive(Clone)]
+pub struct ContainerInfo {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub state: String,
+    // percent of one cpu, like `docker stats`
+    pub cpu: f64,
+    pub memory_used: u64,
+    pub memory_limit: u64,
+    // per second
+    pub net_rx: f64,
+    pub net_tx: f64,
+    pub block_read: f64,
+    pub block_write: f64,
+    counters: Option<(Instant, ContainerCounters)>,
+}
+
+// sent by the worker thread
+enum EngineMessage {
+    Containers(Result<Vec<ContainerInfo>, String>),
+    // "stop web: done" or the error of the action
+    ActionDone(String),
+}
+
+pub struct ContainersInfo {
+    actions: Sender<(String, String, ContainerAction)>,
+    messages: Receiver<EngineMessage>,
+    // false until the engine answered the first time
+    pub loaded: bool,
+    pub containers: Vec<ContainerInfo>,
+    pub error: Option<String>,
+    pub action_result: Option<String>,
+}
+
+impl ContainersInfo {
+    pub fn new(options: &ContainersTileOptions) -> Self {
+        let socket_path = if options.socket.is_empty() {
+            detect_engine_socket()
+        } else {
+            PathBuf::from(&options.socket)
+        };
+        let engine = Engine {
+            socket_path,
+            show_all: options.all,
+            containers: Vec::new(),
+        };
+        let interval = Duration::from_secs(options.interval);
+        let (actions, action_receiver) = mpsc::channel();
+        let (message_sender, messages) = mpsc::channel();
+        std::thread::spawn(move || engine.run(interval, action_receiver, message_sender));
+
+        ContainersInfo {
+            actions,
+            messages,
+            loaded: false,
+            containers: Vec::new(),
+            error: None,
+            action_result: None,
+        }
+    }
+
+    // takes what the worker sent since the previous update, never waits for the engine
+    pub fn update(&mut self) {
+        while let Ok(message) = self.messages.try_recv() {
+            match message {
+                EngineMessage::Containers(Ok(containers)) => {
+                    self.containers = containers;
+                    self.error = None;
+                    self.loaded = true;
+                }
+                EngineMessage::Containers(Err(err)) => {
+                    self.containers.clear();
+                    self.error = Some(err);
+                    self.loaded = true;
+                }
+                EngineMessage::ActionDone(result) => self.action_result = Some(result),
+            }
+        }
+    }
+
+    // the result is in action_result after a later update, the list is refreshed after it
+    pub fn run_action(&self, container_id: &str, container_name: &str, action: ContainerAction) {
+        self.actions
+            .send((container_id.to_string(), container_name.to_string(), action))
+            .ok();
+    }
+}
+
+// owned by the worker thread, keeps the counters between refreshes
+struct Engine {
+    socket_path: PathBuf,
+    show_all: bool,
+    containers: Vec<ContainerInfo>,
+}
+
+impl Engine {
+    // ends when the tile is dropped
+    fn run(
+        mut self,
+        interval: Duration,
+        actions: Receiver<(String, String, ContainerAction)>,
+        messages: Sender<EngineMessage>,
+    ) {
+        loop {
+            let containers = match self.refresh() {
+                Ok(()) => Ok(self.containers.clone()),
+                Err(err) => {
+                    self.containers.clear();
+                    Err(err)
+                }
+            };
+            if messages.send(EngineMessage::Containers(containers)).is_err() {
+                return;
+            }
+            match actions.recv_timeout(interval) {
+                Ok((container_id, container_name, action)) => {
+                    let result = match self.run_action(&container_id, action) {
+                        Ok(()) => format!("{} {container_name}: done", action.name()),
+                        Err(err) => err,
+                    };
+                    messages.send(EngineMessage::ActionDone(result)).ok();
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    fn run_action(&self, container_id: &str, action: ContainerAction) -> Result<(), String> {
+        let response = self.request("POST", &format!("/containers/{container_id}/{}", action.name()), ACTION_TIMEOUT)?;
+        match response.status {
+            // 304 means container is already in the requested state
+            200..=299 | 304 => Ok(()),
+            status => Err(format!("{} failed with status {status}: {}", action.name(), response.body.trim())),
+        }
+    }
+
+    fn refresh(&mut self) -> Result<(), String> {
+        let list_path = if self.show_all { "/containers/json?all=true" } else { "/containers/json" };
+        let response = self.request("GET", list_path, REQUEST_TIMEOUT)?;
+        if response.status != 200 {
+            return Err(format!("Engine returned status {}", response.status));
+        }
+        let list: Value = serde_json::from_str(&response.body).map_err(|err| format!("Invalid engine response: {err}"))?;
+
+        let mut containers: Vec<ContainerInfo> = Vec::new();
+        for entry in list.as_array().into_iter().flatten() {
+            let id = entry["Id"].as_str().unwrap_or_default().to_string();
+            let mut container = match self.containers.iter().position(|container| container.id == id) {
+                Some(position) => self.containers.swap_remove(position),
+                None => ContainerInfo {
+                    id,
+                    name: String::new(),
+                    image: String::new(),
+                    state: String::new(),
+                    cpu: 0.0,
+                    memory_used: 0,
+                    memory_limit: 0,
+                    net_rx: 0.0,
+                    net_tx: 0.0,
+                    block_read: 0.0,
+                    block_write: 0.0,
+                    counters: None,
+                },
+            };
+            // names are returned with leading slash
+            container.name = entry["Names"][0].as_str().unwrap_or_default().trim_start_matches('/').to_string();
+            container.image = entry["Image"].as_str().unwrap_or_default().to_string();
+            container.state = entry["State"].as_str().unwrap_or_default().to_string();
+
+            if container.state == "running" {
+                // one-shot skips the second sample docker takes for precpu_stats
+                let stats_path = format!("/containers/{}/stats?stream=false&one-shot=true", container.id);
+                if let Ok(response) = self.request("GET", &stats_path, REQUEST_TIMEOUT) {
+                    if let Ok(stats) = serde_json::from_str::<Value>(&response.body) {
+                        container.update_stats(&stats);
+                    }
+                }
+            } else {
+                container.cpu = 0.0;
+                container.counters = None;
+            }
+            containers.push(container);
+        }
+        containers.sort_by(|first, second| first.name.cmp(&second.name));
+        self.containers = containers;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn request(&self, method: &str, path: &str, timeout: Duration) -> Result<HttpResponse, String> {
+        crate::http::request_unix(&self.socket_path, method, path, timeout)
+    }
+
+    #[cfg(not(unix))]
+    fn request(&self, _method: &str, _path: &str, _timeout: Duration) -> Result<HttpResponse, String> {
+        Err("Engine sockets are supported only on unix".to_string())
+    }
+}
+
+impl ContainerInfo {
+    fn update_stats(&mut self, stats: &Value) {
+        let number = |value: &Value| value.as_u64().unwrap_or(0);
+
+        // cgroup v2 reports inactive_file, v1 reports cache. Both are excluded like in `docker stats`
+        let memory_stats = &stats["memory_stats"];
+        let cache = match memory_stats["stats"]["inactive_file"].as_u64() {
+            Some(inactive_file) => inactive_file,
+            None => number(&memory_stats["stats"]["cache"]),
+        };
+        self.memory_used = number(&memory_stats["usage"]).saturating_sub(cache);
+        self.memory_limit = number(&memory_stats["limit"]);
+
+        let mut counters = ContainerCounters {
+            cpu_total: number(&stats["cpu_stats"]["cpu_usage"]["total_usage"]),
+            system_total: number(&stats["cpu_stats"]["system_cpu_usage"]),
+            ..Default::default()
+        };
+        for network in stats["networks"].as_object().into_iter().flat_map(|networks| networks.values()) {
+            counters.net_rx += number(&network["rx_bytes"]);
+            counters.net_tx += number(&network["tx_bytes"]);
+        }
+        for entry in stats["blkio_stats"]["io_service_bytes_recursive"].as_array().into_iter().flatten() {
+            match entry["op"].as_str().map(|op| op.to_ascii_lowercase()).as_deref() {
+                Some("read") => counters.block_read += number(&entry["value"]),
+                Some("write") => counters.block_write += number(&entry["value"]),
+                _ => (),
+            }
+        }
+
+        let now = Instant::now();
+        if let Some((last_time, previous)) = self.counters {
+            let elapsed = now.duration_since(last_time).as_secs_f64();
+            let delta = |current: u64, previous: u64| current.saturating_sub(previous) as f64;
+            let online_cpus = stats["cpu_stats"]["online_cpus"].as_u64().unwrap_or(1) as f64;
+
+            let system_delta = delta(counters.system_total, previous.system_total);
+            self.cpu = if system_delta > 0.0 {
+                delta(counters.cpu_total, previous.cpu_total) / system_delta * online_cpus * 100.0
+            } else {
+                0.0
+            };
+            if elapsed > 0.0 {
+                self.net_rx = delta(counters.net_rx, previous.net_rx) / elapsed;
+                self.net_tx = delta(counters.net_tx, previous.net_tx) / elapsed;
+                self.block_read = delta(counters.block_read, previous.block_read) / elapsed;
+                self.block_write = delta(counters.block_write, previous.block_write) / elapsed;
+            }
+        }
+        self.counters = Some((now, counters));
+    }
+}
+
+// docker socket first, then rootless and rootful podman
+fn detect_engine_socket() -> PathBuf {
+    let mut candidates = vec![PathBuf::from("/var/run/docker.sock")];
+    if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+        candidates.push(PathBuf::from(runtime_dir).join("podman/podman.sock"));
+    }
+    candidates.push(PathBuf::from("/run/podman/podman.sock"));
+
+    candidates
+        .iter()
+        .find(|candidate| candidate.exists())
+        .unwrap_or(&candidates[0])
+        .clone()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixListener;
+
+    const LIST: &str = r#"[{"Id":"b2","Names":["/web"],"Image":"nginx","State":"running"},
+        {"Id":"a1","Names":["/db"],"Image":"postgres","State":"exited"}]"#;
+    const STATS: &str = r#"{"memory_stats":{"usage":3000,"limit":10000,"stats":{"inactive_file":1000}},
+        "cpu_stats":{"cpu_usage":{"total_usage":100},"system_cpu_usage":1000,"online_cpus":2},
+        "networks":{"eth0":{"rx_bytes":10,"tx_bytes":20}},
+        "blkio_stats":{"io_service_bytes_recursive":[{"op":"Read","value":5},{"op":"write","value":7}]}}"#;
+
+    // answers the list, stats and action requests of the worker
+    fn serve_engine(fixture: &Fixture) -> PathBuf {
+        let socket_path = fixture.path().join("engine.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut request_line = String::new();
+                BufReader::new(&stream).read_line(&mut request_line).unwrap();
+                let (status, body) = match request_line.split_whitespace().nth(1).unwrap_or_default() {
+                    "/containers/json" => ("200 OK", LIST),
+                    "/containers/b2/stats?stream=false&one-shot=true" => ("200 OK", STATS),
+                    "/containers/b2/stop" => ("204 No Content", ""),
+                    _ => ("404 Not Found", "no such container"),
+                };
+                let mut stream = stream;
+                write!(stream, "HTTP/1.1 {status}\r\nContent-Length: {}\r\n\r\n{body}", body.len()).ok();
+            }
+        });
+        socket_path
+    }
+
+    fn wait_for(containers_info: &mut ContainersInfo, done: impl Fn(&ContainersInfo) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(containers_info) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+            containers_info.update();
+        }
+    }
+
+    #[test]
+    fn lists_containers_from_a_worker_thread() {
+        let fixture = Fixture::new();
+        let socket = serve_engine(&fixture).to_string_lossy().into_owned();
+        let mut containers_info = ContainersInfo::new(&ContainersTileOptions {
+            socket,
+            ..ContainersTileOptions::default()
+        });
+        assert!(!containers_info.loaded);
+        wait_for(&mut containers_info, |containers_info| containers_info.loaded);
+
+        assert_eq!(containers_info.error, None);
+        let names: Vec<&str> = containers_info.containers.iter().map(|container| container.name.as_str()).collect();
+        assert_eq!(names, vec!["db", "web"]);
+        let web = &containers_info.containers[1];
+        assert_eq!((web.memory_used, web.memory_limit), (2000, 10000));
+
+        containers_info.run_action("b2", "web", ContainerAction::Stop);
+        wait_for(&mut containers_info, |containers_info| containers_info.action_result.is_some());
+        assert_eq!(containers_info.action_result.as_deref(), Some("stop web: done"));
+
+        containers_info.run_action("a1", "db", ContainerAction::Start);
+        wait_for(&mut containers_info, |containers_info| containers_info.action_result.as_deref() != Some("stop web: done"));
+        assert_eq!(
+            containers_info.action_result.as_deref(),
+            Some("start failed with status 404: no such container")
+        );
+    }
+
+    #[test]
+    fn reports_missing_engine() {
+        let fixture = Fixture::new();
+        let mut containers_info = ContainersInfo::new(&ContainersTileOptions {
+            socket: fixture.path().join("missing.sock").to_string_lossy().into_owned(),
+            ..ContainersTileOptions::default()
+        });
+        wait_for(&mut containers_info, |containers_info| containers_info.loaded);
+        assert!(containers_info.error.as_deref().is_some_and(|err| err.starts_with("Can't connect to")));
+    }
+}
@@ -1,9 +1,48 @@
-use sysinfo::{CpuExt, System, SystemExt};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
 
+use sysinfo::{CpuExt, CpuRefreshKind, System, SystemExt};
+
+use crate::cgroup_info::{detect_own_cgroup, Cgroup};
+use crate::cpu_topology::{read_frequency, read_online_cpus, read_topology, CoreTopology};
+use crate::file_config::CpuTileOptions;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+
+// share of time spent in each state since the previous update, 0.0..1.0
+#[derive(Default, Clone, Copy)]
+pub struct CpuTimes {
+    pub user: f64,
+    pub nice: f64,
+    pub system: f64,
+    pub iowait: f64,
+    // irq and softirq
+    pub irq: f64,
+    pub steal: f64,
+    // guest and guest_nice
+    pub guest: f64,
+}
+
+// user nice system idle iowait irq softirq steal guest guest_nice from /proc/stat
+pub type CpuCounters = [u64; 10];
 
 pub struct CpuInfo {
     sys: System,
     cpus_usage: Vec<f64>,
+    // filled only when time breakdown is enabled
+    procfs_root: Option<PathBuf>,
+    // keyed by the cpu number, offline cpus have no line in /proc/stat
+    cpus_counters: BTreeMap<usize, CpuCounters>,
+    cpus_times: Vec<CpuTimes>,
+    sysfs_root: PathBuf,
+    show_frequency: bool,
+    // MHz
+    frequencies: Vec<Option<f64>>,
+    // in the order of sysinfo, `cpu` of an entry is the kernel number of the cpu at that index
+    pub topology: Vec<CoreTopology>,
+    // cgroup of tsm when container-aware mode is enabled
+    pub cgroup: Option<Cgroup>,
     pub cpu_count: usize,
     ram_used: u64,
     ram_total: u64,
@@ -12,7 +51,7 @@ pub struct CpuInfo {
 }
 
 impl CpuInfo {
-    pub fn new() -> Self {
+    pub fn new(options: &CpuTileOptions) -> Self {
         let mut sys = System::new();
 
         sys.refresh_cpu();
@@ -29,33 +68,283 @@ impl CpuInfo {
         let swap_used = sys.used_swap() / 1024 / 1024;
         let swap_total = sys.total_swap() / 1024 / 1024;
 
-        CpuInfo {
+        // sysinfo lists online cpus only, so its index is not the cpu number when some are offline
+        let sysfs_root = PathBuf::from(&options.sysfs_root);
+        let cpus = read_online_cpus(&sysfs_root)
+            .filter(|cpus| cpus.len() == cpus_usage.len())
+            .unwrap_or_else(|| (0..cpus_usage.len()).collect());
+
+        let mut cpu_info = CpuInfo {
             sys,
             cpu_count: cpus_usage.len(),
+            procfs_root: options.breakdown.then(|| PathBuf::from(&options.procfs_root)),
+            cpus_counters: BTreeMap::new(),
+            cpus_times: vec![CpuTimes::default(); cpus_usage.len()],
+            show_frequency: options.frequency,
+            frequencies: vec![None; cpus_usage.len()],
+            topology: read_topology(&sysfs_root, &cpus),
+            sysfs_root,
+            cgroup: if options.cgroup {
+                detect_own_cgroup(&PathBuf::from(&options.procfs_root), &PathBuf::from(&options.cgroupfs_root))
+                    .map(Cgroup::new)
+            } else {
+                None
+            },
             cpus_usage,
             ram_used,
             ram_total,
             swap_used,
             swap_total,
+        };
+        cpu_info.update_times();
+        cpu_info
+    }
+
+    // filled by `replay`, nothing is read from this machine
+    pub fn for_replay() -> Self {
+        CpuInfo {
+            sys: System::new(),
+            cpus_usage: Vec::new(),
+            procfs_root: None,
+            cpus_counters: BTreeMap::new(),
+            cpus_times: Vec::new(),
+            sysfs_root: PathBuf::new(),
+            show_frequency: false,
+            frequencies: Vec::new(),
+            topology: Vec::new(),
+            cgroup: None,
+            cpu_count: 0,
+            ram_used: 0,
+            ram_total: 0,
+            swap_used: 0,
+            swap_total: 0,
         }
     }
 
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let (collector, ram, swap) = (
+            get_collector_name("cpu", tile),
+            get_collector_name("ram", tile),
+            get_collector_name("swap", tile),
+        );
+        let cpu_count = sample.get_count(&collector, "usage_percent");
+        if cpu_count != self.cpu_count {
+            // topology is not recorded, every cpu is its own core
+            self.cpu_count = cpu_count;
+            self.topology = (0..cpu_count)
+                .map(|cpu| CoreTopology {
+                    cpu,
+                    socket: 0,
+                    numa_node: None,
+                    core_id: cpu as u32,
+                    kind: None,
+                })
+                .collect();
+        }
+        self.cpus_usage = (0..cpu_count)
+            .map(|cpu| sample.get_number(&format!("{collector}.{cpu}.usage_percent")).unwrap_or(0.0))
+            .collect();
+        self.frequencies = (0..cpu_count)
+            .map(|cpu| sample.get_number(&format!("{collector}.{cpu}.frequency_mhz")))
+            .collect();
+        let get_mb = |key: &str| sample.get_number(key).unwrap_or(0.0) as u64;
+        self.ram_used = get_mb(&format!("{ram}.used_mb"));
+        self.ram_total = get_mb(&format!("{ram}.total_mb"));
+        self.swap_used = get_mb(&format!("{swap}.used_mb"));
+        self.swap_total = get_mb(&format!("{swap}.total_mb"));
+    }
+
     pub fn update(&mut self) {
         self.sys.refresh_cpu();
         for (ind, cpu) in self.sys.cpus().iter().enumerate() {
             self.cpus_usage[ind] = cpu.cpu_usage() as f64;
         }
+        self.update_times();
+        self.update_frequencies();
+        self.update_memory();
+    }
+
+    fn update_memory(&mut self) {
+        self.sys.refresh_memory();
+        self.ram_used = self.sys.used_memory() / 1024 / 1024;
+        self.ram_total = self.sys.total_memory() / 1024 / 1024;
+        self.swap_used = self.sys.used_swap() / 1024 / 1024;
+        self.swap_total = self.sys.total_swap() / 1024 / 1024;
+
+        if let Some(cgroup) = &mut self.cgroup {
+            cgroup.update();
+        }
+    }
+
+    // cpufreq is more accurate, sysinfo is used where it is missing (Windows, some VMs)
+    fn update_frequencies(&mut self) {
+        if !self.show_frequency {
+            return;
+        }
+        self.sys.refresh_cpu_specifics(CpuRefreshKind::new().with_frequency());
+        for (ind, cpu) in self.sys.cpus().iter().enumerate() {
+            let number = self.topology.get(ind).map_or(ind, |core| core.cpu);
+            self.frequencies[ind] = read_frequency(&self.sysfs_root, number).or(match cpu.frequency() {
+                0 => None,
+                frequency => Some(frequency as f64),
+            });
+        }
+    }
+
+    pub fn get_frequency(&self, cpu_index: usize) -> Option<f64> {
+        self.frequencies[cpu_index]
+    }
+
+    fn update_times(&mut self) {
+        let Some(procfs_root) = &self.procfs_root else {
+            return;
+        };
+        let content = fs::read_to_string(procfs_root.join("stat")).unwrap_or_default();
+        let cpus_counters = parse_proc_stat(&content);
+
+        for (core, cpu_times) in self.topology.iter().zip(self.cpus_times.iter_mut()) {
+            let counters = self.cpus_counters.get(&core.cpu).zip(cpus_counters.get(&core.cpu));
+            if let Some(times) = counters.and_then(|(previous, current)| get_cpu_times(previous, current)) {
+                *cpu_times = times;
+            }
+        }
+        self.cpus_counters = cpus_counters;
+    }
+
+    pub fn get_cpu_times(&self, cpu_index: usize) -> Option<CpuTimes> {
+        self.procfs_root.as_ref()?;
+        self.cpus_times.get(cpu_index).copied()
     }
 
     pub fn get_cpu_usage(&self, cpu_index: usize) -> f64 {
         self.cpus_usage[cpu_index]
     }
 
+    // cgroup memory.current against memory.max in container-aware mode
     pub fn get_ram_usage(&self) -> (u64, u64) {
-        (self.ram_used, self.ram_total)
+        match &self.cgroup {
+            Some(cgroup) => (
+                cgroup.memory_current / 1024 / 1024,
+                cgroup
+                    .memory_max
+                    .map(|memory_max| (memory_max / 1024 / 1024).min(self.ram_total))
+                    .unwrap_or(self.ram_total),
+            ),
+            None => (self.ram_used, self.ram_total),
+        }
+    }
+
+    // cpus allowed for the cgroup, all cpus otherwise
+    pub fn is_cpu_visible(&self, cpu_index: usize) -> bool {
+        match self.cgroup.as_ref().and_then(|cgroup| cgroup.cpuset.as_ref()) {
+            Some(cpuset) => self.topology.get(cpu_index).is_some_and(|core| cpuset.contains(&core.cpu)),
+            None => true,
+        }
     }
 
     pub fn get_swap_usage(&self) -> (u64, u64) {
         (self.swap_used, self.swap_total)
     }
 }
+
+impl CpuTimes {
+    // share of time the cpu was busy, iowait counts as idle
+    pub fn busy(&self) -> f64 {
+        self.user + self.nice + self.system + self.irq + self.steal + self.guest
+    }
+}
+
+// shares of the time passed between two readings of the counters, None when no time passed
+pub fn get_cpu_times(previous: &CpuCounters, current: &CpuCounters) -> Option<CpuTimes> {
+    let delta: Vec<f64> = current
+        .iter()
+        .zip(previous.iter())
+        .map(|(current, previous)| current.saturating_sub(*previous) as f64)
+        .collect();
+    // guest time is already included in user and nice
+    let total: f64 = delta[..8].iter().sum();
+    if total == 0.0 {
+        return None;
+    }
+
+    Some(CpuTimes {
+        user: (delta[0] - delta[8]).max(0.0) / total,
+        nice: (delta[1] - delta[9]).max(0.0) / total,
+        system: delta[2] / total,
+        iowait: delta[4] / total,
+        irq: (delta[5] + delta[6]) / total,
+        steal: delta[7] / total,
+        guest: (delta[8] + delta[9]) / total,
+    })
+}
+
+// per core lines look like "cpu0 9139 0 1634 160970 188 0 2 163 0 0", first line is the total
+pub fn parse_proc_stat(content: &str) -> BTreeMap<usize, CpuCounters> {
+    let mut cpus_counters = BTreeMap::new();
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(index) = fields
+            .next()
+            .and_then(|name| name.strip_prefix("cpu"))
+            .and_then(|index| index.parse::<usize>().ok())
+        else {
+            continue;
+        };
+
+        let mut counters: CpuCounters = [0; 10];
+        for (counter, field) in counters.iter_mut().zip(fields) {
+            *counter = field.parse().unwrap_or(0);
+        }
+        cpus_counters.insert(index, counters);
+    }
+    cpus_counters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_keyed_by_the_cpu_number() {
+        let content = "cpu  20 0 20 200 0 0 0 0 0 0\n\
+                       cpu0 10 0 10 100 0 0 0 0 0 0\n\
+                       cpu2 10 0 10 100 0 0 0 0 0 0\n\
+                       intr 1 2 3\n";
+        let cpus_counters = parse_proc_stat(content);
+        assert_eq!(cpus_counters.keys().copied().collect::<Vec<usize>>(), vec![0, 2]);
+        assert_eq!(cpus_counters[&2][3], 100);
+    }
+
+    #[test]
+    fn breakdown_is_the_share_of_each_state() {
+        let previous: CpuCounters = [100, 10, 50, 1000, 5, 1, 1, 0, 0, 0];
+        let current: CpuCounters = [140, 20, 70, 1010, 15, 3, 3, 5, 0, 0];
+        let times = get_cpu_times(&previous, &current).unwrap();
+        // 40 + 10 + 20 + 10 + 10 + 2 + 2 + 5
+        let total = 99.0;
+        assert!((times.user - 40.0 / total).abs() < 1e-9);
+        assert!((times.nice - 10.0 / total).abs() < 1e-9);
+        assert!((times.system - 20.0 / total).abs() < 1e-9);
+        assert!((times.iowait - 10.0 / total).abs() < 1e-9);
+        assert!((times.irq - 4.0 / total).abs() < 1e-9);
+        assert!((times.steal - 5.0 / total).abs() < 1e-9);
+        assert_eq!(times.guest, 0.0);
+    }
+
+    #[test]
+    fn guest_time_is_taken_out_of_user_and_nice() {
+        let previous: CpuCounters = [0; 10];
+        let current: CpuCounters = [60, 20, 10, 10, 0, 0, 0, 0, 30, 5];
+        let times = get_cpu_times(&previous, &current).unwrap();
+        assert!((times.user - 0.3).abs() < 1e-9);
+        assert!((times.nice - 0.15).abs() < 1e-9);
+        assert!((times.guest - 0.35).abs() < 1e-9);
+        assert!((times.system - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_time_passed_gives_no_breakdown() {
+        let counters: CpuCounters = [1, 2, 3, 4, 5, 6, 7, 8, 0, 0];
+        assert!(get_cpu_times(&counters, &counters).is_none());
+    }
+}
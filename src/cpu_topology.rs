@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::Path;
+
+use crate::sysfs::{read_number, read_trimmed};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CoreKind {
+    Performance,
+    Efficient,
+}
+
+#[derive(Clone, Copy)]
+pub struct CoreTopology {
+    pub cpu: usize,
+    pub socket: u32,
+    pub numa_node: Option<u32>,
+    // id of the physical core, SMT siblings share it inside of one socket
+    pub core_id: u32,
+    // only set on hybrid cpus
+    pub kind: Option<CoreKind>,
+}
+
+// kernel numbers of the online cpus, "0-3,6-7" when 4 and 5 are offline
+pub fn read_online_cpus(sysfs_root: &Path) -> Option<Vec<usize>> {
+    read_trimmed(&sysfs_root.join("devices/system/cpu/online")).map(|list| parse_cpu_list(&list))
+}
+
+// one entry per cpu number in the given order
+pub fn read_topology(sysfs_root: &Path, cpus: &[usize]) -> Vec<CoreTopology> {
+    // hybrid Intel cpus register separate pmu devices for P and E cores
+    let performance_cpus = read_cpu_list(&sysfs_root.join("devices/cpu_core/cpus"));
+    let efficient_cpus = read_cpu_list(&sysfs_root.join("devices/cpu_atom/cpus"));
+
+    cpus.iter()
+        .map(|&cpu| {
+            let cpu_dir = sysfs_root.join(format!("devices/system/cpu/cpu{cpu}"));
+            let kind = if performance_cpus.contains(&cpu) {
+                Some(CoreKind::Performance)
+            } else if efficient_cpus.contains(&cpu) {
+                Some(CoreKind::Efficient)
+            } else {
+                None
+            };
+
+            CoreTopology {
+                cpu,
+                socket: read_number(&cpu_dir.join("topology/physical_package_id")).unwrap_or(0.0) as u32,
+                numa_node: get_numa_node(&cpu_dir),
+                core_id: read_number(&cpu_dir.join("topology/core_id")).unwrap_or(cpu as f64) as u32,
+                kind,
+            }
+        })
+        .collect()
+}
+
+// current frequency in MHz
+pub fn read_frequency(sysfs_root: &Path, cpu: usize) -> Option<f64> {
+    read_number(&sysfs_root.join(format!("devices/system/cpu/cpu{cpu}/cpufreq/scaling_cur_freq")))
+        .map(|frequency| frequency / 1000.0)
+}
+
+// cpu directory contains node<N> link to its NUMA node
+fn get_numa_node(cpu_dir: &Path) -> Option<u32> {
+    fs::read_dir(cpu_dir).ok()?.flatten().find_map(|entry| {
+        entry
+            .file_name()
+            .to_string_lossy()
+            .strip_prefix("node")?
+            .parse::<u32>()
+            .ok()
+    })
+}
+
+fn read_cpu_list(path: &Path) -> Vec<usize> {
+    read_trimmed(path).map(|list| parse_cpu_list(&list)).unwrap_or_default()
+}
+
+// kernel cpu lists look like "0-3,8,10-11"
+pub fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|part| !part.is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                if let (Ok(first), Ok(last)) = (first.parse::<usize>(), last.parse::<usize>()) {
+                    cpus.extend(first..=last);
+                }
+            }
+            None => {
+                if let Ok(cpu) = part.parse::<usize>() {
+                    cpus.push(cpu);
+                }
+            }
+        }
+    }
+    cpus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+
+    fn write_cpu(fixture: &Fixture, cpu: usize, socket: u32, core_id: u32, node: u32) {
+        let cpu_dir = format!("devices/system/cpu/cpu{cpu}");
+        fixture
+            .write(&format!("{cpu_dir}/topology/physical_package_id"), &format!("{socket}\n"))
+            .write(&format!("{cpu_dir}/topology/core_id"), &format!("{core_id}\n"))
+            .write(&format!("{cpu_dir}/node{node}/cpulist"), "");
+    }
+
+    #[test]
+    fn parses_cpu_lists() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), [0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list("5"), [5]);
+        assert_eq!(parse_cpu_list(""), Vec::<usize>::new());
+        // broken parts are skipped
+        assert_eq!(parse_cpu_list("0-x,2,,y"), [2]);
+    }
+
+    #[test]
+    fn reads_topology_of_the_online_cpus() {
+        let fixture = Fixture::new();
+        fixture.write("devices/system/cpu/online", "0-1,4-5\n");
+        write_cpu(&fixture, 0, 0, 0, 0);
+        write_cpu(&fixture, 1, 0, 0, 0);
+        write_cpu(&fixture, 4, 1, 2, 1);
+        write_cpu(&fixture, 5, 1, 3, 1);
+
+        let cpus = read_online_cpus(fixture.path()).unwrap();
+        assert_eq!(cpus, [0, 1, 4, 5]);
+        let topology: Vec<(usize, u32, u32, Option<u32>)> = read_topology(fixture.path(), &cpus)
+            .iter()
+            .map(|core| (core.cpu, core.socket, core.core_id, core.numa_node))
+            .collect();
+        assert_eq!(topology, [(0, 0, 0, Some(0)), (1, 0, 0, Some(0)), (4, 1, 2, Some(1)), (5, 1, 3, Some(1))]);
+        assert!(read_online_cpus(&fixture.path().join("missing")).is_none());
+    }
+
+    #[test]
+    fn labels_performance_and_efficient_cores_of_hybrid_cpus() {
+        let fixture = Fixture::new();
+        fixture
+            .write("devices/cpu_core/cpus", "0-3\n")
+            .write("devices/cpu_atom/cpus", "4-5\n");
+        let kinds: Vec<Option<CoreKind>> = read_topology(fixture.path(), &[0, 3, 4, 6])
+            .iter()
+            .map(|core| core.kind)
+            .collect();
+        assert!(kinds == [Some(CoreKind::Performance), Some(CoreKind::Performance), Some(CoreKind::Efficient), None]);
+
+        // without topology files every cpu is its own core of socket 0
+        let core = read_topology(fixture.path(), &[6])[0];
+        assert_eq!((core.socket, core.core_id, core.numa_node), (0, 6, None));
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::file_config::DiskIoTileOptions;
+use crate::history::History;
+use crate::patterns::is_included;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+
+// /proc/diskstats always counts in 512 bytes sectors
+pub const SECTOR_SIZE: f64 = 512.0;
+
+// counters from one line of /proc/diskstats
+#[derive(Default, Clone, Copy)]
+pub struct DiskCounters {
+    pub reads: u64,
+    pub read_sectors: u64,
+    pub read_ms: u64,
+    pub writes: u64,
+    pub write_sectors: u64,
+    pub write_ms: u64,
+    pub io_ms: u64,
+    pub weighted_io_ms: u64,
+}
+
+pub struct DiskDeviceInfo {
+    pub name: String,
+    pub parent: Option<String>,
+    // per second rates
+    pub read_bytes: f64,
+    pub write_bytes: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    // average time of one request in ms
+    pub latency: f64,
+    pub queue_depth: f64,
+    // 0.0..1.0
+    pub busy: f64,
+    pub read_history: History,
+    pub write_history: History,
+    // totals since boot
+    pub counters: DiskCounters,
+}
+
+pub struct DiskInfo {
+    procfs_root: PathBuf,
+    sysfs_root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    show_partitions: bool,
+    last_update: Instant,
+    pub devices: Vec<DiskDeviceInfo>,
+}
+
+impl DiskInfo {
+    pub fn new(options: &DiskIoTileOptions) -> Self {
+        let mut disk_info = DiskInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            sysfs_root: PathBuf::from(&options.sysfs_root),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            show_partitions: options.partitions,
+            last_update: Instant::now(),
+            devices: Vec::new(),
+        };
+        disk_info.update();
+        disk_info
+    }
+
+    // filled by `replay`, include and exclude patterns still apply
+    pub fn for_replay(options: &DiskIoTileOptions) -> Self {
+        DiskInfo {
+            procfs_root: PathBuf::new(),
+            sysfs_root: PathBuf::new(),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            show_partitions: options.partitions,
+            last_update: Instant::now(),
+            devices: Vec::new(),
+        }
+    }
+
+    // partitions are recorded only when they were shown, their parents are not known
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("disk", tile);
+        let mut devices: Vec<DiskDeviceInfo> = Vec::new();
+        for name in sample.get_instances(&collector) {
+            if !is_included(name, &self.include, &self.exclude) {
+                continue;
+            }
+            let mut device = match self.devices.iter().position(|device| device.name == name) {
+                Some(position) => self.devices.swap_remove(position),
+                None => DiskDeviceInfo::new(name.to_string(), DiskCounters::default()),
+            };
+            let get_number = |field: &str| sample.get_number(&format!("{collector}.{name}.{field}")).unwrap_or(0.0);
+            device.read_bytes = get_number("read_bytes_per_second");
+            device.write_bytes = get_number("write_bytes_per_second");
+            device.read_iops = get_number("read_iops");
+            device.write_iops = get_number("write_iops");
+            device.latency = get_number("latency_ms");
+            device.queue_depth = get_number("queue_depth");
+            device.busy = get_number("busy_percent") / 100.0;
+            device.read_history.push(device.read_bytes);
+            device.write_history.push(device.write_bytes);
+            devices.push(device);
+        }
+        self.devices = devices;
+    }
+
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        let content = fs::read_to_string(self.procfs_root.join("diskstats")).unwrap_or_default();
+        let partition_parents = self.get_partition_parents();
+
+        let mut disks: Vec<DiskDeviceInfo> = Vec::new();
+        let mut partitions: Vec<DiskDeviceInfo> = Vec::new();
+
+        for (name, counters) in parse_diskstats(&content) {
+            let parent = partition_parents.get(&name).cloned();
+            let filter_name = parent.as_ref().unwrap_or(&name);
+            if !is_included(filter_name, &self.include, &self.exclude) {
+                continue;
+            }
+            if parent.is_some() && !self.show_partitions {
+                continue;
+            }
+
+            let mut device = match self.devices.iter().position(|device| device.name == name) {
+                Some(position) => self.devices.swap_remove(position),
+                None => DiskDeviceInfo::new(name, counters),
+            };
+            device.parent = parent;
+            device.update(counters, elapsed);
+
+            if device.parent.is_some() {
+                partitions.push(device);
+            } else {
+                disks.push(device);
+            }
+        }
+
+        // partitions are shown right after their parent device
+        let mut devices: Vec<DiskDeviceInfo> = Vec::new();
+        for disk in disks {
+            let disk_name = disk.name.clone();
+            devices.push(disk);
+            while let Some(position) = partitions
+                .iter()
+                .position(|partition| partition.parent.as_ref() == Some(&disk_name))
+            {
+                devices.push(partitions.remove(position));
+            }
+        }
+        self.devices = devices;
+    }
+
+    // every partition has own directory with `partition` file inside of parent device directory
+    fn get_partition_parents(&self) -> HashMap<String, String> {
+        let mut parents = HashMap::new();
+        let Ok(block_devices) = fs::read_dir(self.sysfs_root.join("block")) else {
+            return parents;
+        };
+
+        for block_device in block_devices.flatten() {
+            let disk_name = block_device.file_name().to_string_lossy().to_string();
+            let Ok(entries) = fs::read_dir(block_device.path()) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if entry.path().join("partition").exists() {
+                    parents.insert(entry.file_name().to_string_lossy().to_string(), disk_name.clone());
+                }
+            }
+        }
+        parents
+    }
+}
+
+impl DiskDeviceInfo {
+    fn new(name: String, counters: DiskCounters) -> Self {
+        DiskDeviceInfo {
+            name,
+            parent: None,
+            read_bytes: 0.0,
+            write_bytes: 0.0,
+            read_iops: 0.0,
+            write_iops: 0.0,
+            latency: 0.0,
+            queue_depth: 0.0,
+            busy: 0.0,
+            read_history: History::default(),
+            write_history: History::default(),
+            counters,
+        }
+    }
+
+    fn update(&mut self, counters: DiskCounters, elapsed: f64) {
+        let previous = self.counters;
+        // counters can be reset when device is reattached
+        let delta = |current: u64, previous: u64| current.saturating_sub(previous) as f64;
+
+        if elapsed > 0.0 {
+            let reads = delta(counters.reads, previous.reads);
+            let writes = delta(counters.writes, previous.writes);
+            let elapsed_ms = elapsed * 1000.0;
+
+            self.read_bytes = delta(counters.read_sectors, previous.read_sectors) * SECTOR_SIZE / elapsed;
+            self.write_bytes = delta(counters.write_sectors, previous.write_sectors) * SECTOR_SIZE / elapsed;
+            self.read_iops = reads / elapsed;
+            self.write_iops = writes / elapsed;
+            self.latency = if reads + writes > 0.0 {
+                (delta(counters.read_ms, previous.read_ms) + delta(counters.write_ms, previous.write_ms))
+                    / (reads + writes)
+            } else {
+                0.0
+            };
+            self.queue_depth = delta(counters.weighted_io_ms, previous.weighted_io_ms) / elapsed_ms;
+            self.busy = (delta(counters.io_ms, previous.io_ms) / elapsed_ms).min(1.0);
+        }
+
+        self.counters = counters;
+        self.read_history.push(self.read_bytes);
+        self.write_history.push(self.write_bytes);
+    }
+}
+
+fn parse_diskstats(content: &str) -> Vec<(String, DiskCounters)> {
+    let mut devices = Vec::new();
+
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 14 {
+            continue;
+        }
+        let counter = |index: usize| fields[index].parse::<u64>().unwrap_or(0);
+
+        devices.push((
+            fields[2].to_string(),
+            DiskCounters {
+                reads: counter(3),
+                read_sectors: counter(5),
+                read_ms: counter(6),
+                writes: counter(7),
+                write_sectors: counter(9),
+                write_ms: counter(10),
+                io_ms: counter(12),
+                weighted_io_ms: counter(13),
+            },
+        ));
+    }
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::time::Duration;
+
+    // reads, read sectors, read ms, writes, write sectors, write ms, io ms and weighted io ms
+    fn diskstats_line(name: &str, counters: [u64; 8]) -> String {
+        let [reads, read_sectors, read_ms, writes, write_sectors, write_ms, io_ms, weighted_io_ms] = counters;
+        format!(
+            "   8       0 {name} {reads} 0 {read_sectors} {read_ms} {writes} 0 {write_sectors} {write_ms} 0 \
+             {io_ms} {weighted_io_ms} 0 0 0 0\n"
+        )
+    }
+
+    fn get_names(disk_info: &DiskInfo) -> Vec<&str> {
+        disk_info.devices.iter().map(|device| device.name.as_str()).collect()
+    }
+
+    #[test]
+    fn skips_lines_with_fewer_than_14_fields() {
+        let content = "   8       0 sdb 1 2 3 4 5 6 7 8 9 10\n".to_string() + &diskstats_line("sda", [1, 2, 3, 4, 5, 6, 7, 8]);
+        let devices = parse_diskstats(&content);
+        assert_eq!(devices.len(), 1);
+        let (name, counters) = &devices[0];
+        assert_eq!(name, "sda");
+        assert_eq!((counters.reads, counters.read_sectors, counters.read_ms), (1, 2, 3));
+        assert_eq!((counters.writes, counters.write_sectors, counters.write_ms), (4, 5, 6));
+        assert_eq!((counters.io_ms, counters.weighted_io_ms), (7, 8));
+    }
+
+    #[test]
+    fn shows_partitions_after_their_disk_and_filters_them_by_the_disk() {
+        let fixture = Fixture::new();
+        fixture
+            .write("sys/block/sda/sda1/partition", "1\n")
+            .write("sys/block/nvme0n1/nvme0n1p1/partition", "1\n")
+            .write(
+                "proc/diskstats",
+                &[
+                    diskstats_line("sda1", [0; 8]),
+                    diskstats_line("nvme0n1", [0; 8]),
+                    diskstats_line("nvme0n1p1", [0; 8]),
+                    diskstats_line("sda", [0; 8]),
+                ]
+                .concat(),
+            );
+        let options = DiskIoTileOptions {
+            procfs_root: format!("{}/proc", fixture.root()),
+            sysfs_root: format!("{}/sys", fixture.root()),
+            partitions: true,
+            ..DiskIoTileOptions::default()
+        };
+        let disk_info = DiskInfo::new(&options);
+        assert_eq!(get_names(&disk_info), ["nvme0n1", "nvme0n1p1", "sda", "sda1"]);
+        assert_eq!(disk_info.devices[3].parent.as_deref(), Some("sda"));
+
+        let disk_info = DiskInfo::new(&DiskIoTileOptions {
+            include: vec!["sda".to_string()],
+            ..options.clone()
+        });
+        assert_eq!(get_names(&disk_info), ["sda", "sda1"]);
+
+        let disk_info = DiskInfo::new(&DiskIoTileOptions { partitions: false, ..options });
+        assert_eq!(get_names(&disk_info), ["nvme0n1", "sda"]);
+    }
+
+    #[test]
+    fn turns_counters_into_rates_latency_queue_depth_and_busy() {
+        let fixture = Fixture::new();
+        fixture.write("diskstats", &diskstats_line("sda", [100, 1000, 100, 100, 1000, 100, 1000, 1000]));
+        let mut disk_info = DiskInfo::new(&DiskIoTileOptions {
+            procfs_root: fixture.root(),
+            sysfs_root: fixture.root(),
+            ..DiskIoTileOptions::default()
+        });
+        assert_eq!(disk_info.devices[0].read_bytes, 0.0);
+
+        // 2s with 200 requests that took 400ms, 1s of them busy with 1.5 requests in flight on average
+        fixture.write("diskstats", &diskstats_line("sda", [200, 5000, 400, 200, 3000, 200, 2000, 4000]));
+        disk_info.last_update -= Duration::from_secs(2);
+        disk_info.update();
+        let sda = &disk_info.devices[0];
+        assert!((sda.read_bytes - 4000.0 * 512.0 / 2.0).abs() < 2000.0);
+        assert!((sda.write_bytes - 2000.0 * 512.0 / 2.0).abs() < 1000.0);
+        assert!((sda.read_iops - 50.0).abs() < 0.1);
+        assert!((sda.write_iops - 50.0).abs() < 0.1);
+        assert!((sda.latency - 2.0).abs() < 1e-9);
+        assert!((sda.queue_depth - 1.5).abs() < 0.01);
+        assert!((sda.busy - 0.5).abs() < 0.01);
+
+        // busy is capped, io_ms can run ahead of the wall clock on some devices
+        fixture.write("diskstats", &diskstats_line("sda", [200, 5000, 400, 200, 3000, 200, 9000, 4000]));
+        disk_info.last_update -= Duration::from_secs(2);
+        disk_info.update();
+        let sda = &disk_info.devices[0];
+        assert_eq!((sda.busy, sda.latency, sda.read_iops), (1.0, 0.0, 0.0));
+
+        // counters start over when the device is reattached
+        fixture.write("diskstats", &diskstats_line("sda", [1, 8, 1, 1, 8, 1, 1, 1]));
+        disk_info.last_update -= Duration::from_secs(2);
+        disk_info.update();
+        let sda = &disk_info.devices[0];
+        assert_eq!((sda.read_bytes, sda.write_iops, sda.queue_depth, sda.busy), (0.0, 0.0, 0.0, 0.0));
+        assert_eq!(sda.read_history.values().len(), 4);
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use regex::Regex;
+
+use crate::file_config::EventsTileOptions;
+use crate::sysfs::{get_sorted_dirs, read_number};
+use crate::ui::format_local_time;
+
+// oom_kill increments without a kernel log message are reported as unknown victims after this delay
+const OOM_MATCH_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EventKind {
+    Oom,
+    Mce,
+    Edac,
+    Thermal,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Oom => "OOM",
+            EventKind::Mce => "MCE",
+            EventKind::Edac => "EDAC",
+            EventKind::Thermal => "THERM",
+        }
+    }
+}
+
+pub struct KernelEvent {
+    pub kind: EventKind,
+    // local time when tsm noticed the event, like "14:03:27"
+    pub time: String,
+    pub detected: Instant,
+    // pid and name of the killed process
+    pub victim: Option<(u32, String)>,
+    pub message: String,
+}
+
+// sysfs counter of one event source
+struct EventCounter {
+    kind: EventKind,
+    // "mc0 corrected", "cpu3" or "package"
+    name: String,
+    path: PathBuf,
+    last: Option<u64>,
+}
+
+impl EventCounter {
+    // increment since the previous read, counters present at start are not events
+    fn read_increment(&mut self) -> u64 {
+        let Some(value) = read_number(&self.path).map(|value| value as u64) else {
+            return 0;
+        };
+        let increment = self.last.map(|last| value.saturating_sub(last)).unwrap_or(0);
+        self.last = Some(value);
+        increment
+    }
+}
+
+pub struct EventsInfo {
+    procfs_root: PathBuf,
+    kmsg: Option<File>,
+    kmsg_partial: String,
+    oom_regex: Regex,
+    mce_regex: Regex,
+    oom_kills: Option<u64>,
+    // victims from the kernel log minus oom_kill increments, negative while messages are missing
+    oom_balance: i64,
+    oom_unmatched_since: Option<Instant>,
+    counters: Vec<EventCounter>,
+    max_events: usize,
+    banner: Duration,
+    // newest last
+    pub events: VecDeque<KernelEvent>,
+    // OOM victims are unknown without the kernel log, it needs root when dmesg_restrict is set
+    pub kmsg_error: Option<String>,
+}
+
+impl EventsInfo {
+    pub fn new(options: &EventsTileOptions) -> Self {
+        let (kmsg, kmsg_error) = match open_kmsg(Path::new(&options.kmsg_path)) {
+            Ok(kmsg) => (Some(kmsg), None),
+            Err(err) => (None, Some(format!("{}: {err}", options.kmsg_path))),
+        };
+
+        let mut events_info = EventsInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            kmsg,
+            kmsg_partial: String::new(),
+            // "Out of memory: Killed process 1234 (python3) total-vm:..." and
+            // "Memory cgroup out of memory: Killed process ...", "Kill process" on old kernels
+            oom_regex: Regex::new(r"(Memory cgroup out of memory|Out of memory).*?Kill(?:ed)? process (\d+) \(([^)]*)\)")
+                .expect("valid regex"),
+            // temperature messages of the mce driver are counted by thermal_throttle
+            mce_regex: Regex::new(r"(?i)\[Hardware Error\]|machine check|mce: .*error").expect("valid regex"),
+            oom_kills: None,
+            oom_balance: 0,
+            oom_unmatched_since: None,
+            counters: get_counters(&PathBuf::from(&options.sysfs_root)),
+            max_events: options.max_events.max(1),
+            banner: Duration::from_secs(options.banner),
+            events: VecDeque::new(),
+            kmsg_error,
+        };
+        events_info.update();
+        events_info
+    }
+
+    pub fn update(&mut self) {
+        for message in self.read_kmsg() {
+            self.parse_kmsg_message(&message);
+        }
+        self.check_oom_counter();
+        self.check_counters();
+    }
+
+    // newest event while it's fresh, for the status line
+    pub fn get_banner(&self) -> Option<String> {
+        let event = self.events.back()?;
+        if event.detected.elapsed() > self.banner {
+            return None;
+        }
+        Some(format!("{} {} {}", event.time, event.kind.name(), self.describe(event)))
+    }
+
+    pub fn describe(&self, event: &KernelEvent) -> String {
+        match &event.victim {
+            Some((pid, name)) => format!("{name} ({pid}) {}", event.message),
+            None => event.message.clone(),
+        }
+    }
+
+    fn push_event(&mut self, kind: EventKind, victim: Option<(u32, String)>, message: String) {
+        if self.events.len() == self.max_events {
+            self.events.pop_front();
+        }
+        self.events.push_back(KernelEvent {
+            kind,
+            time: format_local_time(SystemTime::now()),
+            detected: Instant::now(),
+            victim,
+            message,
+        });
+    }
+
+    fn read_kmsg(&mut self) -> Vec<String> {
+        let Some(kmsg) = &mut self.kmsg else {
+            return Vec::new();
+        };
+        // every read of /dev/kmsg returns one record, the buffer must fit the longest one
+        let mut buffer = [0u8; 8192];
+        let mut failed = None;
+        loop {
+            match kmsg.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => self.kmsg_partial.push_str(&String::from_utf8_lossy(&buffer[..read])),
+                // records were overwritten before tsm read them, next read returns the oldest one left
+                Err(err) if err.kind() == ErrorKind::BrokenPipe => continue,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    failed = Some(err);
+                    break;
+                }
+            }
+        }
+        if let Some(err) = failed {
+            self.kmsg_error = Some(format!("Can't read kernel log: {err}"));
+            self.kmsg = None;
+        }
+
+        let mut lines: Vec<String> = self.kmsg_partial.split('\n').map(|line| line.to_string()).collect();
+        self.kmsg_partial = lines.pop().unwrap_or_default();
+        lines
+    }
+
+    fn parse_kmsg_message(&mut self, line: &str) {
+        let Some(message) = get_kmsg_message(line) else {
+            return;
+        };
+        if let Some(captures) = self.oom_regex.captures(message) {
+            let pid = captures[2].parse().unwrap_or(0);
+            let name = captures[3].to_string();
+            let scope = if captures[1].starts_with("Memory cgroup") { "in memory cgroup" } else { "system wide" };
+            // "anon-rss:1234567kB" is what the victim held
+            let rss = message
+                .split_once("anon-rss:")
+                .and_then(|(_, rest)| rest.split_once("kB"))
+                .and_then(|(rss, _)| rss.parse::<u64>().ok())
+                .map(|rss| format!(", rss {}MiB", rss / 1024))
+                .unwrap_or_default();
+            self.oom_balance += 1;
+            self.push_event(EventKind::Oom, Some((pid, name)), format!("killed {scope}{rss}"));
+        } else if self.mce_regex.is_match(message) {
+            // one machine check is logged as several lines
+            let repeated = self
+                .events
+                .back()
+                .is_some_and(|event| event.kind == EventKind::Mce && event.detected.elapsed() < Duration::from_secs(1));
+            if !repeated {
+                self.push_event(EventKind::Mce, None, message.trim().to_string());
+            }
+        }
+    }
+
+    // oom_kill in /proc/vmstat counts kills even when the kernel log is not readable
+    fn check_oom_counter(&mut self) {
+        let oom_kills = std::fs::read_to_string(self.procfs_root.join("vmstat"))
+            .ok()
+            .and_then(|vmstat| {
+                vmstat
+                    .lines()
+                    .find_map(|line| line.strip_prefix("oom_kill "))
+                    .and_then(|value| value.trim().parse::<u64>().ok())
+            });
+        let Some(oom_kills) = oom_kills else {
+            return;
+        };
+        if let Some(last) = self.oom_kills {
+            self.oom_balance -= oom_kills.saturating_sub(last) as i64;
+        }
+        self.oom_kills = Some(oom_kills);
+
+        if self.oom_balance >= 0 {
+            self.oom_unmatched_since = None;
+            // messages of kills that happened before the counter was read first
+            self.oom_balance = 0;
+            return;
+        }
+        let unmatched_since = *self.oom_unmatched_since.get_or_insert_with(Instant::now);
+        if unmatched_since.elapsed() >= OOM_MATCH_DELAY {
+            for _ in 0..-self.oom_balance {
+                self.push_event(EventKind::Oom, None, "process killed, victim unknown".to_string());
+            }
+            self.oom_balance = 0;
+            self.oom_unmatched_since = None;
+        }
+    }
+
+    fn check_counters(&mut self) {
+        let mut throttled: Vec<String> = Vec::new();
+        let mut edac_events: Vec<String> = Vec::new();
+        for counter in &mut self.counters {
+            let increment = counter.read_increment();
+            if increment == 0 {
+                continue;
+            }
+            match counter.kind {
+                EventKind::Thermal => {
+                    if !throttled.contains(&counter.name) {
+                        throttled.push(counter.name.clone());
+                    }
+                }
+                _ => edac_events.push(format!("{}: {increment} errors", counter.name)),
+            }
+        }
+
+        for message in edac_events {
+            self.push_event(EventKind::Edac, None, message);
+        }
+        // every cpu of a package reports the same package counter, one event per update is enough
+        if !throttled.is_empty() {
+            self.push_event(EventKind::Thermal, None, format!("clock throttled on {}", throttled.join(", ")));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn open_kmsg(path: &Path) -> std::io::Result<File> {
+    use std::io::{Seek, SeekFrom};
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut kmsg = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    // only events after start are interesting
+    kmsg.seek(SeekFrom::End(0))?;
+    Ok(kmsg)
+}
+
+#[cfg(not(unix))]
+fn open_kmsg(_path: &Path) -> std::io::Result<File> {
+    Err(std::io::Error::new(ErrorKind::Unsupported, "kernel log is available on Linux only"))
+}
+
+// "6,1234,5678901,-;message" from /dev/kmsg or "[ 5678.901234] message" from dmesg output
+fn get_kmsg_message(line: &str) -> Option<&str> {
+    // continuation lines like " SUBSYSTEM=pci" carry device properties
+    if line.starts_with(' ') || line.is_empty() {
+        return None;
+    }
+    if let Some((header, message)) = line.split_once(';') {
+        if header.split(',').next().is_some_and(|priority| priority.parse::<u32>().is_ok()) {
+            return Some(message);
+        }
+    }
+    if line.starts_with('[') {
+        return line.split_once("] ").map(|(_, message)| message);
+    }
+    Some(line)
+}
+
+// EDAC memory controller error counts and cpu thermal throttling counts
+fn get_counters(sysfs_root: &Path) -> Vec<EventCounter> {
+    let mut counters = Vec::new();
+    for mc_dir in get_sorted_dirs(&sysfs_root.join("devices/system/edac/mc"), "mc") {
+        let mc = mc_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        for (file, name) in [("ce_count", "corrected"), ("ue_count", "uncorrected")] {
+            counters.push(EventCounter {
+                kind: EventKind::Edac,
+                name: format!("{mc} {name}"),
+                path: mc_dir.join(file),
+                last: None,
+            });
+        }
+    }
+
+    for cpu_dir in get_sorted_dirs(&sysfs_root.join("devices/system/cpu"), "cpu") {
+        let cpu = cpu_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let throttle_dir = cpu_dir.join("thermal_throttle");
+        if !throttle_dir.exists() {
+            continue;
+        }
+        counters.push(EventCounter {
+            kind: EventKind::Thermal,
+            name: cpu,
+            path: throttle_dir.join("core_throttle_count"),
+            last: None,
+        });
+        counters.push(EventCounter {
+            kind: EventKind::Thermal,
+            name: "package".to_string(),
+            path: throttle_dir.join("package_throttle_count"),
+            last: None,
+        });
+    }
+
+    for counter in &mut counters {
+        counter.read_increment();
+    }
+    counters
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    // kernel log is a plain file here, lines written after start are read like new kmsg records
+    fn watch_events(fixture: &Fixture) -> EventsInfo {
+        fixture.write("kmsg", "6,1,100,-;before start\n");
+        EventsInfo::new(&EventsTileOptions {
+            kmsg_path: fixture.path().join("kmsg").to_string_lossy().into_owned(),
+            procfs_root: fixture.root(),
+            sysfs_root: fixture.root(),
+            ..EventsTileOptions::default()
+        })
+    }
+
+    fn log(fixture: &Fixture, lines: &str) {
+        let mut kmsg = OpenOptions::new().append(true).open(fixture.path().join("kmsg")).unwrap();
+        kmsg.write_all(lines.as_bytes()).unwrap();
+    }
+
+    fn get_events(events_info: &EventsInfo) -> Vec<(&str, String)> {
+        events_info.events.iter().map(|event| (event.kind.name(), events_info.describe(event))).collect()
+    }
+
+    #[test]
+    fn matches_oom_victims_with_the_vmstat_counter() {
+        let fixture = Fixture::new();
+        fixture.write("vmstat", "pgfault 100\noom_kill 3\n");
+        let mut events_info = watch_events(&fixture);
+        assert_eq!(events_info.kmsg_error, None);
+        assert!(events_info.events.is_empty());
+
+        log(
+            &fixture,
+            "3,812,123456,-;Out of memory: Killed process 1234 (python3) total-vm:4096kB, anon-rss:1048576kB, \
+             file-rss:0kB\n SUBSYSTEM=memory\n\
+             3,813,123457,-;Memory cgroup out of memory: Killed process 77 (java) total-vm:1kB\n\
+             3,814,123458,-;Out of memory: Kill process 5 (old kernel) score 900\n",
+        );
+        fixture.write("vmstat", "pgfault 100\noom_kill 6\n");
+        events_info.update();
+        assert_eq!(
+            get_events(&events_info),
+            [
+                ("OOM", "python3 (1234) killed system wide, rss 1024MiB".to_string()),
+                ("OOM", "java (77) killed in memory cgroup".to_string()),
+                ("OOM", "old kernel (5) killed system wide".to_string()),
+            ]
+        );
+
+        // a kill without a readable message is reported once the message had time to arrive
+        fixture.write("vmstat", "pgfault 100\noom_kill 7\n");
+        events_info.update();
+        assert_eq!(events_info.events.len(), 3);
+        events_info.oom_unmatched_since = Some(Instant::now() - OOM_MATCH_DELAY);
+        events_info.update();
+        assert_eq!(get_events(&events_info)[3], ("OOM", "process killed, victim unknown".to_string()));
+        events_info.update();
+        assert_eq!(events_info.events.len(), 4);
+    }
+
+    #[test]
+    fn reports_one_event_per_machine_check() {
+        let fixture = Fixture::new();
+        let mut events_info = watch_events(&fixture);
+        log(
+            &fixture,
+            "[ 12.345678] mce: [Hardware Error]: CPU 0: Machine Check: 0 Bank 5: be00000000800400\n\
+             [ 12.345679] mce: [Hardware Error]: TSC 0 ADDR fef1a140\n\
+             [ 12.345680] usb 1-1: new high-speed USB device\n",
+        );
+        events_info.update();
+        assert_eq!(
+            get_events(&events_info),
+            [("MCE", "mce: [Hardware Error]: CPU 0: Machine Check: 0 Bank 5: be00000000800400".to_string())]
+        );
+    }
+
+    #[test]
+    fn reports_edac_and_throttling_counter_increments() {
+        let fixture = Fixture::new();
+        fixture
+            .write("devices/system/edac/mc/mc0/ce_count", "1\n")
+            .write("devices/system/edac/mc/mc0/ue_count", "0\n")
+            .write("devices/system/cpu/cpu0/thermal_throttle/core_throttle_count", "5\n")
+            .write("devices/system/cpu/cpu0/thermal_throttle/package_throttle_count", "9\n")
+            .write("devices/system/cpu/cpu1/thermal_throttle/core_throttle_count", "0\n")
+            .write("devices/system/cpu/cpu1/thermal_throttle/package_throttle_count", "9\n");
+        // counts present at start are not events
+        let mut events_info = watch_events(&fixture);
+        assert!(events_info.events.is_empty());
+
+        fixture
+            .write("devices/system/edac/mc/mc0/ce_count", "3\n")
+            .write("devices/system/cpu/cpu0/thermal_throttle/core_throttle_count", "6\n")
+            .write("devices/system/cpu/cpu0/thermal_throttle/package_throttle_count", "10\n")
+            .write("devices/system/cpu/cpu1/thermal_throttle/package_throttle_count", "10\n");
+        events_info.update();
+        assert_eq!(
+            get_events(&events_info),
+            [
+                ("EDAC", "mc0 corrected: 2 errors".to_string()),
+                ("THERM", "clock throttled on cpu0, package".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn takes_messages_of_kmsg_and_dmesg_lines() {
+        assert_eq!(get_kmsg_message("6,1234,5678901,-;eth0: link up"), Some("eth0: link up"));
+        assert_eq!(get_kmsg_message("[ 5678.901234] eth0: link up"), Some("eth0: link up"));
+        assert_eq!(get_kmsg_message(" SUBSYSTEM=net"), None);
+        assert_eq!(get_kmsg_message("plain; text"), Some("plain; text"));
+    }
+}
@@ -0,0 +1,612 @@
+// `--serve <addr>`: the collectors of the config as a Prometheus endpoint, without a terminal
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::disk_info::SECTOR_SIZE;
+use crate::file_config::FileDevice;
+use crate::recorder::{Collectors, Sources, RECORDED_TILES};
+use crate::sensors_info::SensorKind;
+
+// scrapes closer than this share one sample, rates need some time between updates
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+const MIB: f64 = 1024.0 * 1024.0;
+
+// metrics in the text exposition format, HELP and TYPE once before the first sample of a metric
+#[derive(Default)]
+struct Exposition {
+    text: String,
+    last_metric: String,
+}
+
+impl Exposition {
+    fn add(&mut self, name: &str, kind: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        if self.last_metric != name {
+            self.text += &format!("# HELP {name} {help}\n# TYPE {name} {kind}\n");
+            self.last_metric = name.to_string();
+        }
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value)))
+            .collect();
+        let value = format_value(value);
+        if labels.is_empty() {
+            self.text += &format!("{name} {value}\n");
+        } else {
+            self.text += &format!("{name}{{{}}} {value}\n", labels.join(","));
+        }
+    }
+}
+
+// rust writes "inf" and "NaN", the text format wants "+Inf", "-Inf" and "NaN"
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// labels of one instance, the first tile of a type has no tile label so single tile configs keep their series
+fn get_labels<'a>(labels: &[(&'a str, &'a str)], tile: &'a str) -> Vec<(&'a str, &'a str)> {
+    let mut labels = labels.to_vec();
+    if tile != "0" {
+        labels.push(("tile", tile));
+    }
+    labels
+}
+
+// samples of one metric are kept together, so every metric is a loop over the tiles and their instances
+fn get_exposition(sources: &Sources) -> String {
+    let mut exposition = Exposition::default();
+    let tile_names = |count: usize| -> Vec<String> { (0..count).map(|tile| tile.to_string()).collect() };
+
+    let cpu_tiles = tile_names(sources.cpu.len());
+    for (cpu, tile) in sources.cpu.iter().zip(&cpu_tiles) {
+        for index in 0..cpu.cpu_count {
+            let help = "Share of time the cpu was busy since the previous scrape";
+            let index_name = index.to_string();
+            let labels = get_labels(&[("cpu", &index_name)], tile);
+            exposition.add("tsm_cpu_usage_ratio", "gauge", help, &labels, cpu.get_cpu_usage(index) / 100.0);
+        }
+    }
+    for (cpu, tile) in sources.cpu.iter().zip(&cpu_tiles) {
+        for index in 0..cpu.cpu_count {
+            if let Some(frequency) = cpu.get_frequency(index) {
+                let help = "Current cpu frequency";
+                let index_name = index.to_string();
+                let labels = get_labels(&[("cpu", &index_name)], tile);
+                exposition.add("tsm_cpu_frequency_hertz", "gauge", help, &labels, frequency * 1e6);
+            }
+        }
+    }
+    let memory_metrics: [(&str, &str); 4] = [
+        ("tsm_memory_used_bytes", "Used RAM"),
+        ("tsm_memory_total_bytes", "Total RAM"),
+        ("tsm_swap_used_bytes", "Used swap"),
+        ("tsm_swap_total_bytes", "Total swap"),
+    ];
+    for (metric, (name, help)) in memory_metrics.iter().enumerate() {
+        for (cpu, tile) in sources.cpu.iter().zip(&cpu_tiles) {
+            let value = match metric {
+                0 => cpu.get_ram_usage().0,
+                1 => cpu.get_ram_usage().1,
+                2 => cpu.get_swap_usage().0,
+                _ => cpu.get_swap_usage().1,
+            };
+            exposition.add(name, "gauge", help, &get_labels(&[], tile), value as f64 * MIB);
+        }
+    }
+
+    let gpu_tiles = tile_names(sources.gpus.len());
+    // labels of every gpu of every tile
+    let gpu_labels: Vec<Vec<Vec<(&str, String)>>> = sources
+        .gpus
+        .iter()
+        .map(|gpus| {
+            (0..gpus.device_count)
+                .map(|gpu| {
+                    let (index, mig_index) = gpus.get_index(gpu);
+                    let mut labels = vec![
+                        ("gpu", index.to_string()),
+                        ("uuid", gpus.get_uuid(gpu).to_string()),
+                        ("name", gpus.get_name(gpu).to_string()),
+                    ];
+                    if let Some(mig_index) = mig_index {
+                        labels.push(("mig", mig_index.to_string()));
+                    }
+                    labels
+                })
+                .collect()
+        })
+        .collect();
+    let get_gpu_labels = |tile: usize, gpu: u32| -> Vec<(&str, &str)> {
+        let labels: Vec<(&str, &str)> =
+            gpu_labels[tile][gpu as usize].iter().map(|(label, value)| (*label, value.as_str())).collect();
+        get_labels(&labels, &gpu_tiles[tile])
+    };
+    let gpu_metrics: [(&str, &str); 4] = [
+        ("tsm_gpu_memory_used_bytes", "Used gpu memory"),
+        ("tsm_gpu_memory_total_bytes", "Total gpu memory"),
+        ("tsm_gpu_utilization_ratio", "Share of time a kernel was running on the gpu"),
+        ("tsm_gpu_temperature_celsius", "Gpu core temperature"),
+    ];
+    for (metric, (name, help)) in gpu_metrics.iter().enumerate() {
+        for (tile, gpus) in sources.gpus.iter().enumerate() {
+            for gpu in 0..gpus.device_count {
+                // MIG instances have no temperature, and no utilization without GPM
+                let value = match metric {
+                    0 => Some(gpus.get_memory_info(gpu).0 * MIB),
+                    1 => Some(gpus.get_memory_info(gpu).1 * MIB),
+                    2 => gpus.get_utilization_rate_info(gpu).map(|utilization| utilization / 100.0),
+                    _ => gpus.get_temperature(gpu).map(|temperature| temperature as f64),
+                };
+                if let Some(value) = value {
+                    exposition.add(name, "gauge", help, &get_gpu_labels(tile, gpu), value);
+                }
+            }
+        }
+    }
+
+    let network_tiles = tile_names(sources.network.len());
+    // counters are totals, rate() in the query turns them into rates over any window
+    let network_metrics: [(&str, &str, &str); 7] = [
+        ("tsm_network_up", "gauge", "1 when the interface is up"),
+        ("tsm_network_receive_bytes_total", "counter", "Received bytes since the interface came up"),
+        ("tsm_network_transmit_bytes_total", "counter", "Sent bytes since the interface came up"),
+        ("tsm_network_receive_packets_total", "counter", "Received packets since the interface came up"),
+        ("tsm_network_transmit_packets_total", "counter", "Sent packets since the interface came up"),
+        ("tsm_network_errors_total", "counter", "Receive and transmit errors since boot"),
+        ("tsm_network_dropped_total", "counter", "Dropped packets since boot"),
+    ];
+    for (metric, (name, kind, help)) in network_metrics.iter().enumerate() {
+        for (network, tile) in sources.network.iter().zip(&network_tiles) {
+            for interface in &network.interfaces {
+                let value = match metric {
+                    0 => f64::from(interface.link_state == "up"),
+                    1 => interface.counters.rx_bytes as f64,
+                    2 => interface.counters.tx_bytes as f64,
+                    3 => interface.counters.rx_packets as f64,
+                    4 => interface.counters.tx_packets as f64,
+                    5 => interface.errors as f64,
+                    _ => interface.dropped as f64,
+                };
+                exposition.add(name, kind, help, &get_labels(&[("interface", &interface.name)], tile), value);
+            }
+        }
+    }
+
+    let disk_tiles = tile_names(sources.disks.len());
+    // latency is rate(read_time + write_time) / rate(reads + writes), queue depth rate(io_time_weighted)
+    let disk_metrics: [(&str, &str); 8] = [
+        ("tsm_disk_read_bytes_total", "Read bytes since boot"),
+        ("tsm_disk_written_bytes_total", "Written bytes since boot"),
+        ("tsm_disk_reads_completed_total", "Completed reads since boot"),
+        ("tsm_disk_writes_completed_total", "Completed writes since boot"),
+        ("tsm_disk_read_time_seconds_total", "Time spent on reads since boot"),
+        ("tsm_disk_write_time_seconds_total", "Time spent on writes since boot"),
+        ("tsm_disk_io_time_seconds_total", "Time the device had requests in flight since boot"),
+        ("tsm_disk_io_time_weighted_seconds_total", "Time spent on requests times the requests in flight since boot"),
+    ];
+    for (metric, (name, help)) in disk_metrics.iter().enumerate() {
+        for (disks, tile) in sources.disks.iter().zip(&disk_tiles) {
+            for device in &disks.devices {
+                let counters = &device.counters;
+                let value = match metric {
+                    0 => counters.read_sectors as f64 * SECTOR_SIZE,
+                    1 => counters.write_sectors as f64 * SECTOR_SIZE,
+                    2 => counters.reads as f64,
+                    3 => counters.writes as f64,
+                    4 => counters.read_ms as f64 / 1000.0,
+                    5 => counters.write_ms as f64 / 1000.0,
+                    6 => counters.io_ms as f64 / 1000.0,
+                    _ => counters.weighted_io_ms as f64 / 1000.0,
+                };
+                exposition.add(name, "counter", help, &get_labels(&[("device", &device.name)], tile), value);
+            }
+        }
+    }
+
+    let filesystem_tiles = tile_names(sources.filesystems.len());
+    let filesystem_metrics: [(&str, &str); 6] = [
+        ("tsm_filesystem_size_bytes", "Size of the filesystem"),
+        ("tsm_filesystem_used_bytes", "Used bytes"),
+        ("tsm_filesystem_available_bytes", "Bytes available to unprivileged users"),
+        ("tsm_filesystem_inodes_used", "Used inodes"),
+        ("tsm_filesystem_inodes_total", "Total inodes"),
+        ("tsm_filesystem_stale", "1 when the mount didn't answer and the values are from before"),
+    ];
+    for (metric, (name, help)) in filesystem_metrics.iter().enumerate() {
+        for (filesystems, tile) in sources.filesystems.iter().zip(&filesystem_tiles) {
+            for filesystem in &filesystems.filesystems {
+                let value = match metric {
+                    0 => filesystem.total as f64,
+                    1 => filesystem.used as f64,
+                    2 => filesystem.available as f64,
+                    3 => filesystem.inodes_used as f64,
+                    4 => filesystem.inodes_total as f64,
+                    _ => f64::from(filesystem.stale),
+                };
+                let labels = [
+                    ("mountpoint", filesystem.mount_point.as_str()),
+                    ("device", filesystem.device.as_str()),
+                    ("fstype", filesystem.fs_type.as_str()),
+                ];
+                exposition.add(name, "gauge", help, &get_labels(&labels, tile), value);
+            }
+        }
+    }
+
+    let sensor_tiles = tile_names(sources.sensors.len());
+    let sensor_metrics: [(SensorKind, &str, &str); 3] = [
+        (SensorKind::Temperature, "tsm_sensor_temperature_celsius", "Hardware temperature sensor"),
+        (SensorKind::Fan, "tsm_sensor_fan_rpm", "Fan speed"),
+        (SensorKind::Voltage, "tsm_sensor_voltage_volts", "Hardware voltage sensor"),
+    ];
+    for (kind, name, help) in sensor_metrics {
+        for (sensors, tile) in sources.sensors.iter().zip(&sensor_tiles) {
+            for chip in &sensors.chips {
+                for reading in chip.readings.iter().filter(|reading| reading.kind == kind) {
+                    let labels = [("chip", chip.name.as_str()), ("sensor", reading.label.as_str())];
+                    exposition.add(name, "gauge", help, &get_labels(&labels, tile), reading.value);
+                }
+            }
+        }
+    }
+
+    let power_tiles = tile_names(sources.power.len());
+    for (power, tile) in sources.power.iter().zip(&power_tiles) {
+        for component in &power.components {
+            let help = "Power draw of a RAPL zone or a gpu";
+            let labels = get_labels(&[("component", &component.name)], tile);
+            exposition.add("tsm_power_watts", "gauge", help, &labels, component.watts);
+        }
+    }
+    for (power, tile) in sources.power.iter().zip(&power_tiles) {
+        for component in &power.components {
+            let help = "Energy used by a RAPL zone or a gpu since tsm started";
+            let labels = get_labels(&[("component", &component.name)], tile);
+            exposition.add("tsm_power_energy_joules_total", "counter", help, &labels, component.energy);
+        }
+    }
+    for (power, tile) in sources.power.iter().zip(&power_tiles) {
+        let help = "Power draw of packages, dram and gpus together";
+        exposition.add("tsm_power_total_watts", "gauge", help, &get_labels(&[], tile), power.total_watts);
+    }
+
+    let system_tiles = tile_names(sources.system.len());
+    let system_metrics: [(&str, &str, &str); 8] = [
+        ("tsm_load1", "gauge", "Load average over 1 minute"),
+        ("tsm_load5", "gauge", "Load average over 5 minutes"),
+        ("tsm_load15", "gauge", "Load average over 15 minutes"),
+        ("tsm_uptime_seconds", "gauge", "Time since boot"),
+        ("tsm_tasks_running", "gauge", "Runnable tasks"),
+        ("tsm_tasks_blocked", "gauge", "Tasks blocked on I/O"),
+        ("tsm_context_switches_total", "counter", "Context switches since boot"),
+        ("tsm_interrupts_total", "counter", "Interrupts since boot"),
+    ];
+    for (metric, (name, kind, help)) in system_metrics.iter().enumerate() {
+        for (system, tile) in sources.system.iter().zip(&system_tiles) {
+            let value = match metric {
+                0..=2 => Some(system.load[metric]),
+                3 => Some(system.uptime),
+                4 => Some(system.tasks_running as f64),
+                5 => Some(system.tasks_blocked as f64),
+                6 => system.context_switches_total.map(|total| total as f64),
+                _ => system.interrupts_total.map(|total| total as f64),
+            };
+            if let Some(value) = value {
+                exposition.add(name, kind, help, &get_labels(&[], tile), value);
+            }
+        }
+    }
+    // kernels without PSI have no pressure files
+    let pressure_metrics: [(&str, &str); 2] = [
+        ("tsm_pressure_avg10_ratio", "Share of time tasks stalled on the resource over 10 seconds"),
+        ("tsm_pressure_avg60_ratio", "Share of time tasks stalled on the resource over 60 seconds"),
+    ];
+    for (metric, (name, help)) in pressure_metrics.iter().enumerate() {
+        for (system, tile) in sources.system.iter().zip(&system_tiles) {
+            for pressure in &system.pressure {
+                for (kind, line) in [("some", pressure.some), ("full", pressure.full)] {
+                    let Some(line) = line else {
+                        continue;
+                    };
+                    let value = if metric == 0 { line.avg10 } else { line.avg60 };
+                    let labels = get_labels(&[("resource", pressure.resource), ("kind", kind)], tile);
+                    exposition.add(name, "gauge", help, &labels, value / 100.0);
+                }
+            }
+        }
+    }
+
+    let battery_tiles = tile_names(sources.batteries.len());
+    let battery_metrics: [(&str, &str); 4] = [
+        ("tsm_battery_capacity_ratio", "Charge of the battery"),
+        ("tsm_battery_discharging", "1 when the battery is discharging"),
+        ("tsm_battery_power_watts", "Power going in or out of the battery"),
+        ("tsm_battery_time_remaining_seconds", "Time to empty while discharging, to full while charging"),
+    ];
+    for (metric, (name, help)) in battery_metrics.iter().enumerate() {
+        for (batteries, tile) in sources.batteries.iter().zip(&battery_tiles) {
+            for battery in &batteries.batteries {
+                let value = match metric {
+                    0 => Some(battery.capacity),
+                    1 => Some(f64::from(battery.status == "Discharging")),
+                    2 => battery.power,
+                    _ => battery.time_remaining,
+                };
+                if let Some(value) = value {
+                    exposition.add(name, "gauge", help, &get_labels(&[("battery", &battery.name)], tile), value);
+                }
+            }
+        }
+    }
+    for (batteries, tile) in sources.batteries.iter().zip(&battery_tiles) {
+        for adapter in &batteries.adapters {
+            let help = "1 when the power adapter is connected";
+            let labels = get_labels(&[("adapter", &adapter.name)], tile);
+            exposition.add("tsm_power_supply_online", "gauge", help, &labels, f64::from(adapter.online));
+        }
+    }
+
+    let cgroup_tiles = tile_names(sources.cgroups.len());
+    let cgroup_metrics: [(&str, &str, &str); 7] = [
+        ("tsm_cgroup_cpu_usage_seconds_total", "counter", "Cpu time used by the cgroup"),
+        ("tsm_cgroup_memory_bytes", "gauge", "Memory charged to the cgroup"),
+        ("tsm_cgroup_cpu_periods_total", "counter", "Enforcement periods of the cpu.max quota"),
+        ("tsm_cgroup_cpu_throttled_periods_total", "counter", "Periods the cgroup was throttled"),
+        ("tsm_cgroup_cpu_throttled_seconds_total", "counter", "Time the cgroup was throttled"),
+        ("tsm_cgroup_io_read_bytes_total", "counter", "Bytes read by the cgroup"),
+        ("tsm_cgroup_io_written_bytes_total", "counter", "Bytes written by the cgroup"),
+    ];
+    for (metric, (name, kind, help)) in cgroup_metrics.iter().enumerate() {
+        for (cgroups, tile) in sources.cgroups.iter().zip(&cgroup_tiles) {
+            for cgroup in cgroups.parent.iter().chain(&cgroups.children) {
+                let counters = cgroup.counters;
+                let value = match metric {
+                    0 => counters.map(|counters| counters.usage_usec as f64 / 1e6),
+                    1 => Some(cgroup.memory_current as f64),
+                    2 => counters.map(|counters| counters.nr_periods as f64),
+                    3 => counters.map(|counters| counters.nr_throttled as f64),
+                    4 => counters.map(|counters| counters.throttled_usec as f64 / 1e6),
+                    5 => counters.map(|counters| counters.io_read_bytes as f64),
+                    _ => counters.map(|counters| counters.io_write_bytes as f64),
+                };
+                if let Some(value) = value {
+                    exposition.add(name, kind, help, &get_labels(&[("cgroup", &cgroup.name)], tile), value);
+                }
+            }
+        }
+    }
+    exposition.text
+}
+
+// ":9101" listens on every interface
+fn parse_address(address: &str) -> String {
+    match address.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{port}"),
+        None => address.to_string(),
+    }
+}
+
+// runs until killed, on_tick is called between requests
+pub fn serve(address: &str, devices: &[FileDevice], on_tick: impl FnMut()) -> Result<(), String> {
+    let address = parse_address(address);
+    let listener = TcpListener::bind(&address).map_err(|err| format!("Can't listen on {address}: {err}"))?;
+    // accept is polled so the recorder keeps running without scrapes
+    listener.set_nonblocking(true).map_err(|err| err.to_string())?;
+    eprintln!("Serving metrics on http://{address}/metrics");
+    serve_listener(listener, Collectors::new(devices, &RECORDED_TILES), on_tick)
+}
+
+fn serve_listener(listener: TcpListener, mut collectors: Collectors, mut on_tick: impl FnMut()) -> Result<(), String> {
+    let mut last_sample = Instant::now();
+    let mut exposition: Arc<str> = Arc::from("");
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if exposition.is_empty() || last_sample.elapsed() >= MIN_SAMPLE_INTERVAL {
+                    collectors.update();
+                    exposition = Arc::from(get_exposition(&collectors.get_sources()));
+                    last_sample = Instant::now();
+                }
+                // a slow client holds only its own thread, a client that went away doesn't stop the exporter
+                let exposition = Arc::clone(&exposition);
+                std::thread::spawn(move || handle_client(stream, &exposition).ok());
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(err) => eprintln!("ERROR: Can't accept connection: {err}"),
+        }
+        on_tick();
+    }
+}
+
+fn handle_client(stream: TcpStream, exposition: &str) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    // for the whole request, not for every read
+    let deadline = Instant::now() + CLIENT_TIMEOUT;
+    let read_line = |reader: &mut BufReader<&TcpStream>, line: &mut String| {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        }
+        reader.get_ref().set_read_timeout(Some(remaining))?;
+        reader.read_line(line)
+    };
+
+    // "GET /metrics HTTP/1.1", headers are not needed
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    read_line(&mut reader, &mut request_line)?;
+    // unread headers would reset the connection before the client reads the response
+    let mut header = String::new();
+    while read_line(&mut reader, &mut header)? > 2 {
+        header.clear();
+    }
+    let (status, content_type, body) = route(&request_line, exposition);
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+// status, content type and body for a request line
+fn route<'a>(request_line: &str, exposition: &'a str) -> (&'static str, &'static str, &'a str) {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let path = path.split('?').next().unwrap_or_default();
+
+    match (method, path) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", exposition),
+        ("GET", "/") => ("200 OK", "text/html", "<html><body><a href=\"/metrics\">Metrics</a></body></html>\n"),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not found\n"),
+        _ => ("405 Method Not Allowed", "text/plain", "Method not allowed\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use serde_json::{json, Map};
+    use std::io::Read;
+
+    #[test]
+    fn writes_help_and_type_once_per_metric() {
+        let mut exposition = Exposition::default();
+        exposition.add("tsm_cpu_usage_ratio", "gauge", "Cpu busy share", &[("cpu", "0")], 0.25);
+        exposition.add("tsm_cpu_usage_ratio", "gauge", "Cpu busy share", &[("cpu", "1")], 1.0);
+        exposition.add("tsm_memory_used_bytes", "gauge", "Used RAM", &[], 1048576.0);
+        assert_eq!(
+            exposition.text,
+            "# HELP tsm_cpu_usage_ratio Cpu busy share\n\
+             # TYPE tsm_cpu_usage_ratio gauge\n\
+             tsm_cpu_usage_ratio{cpu=\"0\"} 0.25\n\
+             tsm_cpu_usage_ratio{cpu=\"1\"} 1\n\
+             # HELP tsm_memory_used_bytes Used RAM\n\
+             # TYPE tsm_memory_used_bytes gauge\n\
+             tsm_memory_used_bytes 1048576\n"
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let mut exposition = Exposition::default();
+        exposition.add("tsm_gpu_utilization_ratio", "gauge", "Busy", &[("gpu", "0"), ("name", "a\"b\\c\nd")], 0.5);
+        assert!(exposition
+            .text
+            .ends_with("tsm_gpu_utilization_ratio{gpu=\"0\",name=\"a\\\"b\\\\c\\nd\"} 0.5\n"));
+    }
+
+    #[test]
+    fn routes_requests() {
+        assert_eq!(route("GET /metrics?x=1 HTTP/1.1\r\n", "text").0, "200 OK");
+        assert_eq!(route("GET /metrics HTTP/1.1\r\n", "text").2, "text");
+        assert_eq!(route("GET / HTTP/1.1\r\n", "text").0, "200 OK");
+        assert_eq!(route("GET /other HTTP/1.1\r\n", "text").0, "404 Not Found");
+        assert_eq!(route("POST /metrics HTTP/1.1\r\n", "text").0, "405 Method Not Allowed");
+        assert_eq!(parse_address(":9101"), "0.0.0.0:9101");
+        assert_eq!(parse_address("127.0.0.1:9101"), "127.0.0.1:9101");
+    }
+
+    #[test]
+    fn exports_every_recorded_collector_of_the_config() {
+        let first = Fixture::new();
+        first.write("self/mounts", &format!("/dev/sda1 {} ext4 rw 0 0\n", first.root()));
+        first.write("loadavg", "0.50 0.25 0.10 2/300 4000\n");
+        first.write("stat", "cpu  1 2 3 4 5 6 7 8 0 0\nintr 7000 10 20\nctxt 5000\n");
+        first.write(
+            "net/dev",
+            "Inter-| Receive | Transmit\n face |bytes packets|bytes packets\n  \
+             eth0: 1000 10 0 0 0 0 0 0 2000 20 0 0 0 0 0 0\n",
+        );
+        first.write(
+            "pressure/io",
+            "some avg10=5.00 avg60=2.00 avg300=1.00 total=100\nfull avg10=1.00 avg60=0.50 avg300=0.10 total=10\n",
+        );
+        let second = Fixture::new();
+        second.write("self/mounts", &format!("/dev/sdb1 {} ext4 rw 0 0\n", second.root()));
+        let tile = |device_type: &str, fixture: &Fixture| {
+            let mut options = Map::new();
+            options.insert("procfs_root".to_string(), json!(fixture.root()));
+            FileDevice {
+                device_type: device_type.to_string(),
+                row: 0,
+                col: 0,
+                width: 1,
+                height: 1,
+                options,
+            }
+        };
+        let devices = [
+            tile("filesystems", &first),
+            tile("filesystems", &second),
+            tile("system", &first),
+            tile("network", &first),
+        ];
+
+        let text = get_exposition(&Collectors::new(&devices, &RECORDED_TILES).get_sources());
+        let first_mount = format!("used_bytes{{mountpoint=\"{}\",device=\"/dev/sda1\",fstype=\"ext4\"}}", first.root());
+        let second_mount = format!("mountpoint=\"{}\",device=\"/dev/sdb1\",fstype=\"ext4\",tile=\"1\"}}", second.root());
+        assert!(text.contains(&first_mount), "{text}");
+        assert!(text.contains(&second_mount), "{text}");
+        assert!(text.contains("\ntsm_load1 0.5\n"));
+        assert!(text.contains("\ntsm_pressure_avg10_ratio{resource=\"io\",kind=\"full\"} 0.01\n"), "{text}");
+        assert_eq!(text.matches("# TYPE tsm_filesystem_used_bytes gauge\n").count(), 1);
+        // counters are exported as totals, not as rates since the previous scrape
+        assert!(text.contains("counter\ntsm_context_switches_total 5000\n"), "{text}");
+        assert!(text.contains("\ntsm_interrupts_total 7000\n"), "{text}");
+        assert!(text.contains("\ntsm_network_transmit_packets_total{interface=\"eth0\"} 20\n"), "{text}");
+    }
+
+    #[test]
+    fn writes_non_finite_values_as_the_text_format_does() {
+        let mut exposition = Exposition::default();
+        for value in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN, -0.5] {
+            exposition.add("tsm_sensor_voltage_volts", "gauge", "Voltage", &[], value);
+        }
+        let values: Vec<&str> = exposition.text.lines().skip(2).map(|line| &line[25..]).collect();
+        assert_eq!(values, ["+Inf", "-Inf", "NaN", "-0.5"]);
+    }
+
+    fn scrape(address: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_metrics_while_a_client_stalls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || serve_listener(listener, Collectors::new(&[], &RECORDED_TILES), || {}));
+
+        // connected and silent, it must not delay the scrape
+        let _stalled = TcpStream::connect(&address).unwrap();
+        let started = Instant::now();
+        let response = scrape(&address, "/metrics");
+        assert!(started.elapsed() < CLIENT_TIMEOUT);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE tsm_cpu_usage_ratio gauge\n"));
+        assert!(response.contains("\ntsm_memory_total_bytes "));
+        assert!(scrape(&address, "/missing").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
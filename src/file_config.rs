@@ -1,5 +1,5 @@
-use::serde::{Deserialize, Serialize};
-use::serde_json;
+use::serde::{de::DeserializeOwned, Deserialize, Serialize};
+use::serde_json::{self, Map, Value};
 use::std::{env, fs};
 use std::path::PathBuf;
 use::std::process::exit;
@@ -11,10 +11,572 @@ pub struct FileDevice {
     pub row: u16,
     pub col: u16,
     pub width: u16,
-    pub height: u16
+    pub height: u16,
+    // tile specific fields, parsed by the tile itself with `parse_options`
+    #[serde(flatten)]
+    pub options: Map<String, Value>
 }
 
+impl FileDevice {
+    pub fn parse_options<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_value(Value::Object(self.options.clone())).unwrap_or_else(|err| {
+            eprintln!("ERROR: Invalid options for {} tile: {err}", self.device_type);
+            exit(1)
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuGrouping {
+    // enumeration order
+    #[default]
+    None,
+    // SMT siblings are paired in all groupings below
+    Socket,
+    Numa,
+    Core,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CpuTileOptions {
+    // draw cores as user/nice/system/iowait/irq/steal/guest segments from /proc/stat
+    pub breakdown: bool,
+    pub frequency: bool,
+    pub group_by: CpuGrouping,
+    // report cpu quota, memory limit and throttling of the cgroup tsm runs in
+    pub cgroup: bool,
+    pub procfs_root: String,
+    pub sysfs_root: String,
+    pub cgroupfs_root: String,
+}
+
+impl Default for CpuTileOptions {
+    fn default() -> Self {
+        CpuTileOptions {
+            breakdown: false,
+            frequency: false,
+            group_by: CpuGrouping::None,
+            cgroup: false,
+            procfs_root: "/proc".to_string(),
+            sysfs_root: "/sys".to_string(),
+            cgroupfs_root: "/sys/fs/cgroup".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct GpuTileOptions {
+    // gpu index ("0"), index range ("0-3"), UUID ("GPU-..."/"MIG-...") or PCI bus id ("0000:3B:00.0").
+    // Empty list selects every gpu
+    pub gpus: Vec<String>,
+    // don't list MIG instances as sub devices of their parent gpu
+    pub hide_mig: bool,
+}
+
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct NetworkTileOptions {
+    // interface name patterns, `*` and `?` wildcards are supported
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub history: bool,
+    pub procfs_root: String,
+    pub sysfs_root: String,
+}
+
+impl Default for NetworkTileOptions {
+    fn default() -> Self {
+        NetworkTileOptions {
+            include: Vec::new(),
+            exclude: vec!["lo".to_string(), "veth*".to_string()],
+            history: true,
+            procfs_root: "/proc".to_string(),
+            sysfs_root: "/sys".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DiskIoTileOptions {
+    // block device name patterns, partitions are filtered by their parent device name
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // show partitions under their parent device
+    pub partitions: bool,
+    pub history: bool,
+    pub procfs_root: String,
+    pub sysfs_root: String,
+}
+
+impl Default for DiskIoTileOptions {
+    fn default() -> Self {
+        DiskIoTileOptions {
+            include: Vec::new(),
+            exclude: vec!["loop*".to_string(), "ram*".to_string(), "zram*".to_string()],
+            partitions: false,
+            history: false,
+            procfs_root: "/proc".to_string(),
+            sysfs_root: "/sys".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct FilesystemsTileOptions {
+    // mount point patterns
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // pseudo filesystems are hidden by default
+    pub exclude_types: Vec<String>,
+    // seconds of history used for the time until full estimate
+    pub forecast_window: u64,
+    pub procfs_root: String,
+}
+
+impl Default for FilesystemsTileOptions {
+    fn default() -> Self {
+        let exclude_types = [
+            "autofs", "binfmt_misc", "bpf", "cgroup", "cgroup2", "configfs", "debugfs", "devpts",
+            "devtmpfs", "efivarfs", "fusectl", "hugetlbfs", "mqueue", "nsfs", "proc", "pstore",
+            "ramfs", "rpc_pipefs", "securityfs", "squashfs", "sysfs", "tracefs",
+        ];
+        FilesystemsTileOptions {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            exclude_types: exclude_types.iter().map(|fs_type| fs_type.to_string()).collect(),
+            forecast_window: 600,
+            procfs_root: "/proc".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SensorsTileOptions {
+    // chip name patterns (coretemp, k10temp, nvme, ...), thermal zones are matched as "thermal"
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub thermal_zones: bool,
+    pub sysfs_root: String,
+}
+
+impl Default for SensorsTileOptions {
+    fn default() -> Self {
+        SensorsTileOptions {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            thermal_zones: true,
+            sysfs_root: "/sys".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct BatteryTileOptions {
+    pub sysfs_root: String,
+}
+
+impl Default for BatteryTileOptions {
+    fn default() -> Self {
+        BatteryTileOptions {
+            sysfs_root: "/sys".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SystemTileOptions {
+    pub pressure: bool,
+    pub procfs_root: String,
+}
+
+impl Default for SystemTileOptions {
+    fn default() -> Self {
+        SystemTileOptions {
+            pressure: true,
+            procfs_root: "/proc".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CgroupsTileOptions {
+    // parent cgroup relative to cgroupfs root, cgroup of tsm if empty
+    pub path: String,
+    // child cgroup name patterns
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub procfs_root: String,
+    pub cgroupfs_root: String,
+}
+
+impl Default for CgroupsTileOptions {
+    fn default() -> Self {
+        CgroupsTileOptions {
+            path: String::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            procfs_root: "/proc".to_string(),
+            cgroupfs_root: "/sys/fs/cgroup".to_string(),
+        }
+    }
+}
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ContainersTileOptions {
+    // docker or podman API socket, detected if empty
+    pub socket: String,
+    // list stopped containers too
+    pub all: bool,
+    // seconds between engine requests
+    pub interval: u64,
+}
+
+impl Default for ContainersTileOptions {
+    fn default() -> Self {
+        ContainersTileOptions {
+            socket: String::new(),
+            all: false,
+            interval: 2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct NumaTileOptions {
+    // show gpus attached to each node
+    pub gpus: bool,
+    pub procfs_root: String,
+    pub sysfs_root: String,
+}
+
+impl Default for NumaTileOptions {
+    fn default() -> Self {
+        NumaTileOptions {
+            gpus: true,
+            procfs_root: "/proc".to_string(),
+            sysfs_root: "/sys".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SocketsSort {
+    // tcp and udp connections, most first
+    #[default]
+    Connections,
+    Pid,
+    Name,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SocketsTileOptions {
+    // process name patterns, only for sockets with a visible owner
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // TIME_WAIT sockets and sockets of processes we can't read, shown as "?"
+    pub show_unowned: bool,
+    pub sort_by: SocketsSort,
+    // seconds between scans of /proc/<pid>/fd
+    pub interval: u64,
+    pub procfs_root: String,
+}
+
+impl Default for SocketsTileOptions {
+    fn default() -> Self {
+        SocketsTileOptions {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            show_unowned: true,
+            sort_by: SocketsSort::Connections,
+            interval: 2,
+            procfs_root: "/proc".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct InterruptsTileOptions {
+    // number of busiest sources shown as columns
+    pub top: usize,
+    pub softirqs: bool,
+    pub group_by: CpuGrouping,
+    pub procfs_root: String,
+    pub sysfs_root: String,
+}
+
+impl Default for InterruptsTileOptions {
+    fn default() -> Self {
+        InterruptsTileOptions {
+            top: 8,
+            softirqs: true,
+            group_by: CpuGrouping::None,
+            procfs_root: "/proc".to_string(),
+            sysfs_root: "/sys".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PowerTileOptions {
+    // add power of nvidia gpus from NVML
+    pub gpus: bool,
+    pub sysfs_root: String,
+}
+
+impl Default for PowerTileOptions {
+    fn default() -> Self {
+        PowerTileOptions {
+            gpus: true,
+            sysfs_root: "/sys".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandFormat {
+    // stdout as is
+    #[default]
+    Text,
+    // "42.5", drawn as a bar when max is set
+    Number,
+    // "42" or "42%"
+    Percent,
+    // "12/50"
+    Ratio,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CommandTileOptions {
+    // run with `sh -c`
+    pub command: String,
+    // seconds between runs and before the command is killed
+    pub interval: u64,
+    pub timeout: u64,
+    pub format: CommandFormat,
+    pub max: Option<f64>,
+    // drawn before the bar and after the value
+    pub label: String,
+    pub unit: String,
+    pub history: bool,
+}
+
+impl Default for CommandTileOptions {
+    fn default() -> Self {
+        CommandTileOptions {
+            command: String::new(),
+            interval: 5,
+            timeout: 5,
+            format: CommandFormat::Text,
+            max: None,
+            label: String::new(),
+            unit: String::new(),
+            history: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PluginTileOptions {
+    // long-running process speaking the JSON lines protocol, run with `sh -c`
+    pub command: String,
+    // seconds between sample requests
+    pub interval: u64,
+}
+
+impl Default for PluginTileOptions {
+    fn default() -> Self {
+        PluginTileOptions {
+            command: String::new(),
+            interval: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrometheusDisplay {
+    #[default]
+    Number,
+    // drawn against max
+    Bar,
+    // sparkline scaled to max or to the highest value in history
+    Graph,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PrometheusSeries {
+    pub metric: String,
+    // PromQL style matchers: code="200", method!="GET", path=~"/api/.*"
+    pub labels: Vec<String>,
+    pub display: PrometheusDisplay,
+    // replaces the metric name in the tile
+    pub label: String,
+    pub unit: String,
+    pub max: Option<f64>,
+}
+
+impl Default for PrometheusSeries {
+    fn default() -> Self {
+        PrometheusSeries {
+            metric: String::new(),
+            labels: Vec::new(),
+            display: PrometheusDisplay::Number,
+            label: String::new(),
+            unit: String::new(),
+            max: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PrometheusTileOptions {
+    // http endpoint serving the text exposition format
+    pub url: String,
+    // seconds between scrapes and before a scrape is abandoned
+    pub interval: u64,
+    pub timeout: u64,
+    pub series: Vec<PrometheusSeries>,
+}
+
+impl Default for PrometheusTileOptions {
+    fn default() -> Self {
+        PrometheusTileOptions {
+            url: "http://localhost:9100/metrics".to_string(),
+            interval: 5,
+            timeout: 2,
+            series: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LogHighlight {
+    // regex, matched part of the line is colored
+    pub pattern: String,
+    // crossterm color name: red, dark_red, yellow, green, cyan, grey, ...
+    pub color: String,
+}
+
+impl Default for LogHighlight {
+    fn default() -> Self {
+        LogHighlight {
+            pattern: String::new(),
+            color: "red".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LogTileOptions {
+    // followed across rotation and truncation
+    pub files: Vec<String>,
+    // long-running command like `journalctl -f`, run with `sh -c`
+    pub command: String,
+    // lines kept for the tile
+    pub lines: usize,
+    // regex, other lines are dropped
+    pub filter: String,
+    pub highlight: Vec<LogHighlight>,
+}
+
+impl Default for LogTileOptions {
+    fn default() -> Self {
+        LogTileOptions {
+            files: Vec::new(),
+            command: String::new(),
+            lines: 100,
+            filter: String::new(),
+            highlight: vec![
+                LogHighlight {
+                    pattern: "(?i)error|fatal|panic".to_string(),
+                    color: "red".to_string(),
+                },
+                LogHighlight {
+                    pattern: "(?i)warn(ing)?".to_string(),
+                    color: "yellow".to_string(),
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct EventsTileOptions {
+    // events kept for the tile
+    pub max_events: usize,
+    // seconds the newest event stays in the status line
+    pub banner: u64,
+    // kernel log with OOM victims and machine check messages
+    pub kmsg_path: String,
+    pub procfs_root: String,
+    pub sysfs_root: String,
+}
+
+impl Default for EventsTileOptions {
+    fn default() -> Self {
+        EventsTileOptions {
+            max_events: 50,
+            banner: 30,
+            kmsg_path: "/dev/kmsg".to_string(),
+            procfs_root: "/proc".to_string(),
+            sysfs_root: "/sys".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct WatchTileOptions {
+    // watched process, after it exits a new process with the same name is watched
+    pub pid: Option<u32>,
+    // regex matched against process name and command line, used when pid is not set
+    pub name: String,
+    pub children: bool,
+    // proportional set size from smaps_rollup, costs a page table walk per process
+    pub pss: bool,
+    pub gpus: bool,
+    pub history: bool,
+    pub procfs_root: String,
+}
+
+impl Default for WatchTileOptions {
+    fn default() -> Self {
+        WatchTileOptions {
+            pid: None,
+            name: String::new(),
+            children: true,
+            pss: true,
+            gpus: true,
+            history: true,
+            procfs_root: "/proc".to_string(),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileConfig {
@@ -35,14 +597,16 @@ impl Default for FileConfig {
                     row: 0,
                     col: 0,
                     width: 1,
-                    height: 2
+                    height: 2,
+                    options: Map::new()
                 },
                 FileDevice{
                     device_type: "gpu".to_string(),
                     row: 0, 
                     col: 1,
                     width: 2,
-                    height: 1
+                    height: 1,
+                    options: Map::new()
                 }
             ]
         }
@@ -68,6 +632,22 @@ impl FileConfig {
         }
     }
 
+    // one tile over the whole screen, for command line modes like `--watch-pid`
+    pub fn single_tile(device_type: &str, options: Map<String, Value>) -> Self {
+        FileConfig {
+            name: device_type.to_string(),
+            symbol: "|".to_string(),
+            devices: vec![FileDevice {
+                device_type: device_type.to_string(),
+                row: 0,
+                col: 0,
+                width: 1,
+                height: 1,
+                options,
+            }],
+        }
+    }
+
     fn load_config_from_file(path: &PathBuf) -> Self {
         let file_config: FileConfig = match fs::read_to_string(path) {
             Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+
+use crate::file_config::FilesystemsTileOptions;
+use crate::patterns::is_included;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+
+// all statvfs calls of an update share it, a mount that doesn't answer in time is stale
+const STATS_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Default, Clone, Copy)]
+struct FsStats {
+    total: u64,
+    used: u64,
+    available: u64,
+    inodes_total: u64,
+    inodes_used: u64,
+}
+
+pub struct FilesystemInfo {
+    pub device: String,
+    pub mount_point: String,
+    pub fs_type: String,
+    pub total: u64,
+    pub used: u64,
+    pub available: u64,
+    pub inodes_total: u64,
+    pub inodes_used: u64,
+    // seconds until filesystem is full at the current growth rate
+    pub time_to_full: Option<f64>,
+    // statvfs hangs, like on an unreachable NFS or CIFS server, the values are from the last answer
+    pub stale: bool,
+    used_samples: VecDeque<(Instant, u64)>,
+}
+
+pub struct FsInfo {
+    procfs_root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    exclude_types: Vec<String>,
+    forecast_window: Duration,
+    // statvfs of a hung mount blocks its thread until the server answers, one thread per mount at most
+    pending: HashMap<String, Receiver<Option<FsStats>>>,
+    pub filesystems: Vec<FilesystemInfo>,
+}
+
+impl FsInfo {
+    pub fn new(options: &FilesystemsTileOptions) -> Self {
+        let mut fs_info = FsInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            exclude_types: options.exclude_types.clone(),
+            forecast_window: Duration::from_secs(options.forecast_window),
+            pending: HashMap::new(),
+            filesystems: Vec::new(),
+        };
+        fs_info.update();
+        fs_info
+    }
+
+    // filled by `replay`, include and exclude patterns still apply
+    pub fn for_replay(options: &FilesystemsTileOptions) -> Self {
+        FsInfo {
+            procfs_root: PathBuf::new(),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            exclude_types: options.exclude_types.clone(),
+            forecast_window: Duration::from_secs(options.forecast_window),
+            pending: HashMap::new(),
+            filesystems: Vec::new(),
+        }
+    }
+
+    // device and type are not recorded, the forecast is not shown as it needs the time of this machine
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("fs", tile);
+        self.filesystems = sample
+            .get_instances(&collector)
+            .into_iter()
+            .filter(|mount_point| is_included(mount_point, &self.include, &self.exclude))
+            .map(|mount_point| {
+                let key = |field: &str| format!("{collector}.{mount_point}.{field}");
+                let get_number = |field: &str| sample.get_number(&key(field)).unwrap_or(0.0) as u64;
+                FilesystemInfo {
+                    device: String::new(),
+                    mount_point: mount_point.to_string(),
+                    fs_type: String::new(),
+                    total: get_number("total_bytes"),
+                    used: get_number("used_bytes"),
+                    available: get_number("available_bytes"),
+                    inodes_total: get_number("inodes_total"),
+                    inodes_used: get_number("inodes_used"),
+                    time_to_full: None,
+                    stale: sample.get_bool(&key("stale")),
+                    used_samples: VecDeque::new(),
+                }
+            })
+            .collect();
+    }
+
+    pub fn update(&mut self) {
+        let content = fs::read_to_string(self.procfs_root.join("self/mounts")).unwrap_or_default();
+        let mut mounts: Vec<(String, String, String)> = Vec::new();
+        // bind mounts show the same block device several times
+        let mut seen_devices: HashSet<String> = HashSet::new();
+        // mounts that already timed out and whose statvfs has not returned yet
+        let mut hung: HashSet<String> = HashSet::new();
+
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 3 {
+                continue;
+            }
+            let device = unescape_mount_field(fields[0]);
+            let mount_point = unescape_mount_field(fields[1]);
+            let fs_type = fields[2].to_string();
+
+            if self.exclude_types.contains(&fs_type) || !is_included(&mount_point, &self.include, &self.exclude) {
+                continue;
+            }
+            if device.starts_with('/') && !seen_devices.insert(device.clone()) {
+                continue;
+            }
+            // mounted over, statvfs sees the last one
+            if let Some(mount) = mounts.iter_mut().find(|(_, other, _)| *other == mount_point) {
+                *mount = (device, mount_point, fs_type);
+                continue;
+            }
+            // the answer of a hung mount is still awaited by its thread
+            if self.pending.contains_key(&mount_point) {
+                hung.insert(mount_point.clone());
+            } else {
+                self.pending.insert(mount_point.clone(), spawn_fs_stats(&mount_point));
+            }
+            mounts.push((device, mount_point, fs_type));
+        }
+        // threads of unmounted filesystems send to nobody
+        self.pending.retain(|mount_point, _| mounts.iter().any(|(_, other, _)| other == mount_point));
+
+        let deadline = Instant::now() + STATS_TIMEOUT;
+        let mut filesystems: Vec<FilesystemInfo> = Vec::new();
+        for (device, mount_point, fs_type) in mounts {
+            let receiver = &self.pending[&mount_point];
+            // hung mounts are shown as stale right away instead of taking the time of the others
+            let received = if hung.contains(&mount_point) {
+                receiver.try_recv().map_err(|err| match err {
+                    TryRecvError::Empty => RecvTimeoutError::Timeout,
+                    TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+                })
+            } else {
+                receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            };
+            let stats = match received {
+                Ok(stats) => {
+                    self.pending.remove(&mount_point);
+                    match stats {
+                        Some(stats) if stats.total > 0 => Some(stats),
+                        _ => continue,
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.pending.remove(&mount_point);
+                    continue;
+                }
+            };
+
+            let mut filesystem = match self.filesystems.iter().position(|filesystem| filesystem.mount_point == mount_point) {
+                Some(position) => self.filesystems.swap_remove(position),
+                None => FilesystemInfo {
+                    device: String::new(),
+                    mount_point,
+                    fs_type: String::new(),
+                    total: 0,
+                    used: 0,
+                    available: 0,
+                    inodes_total: 0,
+                    inodes_used: 0,
+                    time_to_full: None,
+                    stale: false,
+                    used_samples: VecDeque::new(),
+                },
+            };
+            filesystem.device = device;
+            filesystem.fs_type = fs_type;
+            filesystem.stale = stats.is_none();
+            if let Some(stats) = stats {
+                filesystem.update(stats, self.forecast_window);
+            }
+            filesystems.push(filesystem);
+        }
+        self.filesystems = filesystems;
+    }
+}
+
+impl FilesystemInfo {
+    fn update(&mut self, stats: FsStats, forecast_window: Duration) {
+        self.total = stats.total;
+        self.used = stats.used;
+        self.available = stats.available;
+        self.inodes_total = stats.inodes_total;
+        self.inodes_used = stats.inodes_used;
+
+        let now = Instant::now();
+        self.used_samples.push_back((now, stats.used));
+        while let Some((sample_time, _)) = self.used_samples.front() {
+            if now.duration_since(*sample_time) <= forecast_window {
+                break;
+            }
+            self.used_samples.pop_front();
+        }
+
+        self.time_to_full = match self.get_growth_rate() {
+            Some(rate) if rate > 0.0 => Some(self.available as f64 / rate),
+            _ => None,
+        };
+    }
+
+    // least squares slope of used bytes over the forecast window, bytes per second
+    fn get_growth_rate(&self) -> Option<f64> {
+        if self.used_samples.len() < 2 {
+            return None;
+        }
+        let start = self.used_samples.front()?.0;
+        let points: Vec<(f64, f64)> = self
+            .used_samples
+            .iter()
+            .map(|(time, used)| (time.duration_since(start).as_secs_f64(), *used as f64))
+            .collect();
+
+        let count = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / count;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+        let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+
+        if variance == 0.0 {
+            None
+        } else {
+            Some(covariance / variance)
+        }
+    }
+}
+
+// spaces and tabs in mount points are written as octal escapes (\040)
+fn unescape_mount_field(field: &str) -> String {
+    let mut result = String::new();
+    let mut chars = field.chars();
+    while let Some(symbol) = chars.next() {
+        if symbol == '\\' {
+            let code: String = chars.clone().take(3).collect();
+            if let Ok(value) = u8::from_str_radix(&code, 8) {
+                result.push(value as char);
+                chars.nth(2);
+                continue;
+            }
+        }
+        result.push(symbol);
+    }
+    result
+}
+
+fn spawn_fs_stats(mount_point: &str) -> Receiver<Option<FsStats>> {
+    let (sender, receiver) = mpsc::channel();
+    let mount_point = PathBuf::from(mount_point);
+    std::thread::spawn(move || {
+        sender.send(get_fs_stats(&mount_point)).ok();
+    });
+    receiver
+}
+
+#[cfg(unix)]
+fn get_fs_stats(mount_point: &Path) -> Option<FsStats> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(mount_point.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    let block_size = stat.f_frsize as u64;
+    Some(FsStats {
+        total: stat.f_blocks as u64 * block_size,
+        used: (stat.f_blocks as u64).saturating_sub(stat.f_bfree as u64) * block_size,
+        available: stat.f_bavail as u64 * block_size,
+        inodes_total: stat.f_files as u64,
+        inodes_used: (stat.f_files as u64).saturating_sub(stat.f_ffree as u64),
+    })
+}
+
+#[cfg(not(unix))]
+fn get_fs_stats(_mount_point: &Path) -> Option<FsStats> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+
+    #[test]
+    fn unescapes_octal_mount_fields() {
+        assert_eq!(unescape_mount_field("/mnt/my\\040disk"), "/mnt/my disk");
+        assert_eq!(unescape_mount_field("/mnt/tab\\011end\\"), "/mnt/tab\tend\\");
+    }
+
+    #[test]
+    fn reads_mounts_without_pseudo_filesystems_and_bind_mounts() {
+        let fixture = Fixture::new();
+        fs::create_dir_all(fixture.path().join("my disk")).unwrap();
+        fs::create_dir_all(fixture.path().join("bind")).unwrap();
+        let root = fixture.root();
+        let mounts = format!(
+            "proc /proc proc rw 0 0\n\
+             /dev/sda1 {root}/my\\040disk ext4 rw 0 0\n\
+             /dev/sda1 {root}/bind ext4 rw 0 0\n\
+             server:/export {root}/missing nfs4 rw 0 0\n\
+             tmpfs {root}/my\\040disk tmpfs rw 0 0\n"
+        );
+        fixture.write("self/mounts", &mounts);
+
+        let fs_info = FsInfo::new(&FilesystemsTileOptions {
+            procfs_root: root.clone(),
+            ..FilesystemsTileOptions::default()
+        });
+        assert_eq!(fs_info.filesystems.len(), 1);
+        let filesystem = &fs_info.filesystems[0];
+        assert_eq!(filesystem.mount_point, format!("{root}/my disk"));
+        assert_eq!((filesystem.device.as_str(), filesystem.fs_type.as_str()), ("tmpfs", "tmpfs"));
+        assert!(filesystem.total > 0 && !filesystem.stale);
+    }
+
+    #[test]
+    fn shows_hung_mounts_as_stale_without_waiting() {
+        let fixture = Fixture::new();
+        let root = fixture.root();
+        fixture.write("self/mounts", &format!("server:/export {root}/nfs nfs4 rw 0 0\n"));
+        let mut fs_info = FsInfo::for_replay(&FilesystemsTileOptions::default());
+        fs_info.procfs_root = fixture.path().to_path_buf();
+        // statvfs calls that never return
+        let (_hung_sender, hung) = mpsc::channel();
+        let (_unmounted_sender, unmounted) = mpsc::channel();
+        fs_info.pending.insert(format!("{root}/nfs"), hung);
+        fs_info.pending.insert(format!("{root}/unmounted"), unmounted);
+
+        let started = Instant::now();
+        fs_info.update();
+        assert!(started.elapsed() < STATS_TIMEOUT);
+        assert_eq!(fs_info.filesystems.len(), 1);
+        assert!(fs_info.filesystems[0].stale);
+        let pending: Vec<&String> = fs_info.pending.keys().collect();
+        assert_eq!(pending, [&format!("{root}/nfs")]);
+    }
+
+    #[test]
+    fn growth_rate_is_the_slope_of_used_bytes() {
+        let start = Instant::now();
+        let mut filesystem = FilesystemInfo {
+            device: String::new(),
+            mount_point: String::new(),
+            fs_type: String::new(),
+            total: 0,
+            used: 0,
+            available: 0,
+            inodes_total: 0,
+            inodes_used: 0,
+            time_to_full: None,
+            stale: false,
+            used_samples: VecDeque::new(),
+        };
+        assert_eq!(filesystem.get_growth_rate(), None);
+        for second in 0..5 {
+            filesystem.used_samples.push_back((start + Duration::from_secs(second), 1000 + second * 10));
+        }
+        assert!((filesystem.get_growth_rate().unwrap() - 10.0).abs() < 1e-9);
+    }
+}
@@ -1,34 +1,168 @@
+use libloading::Library;
 use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::error::nvml_try;
 use nvml_wrapper::{Device, Nvml};
+use nvml_wrapper_sys::bindings::{
+    nvmlDevice_t, nvmlGpmMetricId_t_NVML_GPM_METRIC_GRAPHICS_UTIL, nvmlGpmMetricsGet_t, nvmlGpmSample_t,
+    nvmlReturn_t, NvmlLib, NVML_GPM_METRICS_GET_VERSION,
+};
+
+use crate::file_config::GpuTileOptions;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+
+// nvml-wrapper has no MIG api, so MIG handles are taken from the raw library
+#[cfg(target_os = "windows")]
+const NVML_LIB_PATH: &str = "nvml.dll";
+#[cfg(not(target_os = "windows"))]
+const NVML_LIB_PATH: &str = "libnvidia-ml.so";
 
 pub struct GpuAll {
-    nvml: Nvml,
+    // not loaded when a recording is replayed
+    nvml: Option<Nvml>,
+    nvml_lib: Option<NvmlLib>,
+    // functions newer than the bindings are looked up here
+    nvml_library: Option<Library>,
     gpu_devices: Vec<GpuDeviceInfo>,
     pub device_count: u32,
 }
 
 impl GpuAll {
-    pub fn new() -> Self {
+    pub fn new(options: &GpuTileOptions) -> Self {
         let nvml = Nvml::init().unwrap();
-        let device_count = nvml.device_count().unwrap();
+        let nvml_lib = unsafe { NvmlLib::new(NVML_LIB_PATH) }.ok();
+        let nvml_library = unsafe { Library::new(NVML_LIB_PATH) }.ok();
+        let total_count = nvml.device_count().unwrap();
         let mut gpu_devices: Vec<GpuDeviceInfo> = Vec::new();
 
-        for i in 0..device_count {
+        for i in 0..total_count {
             let device = nvml.device_by_index(i).unwrap();
-            gpu_devices.push(GpuDeviceInfo::new(device))
+            let gpu_selected = Self::is_selected(&options.gpus, i, &device);
+
+            let mig_devices = if options.hide_mig {
+                Vec::new()
+            } else {
+                get_mig_devices(&nvml, nvml_lib.as_ref(), &device)
+            };
+
+            if gpu_selected {
+                gpu_devices.push(GpuDeviceInfo::new(i, None, device));
+            }
+            for (mig_index, mig_device) in mig_devices {
+                if gpu_selected || Self::is_selected_by_uuid(&options.gpus, &mig_device) {
+                    gpu_devices.push(GpuDeviceInfo::new(i, Some(mig_index), mig_device));
+                }
+            }
         }
+
         GpuAll {
-            nvml,
+            nvml: Some(nvml),
+            nvml_lib,
+            nvml_library,
+            device_count: gpu_devices.len() as u32,
             gpu_devices,
-            device_count,
         }
     }
+
+    // filled by `replay`, works without a driver
+    pub fn for_replay() -> Self {
+        GpuAll {
+            nvml: None,
+            nvml_lib: None,
+            nvml_library: None,
+            gpu_devices: Vec::new(),
+            device_count: 0,
+        }
+    }
+
+    // tile is the index of the gpu tile among the gpu tiles, like the recorder keys them
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("gpu", tile);
+        self.gpu_devices = (0..sample.get_count(&collector, "name"))
+            .map(|index| {
+                let get_number = |field: &str| sample.get_number(&format!("{collector}.{index}.{field}"));
+                GpuDeviceInfo {
+                    index: index as u32,
+                    // only tells that the instance is drawn under its parent
+                    mig_index: sample.get_bool(&format!("{collector}.{index}.mig")).then_some(0),
+                    gpu_info: sample.get_text(&format!("{collector}.{index}.name")).unwrap_or_default().to_string(),
+                    // not in recordings made before the exporter
+                    name: sample.get_text(&format!("{collector}.{index}.model")).unwrap_or_default().to_string(),
+                    uuid: sample.get_text(&format!("{collector}.{index}.uuid")).unwrap_or_default().to_string(),
+                    memory_used: get_number("memory_used_mb").unwrap_or(0.0),
+                    memory_total: get_number("memory_total_mb").unwrap_or(1.0),
+                    utilization_rates: get_number("utilization_percent"),
+                    temperature: get_number("temperature_celsius").map(|temperature| temperature as u32),
+                    gpm_sample: None,
+                }
+            })
+            .collect();
+        self.device_count = self.gpu_devices.len() as u32;
+    }
+
+    fn is_selected(selection: &[String], index: u32, device: &Device) -> bool {
+        if selection.is_empty() {
+            return true;
+        }
+        let bus_id = device.pci_info().map(|info| info.bus_id).unwrap_or_default();
+
+        selection.iter().any(|selector| {
+            if let Some((first, last)) = selector.split_once('-') {
+                if let (Ok(first), Ok(last)) = (first.trim().parse::<u32>(), last.trim().parse::<u32>()) {
+                    return (first..=last).contains(&index);
+                }
+            }
+            if let Ok(selected_index) = selector.trim().parse::<u32>() {
+                return selected_index == index;
+            }
+            if selector.contains(':') {
+                return pci_bus_id_matches(selector, &bus_id);
+            }
+            false
+        }) || Self::is_selected_by_uuid(selection, device)
+    }
+
+    fn is_selected_by_uuid(selection: &[String], device: &Device) -> bool {
+        match device.uuid() {
+            Ok(uuid) => selection.iter().any(|selector| selector.trim().eq_ignore_ascii_case(&uuid)),
+            Err(_err) => false,
+        }
+    }
+
     pub fn get_info(&self, device_index: u32) -> String {
-        format!(
-            "{}, T: {:>3}°C",
-            self.gpu_devices[device_index as usize].gpu_info,
-            self.gpu_devices[device_index as usize].temperature,
-        )
+        let device = &self.gpu_devices[device_index as usize];
+        match device.temperature {
+            Some(temperature) => format!("{}, T: {:>3}°C", device.gpu_info, temperature),
+            None => device.gpu_info.clone(),
+        }
+    }
+
+    // "0: NVIDIA A100-SXM4-40GB, Cap: 8.0" or "MIG 1: ..." without the temperature
+    pub fn get_title(&self, device_index: u32) -> &str {
+        &self.gpu_devices[device_index as usize].gpu_info
+    }
+
+    // "NVIDIA A100-SXM4-40GB"
+    pub fn get_name(&self, device_index: u32) -> &str {
+        &self.gpu_devices[device_index as usize].name
+    }
+
+    pub fn get_uuid(&self, device_index: u32) -> &str {
+        &self.gpu_devices[device_index as usize].uuid
+    }
+
+    // NVML index of the gpu and index of the MIG instance on it
+    pub fn get_index(&self, device_index: u32) -> (u32, Option<u32>) {
+        let device = &self.gpu_devices[device_index as usize];
+        (device.index, device.mig_index)
+    }
+
+    pub fn get_temperature(&self, device_index: u32) -> Option<u32> {
+        self.gpu_devices[device_index as usize].temperature
+    }
+
+    pub fn is_mig(&self, device_index: u32) -> bool {
+        self.gpu_devices[device_index as usize].mig_index.is_some()
     }
 
     pub fn get_memory_info(&self, device_index: u32) -> (f64, f64) {
@@ -38,50 +172,201 @@ impl GpuAll {
         )
     }
 
-    pub fn get_utilization_rate_info(&self, device_index: u32) -> f64 {
+    // MIG instances report it only through GPM, on Hopper and newer gpus, and from the second update
+    pub fn get_utilization_rate_info(&self, device_index: u32) -> Option<f64> {
         self.gpu_devices[device_index as usize].utilization_rates
     }
 
     pub fn update(&mut self) {
-        for ind in 0..self.device_count {
-            let device = self.nvml.device_by_index(ind).unwrap();
-            let _ = &mut self.gpu_devices[ind as usize].update(device);
+        let Some(nvml) = &self.nvml else {
+            return;
+        };
+        for gpu_device in &mut self.gpu_devices {
+            let device = nvml.device_by_index(gpu_device.index).unwrap();
+            match gpu_device.mig_index {
+                None => gpu_device.update(device),
+                Some(mig_index) => {
+                    // MIG instance can be destroyed while tsm is running
+                    if let Some(mig_device) =
+                        get_mig_device_by_index(nvml, self.nvml_lib.as_ref(), &device, mig_index)
+                    {
+                        let libraries = self.nvml_lib.as_ref().zip(self.nvml_library.as_ref());
+                        let utilization = libraries.and_then(|(nvml_lib, library)| {
+                            get_mig_utilization(nvml_lib, library, &device, &mig_device, &mut gpu_device.gpm_sample)
+                        });
+                        gpu_device.update(mig_device);
+                        gpu_device.utilization_rates = utilization;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for GpuAll {
+    fn drop(&mut self) {
+        let Some(free) = self.nvml_lib.as_ref().and_then(|nvml_lib| nvml_lib.nvmlGpmSampleFree.as_ref().ok()) else {
+            return;
+        };
+        for sample in self.gpu_devices.iter_mut().filter_map(|gpu_device| gpu_device.gpm_sample.take()) {
+            unsafe { free(sample) };
+        }
+    }
+}
+
+// graphics engine activity of a MIG instance between the previous sample and a new one, None without GPM support
+fn get_mig_utilization(
+    nvml_lib: &NvmlLib,
+    nvml_library: &Library,
+    device: &Device,
+    mig_device: &Device,
+    previous: &mut Option<nvmlGpmSample_t>,
+) -> Option<f64> {
+    let alloc = nvml_lib.nvmlGpmSampleAlloc.as_ref().ok()?;
+    let free = nvml_lib.nvmlGpmSampleFree.as_ref().ok()?;
+    let get_metrics = nvml_lib.nvmlGpmMetricsGet.as_ref().ok()?;
+    let get_instance_id = nvml_lib.nvmlDeviceGetGpuInstanceId.as_ref().ok()?;
+    // newer than the bindings, drivers before 525 don't have it
+    type MigSampleGet = unsafe extern "C" fn(nvmlDevice_t, u32, nvmlGpmSample_t) -> nvmlReturn_t;
+    let mig_sample_get = unsafe { nvml_library.get::<MigSampleGet>(b"nvmlGpmMigSampleGet\0") }.ok()?;
+
+    let mut instance_id = 0;
+    let mut sample: nvmlGpmSample_t = std::ptr::null_mut();
+    unsafe {
+        nvml_try(get_instance_id(mig_device.handle(), &mut instance_id)).ok()?;
+        nvml_try(alloc(&mut sample)).ok()?;
+        // fails with NotSupported on gpus before Hopper
+        if nvml_try(mig_sample_get(device.handle(), instance_id, sample)).is_err() {
+            free(sample);
+            return None;
+        }
+    }
+
+    let utilization = previous.and_then(|previous| {
+        let mut metrics: nvmlGpmMetricsGet_t = unsafe { std::mem::zeroed() };
+        metrics.version = NVML_GPM_METRICS_GET_VERSION;
+        metrics.numMetrics = 1;
+        metrics.sample1 = previous;
+        metrics.sample2 = sample;
+        metrics.metrics[0].metricId = nvmlGpmMetricId_t_NVML_GPM_METRIC_GRAPHICS_UTIL;
+        unsafe { nvml_try(get_metrics(&mut metrics)) }.ok()?;
+        nvml_try(metrics.metrics[0].nvmlReturn).ok()?;
+        Some(metrics.metrics[0].value)
+    });
+    if let Some(previous) = previous.replace(sample) {
+        unsafe { free(previous) };
+    }
+    utilization
+}
+
+fn get_mig_devices<'nvml>(
+    nvml: &'nvml Nvml,
+    nvml_lib: Option<&NvmlLib>,
+    device: &Device,
+) -> Vec<(u32, Device<'nvml>)> {
+    let get_count = match nvml_lib.map(|lib| &lib.nvmlDeviceGetMaxMigDeviceCount) {
+        Some(Ok(get_count)) => get_count,
+        _ => return Vec::new(),
+    };
+
+    let mut max_count = 0;
+    // fails with NotSupported for gpus without MIG
+    if nvml_try(unsafe { get_count(device.handle(), &mut max_count) }).is_err() {
+        return Vec::new();
+    }
+
+    (0..max_count)
+        .filter_map(|mig_index| {
+            get_mig_device_by_index(nvml, nvml_lib, device, mig_index).map(|mig_device| (mig_index, mig_device))
+        })
+        .collect()
+}
+
+fn get_mig_device_by_index<'nvml>(
+    nvml: &'nvml Nvml,
+    nvml_lib: Option<&NvmlLib>,
+    device: &Device,
+    mig_index: u32,
+) -> Option<Device<'nvml>> {
+    let get_handle = nvml_lib?.nvmlDeviceGetMigDeviceHandleByIndex.as_ref().ok()?;
+    let mut handle: nvmlDevice_t = std::ptr::null_mut();
+
+    unsafe {
+        nvml_try(get_handle(device.handle(), mig_index, &mut handle)).ok()?;
+        Some(Device::new(handle, nvml))
+    }
+}
+
+// NVML reports bus id as "00000000:3B:00.0", config may use "0000:3b:00.0" or "3b:00.0"
+fn pci_bus_id_matches(selector: &str, bus_id: &str) -> bool {
+    let split_domain = |id: &str| -> (Option<u32>, String) {
+        let id = id.trim().to_uppercase();
+        match id.matches(':').count() {
+            2 => {
+                let (domain, rest) = id.split_once(':').unwrap();
+                (u32::from_str_radix(domain, 16).ok(), rest.to_string())
+            }
+            _ => (None, id),
         }
+    };
+
+    let (selector_domain, selector_rest) = split_domain(selector);
+    let (bus_domain, bus_rest) = split_domain(bus_id);
+    match selector_domain {
+        Some(domain) => Some(domain) == bus_domain && selector_rest == bus_rest,
+        None => selector_rest == bus_rest,
     }
 }
 
 struct GpuDeviceInfo {
+    index: u32,
+    mig_index: Option<u32>,
     gpu_info: String,
+    name: String,
+    uuid: String,
     memory_used: f64,
     memory_total: f64,
-    utilization_rates: f64,
-    temperature: u32,
+    utilization_rates: Option<f64>,
+    temperature: Option<u32>,
+    // previous GPM sample of a MIG instance
+    gpm_sample: Option<nvmlGpmSample_t>,
 }
 
 impl GpuDeviceInfo {
-    fn new(device: Device) -> Self {
+    fn new(index: u32, mig_index: Option<u32>, device: Device) -> Self {
         let name: String = device.name().expect("Can't read GPU device name");
-        let capability = match device.cuda_compute_capability() {
-            Ok(compute_capability) => {
-                format!("{}.{}", compute_capability.major, compute_capability.minor)
+
+        let info = match mig_index {
+            Some(mig_index) => format!("MIG {}: {}", mig_index, name),
+            None => {
+                let capability = match device.cuda_compute_capability() {
+                    Ok(compute_capability) => {
+                        format!("{}.{}", compute_capability.major, compute_capability.minor)
+                    }
+                    Err(_err) => "".to_string(),
+                };
+                format!("{}: {}, Cap: {}", index, name, capability)
             }
-            Err(_err) => "".to_string(),
         };
 
-        let info = format!("{}, Cap: {}", name, capability);
-
         GpuDeviceInfo {
+            index,
+            mig_index,
             gpu_info: info,
+            uuid: device.uuid().unwrap_or_default(),
+            name,
             memory_total: 1.0,
             memory_used: 0.0,
-            utilization_rates: 0.0,
-            temperature: 0,
+            utilization_rates: None,
+            temperature: None,
+            gpm_sample: None,
         }
     }
 
     fn update(&mut self, device: Device) {
         self.temperature = match device.temperature(TemperatureSensor::Gpu) {
-            Ok(temperature) => temperature,
+            Ok(temperature) => Some(temperature),
+            Err(_err) if self.mig_index.is_some() => None,
             Err(_err) => panic!("Can't read temperature"),
         };
 
@@ -95,7 +380,8 @@ impl GpuDeviceInfo {
         self.memory_total = (memory_info.total / 1024 / 1024) as f64;
 
         self.utilization_rates = match device.utilization_rates() {
-            Ok(utilization_rates) => utilization_rates.gpu as f64,
+            Ok(utilization_rates) => Some(utilization_rates.gpu as f64),
+            Err(_err) if self.mig_index.is_some() => None,
             Err(_err) => panic!("{}", _err),
         };
     }
@@ -0,0 +1,26 @@
+use std::collections::VecDeque;
+
+// enough samples to fill a full width graph on wide terminals
+pub const HISTORY_LEN: usize = 256;
+
+#[derive(Default)]
+pub struct History {
+    values: VecDeque<f64>,
+}
+
+impl History {
+    pub fn push(&mut self, value: f64) {
+        if self.values.len() == HISTORY_LEN {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    pub fn values(&self) -> &VecDeque<f64> {
+        &self.values
+    }
+
+    pub fn max(&self) -> f64 {
+        self.values.iter().cloned().fold(0.0, f64::max)
+    }
+}
@@ -0,0 +1,113 @@
+// minimal HTTP/1.1 client, enough for local engine sockets and metrics endpoints
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+pub fn send_request<S: Read + Write>(mut stream: S, method: &str, host: &str, path: &str) -> Result<HttpResponse, String> {
+    let request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: tsm\r\nAccept: */*\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|err| format!("Can't send request: {err}"))?;
+
+    let mut raw = Vec::new();
+    stream
+        .read_to_end(&mut raw)
+        .map_err(|err| format!("Can't read response: {err}"))?;
+    parse_response(&raw)
+}
+
+#[cfg(unix)]
+pub fn request_unix(socket_path: &std::path::Path, method: &str, path: &str, timeout: Duration) -> Result<HttpResponse, String> {
+    let stream = std::os::unix::net::UnixStream::connect(socket_path)
+        .map_err(|err| format!("Can't connect to {}: {err}", socket_path.display()))?;
+    stream.set_read_timeout(Some(timeout)).ok();
+    stream.set_write_timeout(Some(timeout)).ok();
+
+    send_request(stream, method, "localhost", path)
+}
+
+// plain http only, like "http://10.0.0.5:9100/metrics"
+pub fn get_url(url: &str, timeout: Duration) -> Result<HttpResponse, String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Only http:// urls are supported: {url}"))?;
+    let (host, path) = match rest.find('/') {
+        Some(path_start) => rest.split_at(path_start),
+        None => (rest, "/"),
+    };
+    // "[::1]" is an ipv6 address without port
+    let address = if host.rsplit_once(':').is_some_and(|(_, port)| !port.ends_with(']')) {
+        host.to_string()
+    } else {
+        format!("{host}:80")
+    };
+
+    let socket_address = address
+        .to_socket_addrs()
+        .map_err(|err| format!("Can't resolve {host}: {err}"))?
+        .next()
+        .ok_or_else(|| format!("Can't resolve {host}"))?;
+    let stream = TcpStream::connect_timeout(&socket_address, timeout).map_err(|err| format!("Can't connect to {host}: {err}"))?;
+    stream.set_read_timeout(Some(timeout)).ok();
+    stream.set_write_timeout(Some(timeout)).ok();
+
+    send_request(stream, "GET", host, path)
+}
+
+fn parse_response(raw: &[u8]) -> Result<HttpResponse, String> {
+    let header_end = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or("Invalid HTTP response")?;
+    let headers = String::from_utf8_lossy(&raw[..header_end]);
+    let body = &raw[header_end + 4..];
+
+    // "HTTP/1.1 200 OK"
+    let status = headers
+        .lines()
+        .next()
+        .and_then(|status_line| status_line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or("Invalid HTTP status line")?;
+
+    let chunked = headers.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    let body = if chunked { decode_chunked(body)? } else { body.to_vec() };
+
+    Ok(HttpResponse {
+        status,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
+}
+
+// every chunk is "<size in hex>\r\n<data>\r\n", zero size chunk ends the body
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or("Invalid chunked body")?;
+        let size_line = String::from_utf8_lossy(&body[..line_end]);
+        // chunk extensions after ';' are ignored
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_err| "Invalid chunk size")?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+
+        let data_start = line_end + 2;
+        let data_end = (data_start + size).min(body.len());
+        decoded.extend_from_slice(&body[data_start..data_end]);
+        body = body.get(data_end + 2..).unwrap_or_default();
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::file_config::InterruptsTileOptions;
+
+// one line of /proc/interrupts or /proc/softirqs
+pub struct InterruptSource {
+    pub label: String,
+    // per second for every cpu
+    pub rates: Vec<f64>,
+    pub total: f64,
+}
+
+pub struct InterruptsInfo {
+    procfs_root: PathBuf,
+    show_softirqs: bool,
+    last_update: Instant,
+    counters: HashMap<String, Vec<u64>>,
+    // highest cpu number seen in the header plus one
+    pub cpu_count: usize,
+    // sorted by total rate, busiest first
+    pub sources: Vec<InterruptSource>,
+}
+
+impl InterruptsInfo {
+    pub fn new(options: &InterruptsTileOptions) -> Self {
+        let mut interrupts_info = InterruptsInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            show_softirqs: options.softirqs,
+            last_update: Instant::now(),
+            counters: HashMap::new(),
+            cpu_count: 0,
+            sources: Vec::new(),
+        };
+        interrupts_info.update();
+        interrupts_info
+    }
+
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        let mut lines = parse_interrupts(&fs::read_to_string(self.procfs_root.join("interrupts")).unwrap_or_default());
+        if self.show_softirqs {
+            lines.extend(parse_interrupts(&fs::read_to_string(self.procfs_root.join("softirqs")).unwrap_or_default()));
+        }
+        self.cpu_count = lines.iter().map(|(_, counters)| counters.len()).max().unwrap_or(0);
+
+        let mut sources: Vec<InterruptSource> = Vec::new();
+        let mut counters: HashMap<String, Vec<u64>> = HashMap::new();
+        for (label, current) in lines {
+            let rates: Vec<f64> = match self.counters.get(&label) {
+                Some(previous) if elapsed > 0.0 => current
+                    .iter()
+                    .enumerate()
+                    .map(|(cpu, current)| {
+                        current.saturating_sub(previous.get(cpu).copied().unwrap_or(*current)) as f64 / elapsed
+                    })
+                    .collect(),
+                _ => vec![0.0; current.len()],
+            };
+            sources.push(InterruptSource {
+                total: rates.iter().sum(),
+                label: label.clone(),
+                rates,
+            });
+            counters.insert(label, current);
+        }
+        sources.sort_by(|first, second| second.total.total_cmp(&first.total));
+        self.sources = sources;
+        self.counters = counters;
+    }
+}
+
+// header is "CPU0 CPU1 CPU3" with offline cpus missing, lines look like
+// " 28:   0   12  PCI-MSIX-0000:00:01.0   0-edge      virtio0-config" or "NET_RX:   4595   17".
+// Counters are returned by cpu number
+fn parse_interrupts(content: &str) -> Vec<(String, Vec<u64>)> {
+    let mut lines = content.lines();
+    let cpus: Vec<usize> = lines
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|cpu| cpu.strip_prefix("CPU")?.parse().ok())
+        .collect();
+    let cpu_count = cpus.iter().max().map(|cpu| cpu + 1).unwrap_or(0);
+
+    let mut interrupts = Vec::new();
+    for line in lines {
+        let Some((name, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.trim();
+        let mut fields = rest.split_whitespace().peekable();
+
+        let mut counters = vec![0; cpu_count];
+        for cpu in &cpus {
+            match fields.peek().and_then(|field| field.parse::<u64>().ok()) {
+                Some(value) => {
+                    counters[*cpu] = value;
+                    fields.next();
+                }
+                // lines like "ERR: 0" have a single counter
+                None => break,
+            }
+        }
+
+        // numbered irqs are labeled with their device, the last word of the description
+        let label = match (name.parse::<u32>(), fields.last()) {
+            (Ok(_), Some(device)) => format!("{name}:{device}"),
+            _ => name.to_string(),
+        };
+        interrupts.push((label, counters));
+    }
+    interrupts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::time::Duration;
+
+    // cpu2 is offline
+    fn write_interrupts(fixture: &Fixture, eth0: [u64; 3], net_rx: [u64; 3]) {
+        let [eth0_0, eth0_1, eth0_3] = eth0;
+        let [net_rx_0, net_rx_1, net_rx_3] = net_rx;
+        fixture
+            .write(
+                "interrupts",
+                &format!(
+                    "           CPU0       CPU1       CPU3\n\
+                     \x20 0:         10          0          0  IR-IO-APIC    2-edge      timer\n\
+                     \x2028:   {eth0_0:8} {eth0_1:10} {eth0_3:10}  PCI-MSIX-0000:00:03.0   0-edge      eth0\n\
+                     NMI:          1          2          3   Non-maskable interrupts\n\
+                     ERR:          0\n"
+                ),
+            )
+            .write(
+                "softirqs",
+                &format!(
+                    "                    CPU0       CPU1       CPU3\n\
+                     \x20         HI:          0          0          0\n\
+                     \x20     NET_RX: {net_rx_0:10} {net_rx_1:10} {net_rx_3:10}\n"
+                ),
+            );
+    }
+
+    fn get_sources(interrupts_info: &InterruptsInfo) -> Vec<(&str, Vec<f64>)> {
+        interrupts_info.sources.iter().map(|source| (source.label.as_str(), source.rates.clone())).collect()
+    }
+
+    #[test]
+    fn parses_counters_by_cpu_number() {
+        let interrupts = parse_interrupts(
+            "           CPU0       CPU2\n  9:   5   7   IO-APIC   9-fasteoi   acpi\nMIS:          3\nbad line\n",
+        );
+        assert_eq!(interrupts, [("9:acpi".to_string(), vec![5, 0, 7]), ("MIS".to_string(), vec![3, 0, 0])]);
+        assert!(parse_interrupts("").is_empty());
+    }
+
+    #[test]
+    fn turns_counters_into_rates_per_cpu() {
+        let fixture = Fixture::new();
+        write_interrupts(&fixture, [100, 200, 300], [4595, 17, 3]);
+        let mut interrupts_info = InterruptsInfo::new(&InterruptsTileOptions {
+            procfs_root: fixture.root(),
+            ..InterruptsTileOptions::default()
+        });
+        assert_eq!(interrupts_info.cpu_count, 4);
+        assert!(interrupts_info.sources.iter().all(|source| source.total == 0.0));
+
+        // counters going back, like after a cpu hotplug, give no rate
+        write_interrupts(&fixture, [150, 300, 400], [4595, 57, 1]);
+        interrupts_info.last_update -= Duration::from_secs(2);
+        interrupts_info.update();
+
+        let sources = get_sources(&interrupts_info);
+        assert_eq!(sources.len(), 6);
+        let round = |rates: &[f64]| rates.iter().map(|rate| rate.round()).collect::<Vec<f64>>();
+        assert_eq!((sources[0].0, round(&sources[0].1)), ("28:eth0", vec![25.0, 50.0, 0.0, 50.0]));
+        assert_eq!((sources[1].0, round(&sources[1].1)), ("NET_RX", vec![0.0, 20.0, 0.0, 0.0]));
+        assert!(sources[2..].iter().all(|(_, rates)| rates.iter().all(|rate| *rate == 0.0)));
+        assert!((interrupts_info.sources[0].total - 125.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn skips_softirqs_when_disabled() {
+        let fixture = Fixture::new();
+        write_interrupts(&fixture, [0, 0, 0], [0, 0, 0]);
+        let interrupts_info = InterruptsInfo::new(&InterruptsTileOptions {
+            softirqs: false,
+            procfs_root: fixture.root(),
+            ..InterruptsTileOptions::default()
+        });
+        let mut labels: Vec<&str> = interrupts_info.sources.iter().map(|source| source.label.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, ["0:timer", "28:eth0", "ERR", "NMI"]);
+    }
+}
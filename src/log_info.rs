@@ -0,0 +1,404 @@
+use std::collections::VecDeque;
+use std::fs::{File, Metadata};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::process::{exit, Child, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use crossterm::style::Color;
+use regex::Regex;
+
+use crate::command_info::{kill_command, shell_command};
+use crate::file_config::LogTileOptions;
+
+// end of a file read when tsm starts, enough to fill the tile
+const INITIAL_TAIL_BYTES: u64 = 64 * 1024;
+// a burst bigger than this between updates is skipped to its end
+const MAX_READ_BYTES: u64 = 1024 * 1024;
+// bytes before the read position compared to notice a file rewritten between updates
+const CHECKED_BYTES: usize = 64;
+const COMMAND_RESTART_DELAY: Duration = Duration::from_secs(5);
+
+struct FollowedFile {
+    path: PathBuf,
+    // shown before lines when several files are followed
+    name: String,
+    file: Option<File>,
+    // device and inode of the open file, a new one at the same path means rotation
+    file_id: (u64, u64),
+    position: u64,
+    // last bytes read, right before position
+    last_bytes: Vec<u8>,
+    // tail reading starts in the middle of a line
+    skip_partial: bool,
+    // text after the last newline, completed by the next read
+    partial: String,
+    opened_before: bool,
+}
+
+impl FollowedFile {
+    fn new(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        FollowedFile {
+            name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            path,
+            file: None,
+            file_id: (0, 0),
+            position: 0,
+            last_bytes: Vec::new(),
+            skip_partial: false,
+            partial: String::new(),
+            opened_before: false,
+        }
+    }
+
+    fn update(&mut self, lines: &mut Vec<String>) -> Result<(), String> {
+        let metadata = std::fs::metadata(&self.path);
+        if let (Ok(metadata), Some(_)) = (&metadata, &self.file) {
+            if get_file_id(metadata) != self.file_id {
+                // rotated, the rest of the old file comes before the new one
+                self.read_lines(lines);
+                self.file = None;
+            } else if metadata.len() < self.position || self.is_rewritten() {
+                // truncated like `> file` or copytruncate
+                self.position = 0;
+                self.last_bytes.clear();
+                self.partial.clear();
+            } else if metadata.len() - self.position > MAX_READ_BYTES {
+                self.position = metadata.len() - INITIAL_TAIL_BYTES;
+                self.last_bytes.clear();
+                self.skip_partial = true;
+                self.partial.clear();
+            }
+        }
+
+        // a removed file is read through the open handle until a new one appears
+        if self.file.is_none() {
+            let metadata = metadata.map_err(|err| format!("{}: {err}", self.path.display()))?;
+            let file = File::open(&self.path).map_err(|err| format!("{}: {err}", self.path.display()))?;
+            // files created after start, like the new file after rotation, are read whole
+            self.position = if self.opened_before { 0 } else { metadata.len().saturating_sub(INITIAL_TAIL_BYTES) };
+            self.skip_partial = self.position > 0;
+            self.last_bytes.clear();
+            self.partial.clear();
+            self.file_id = get_file_id(&metadata);
+            self.file = Some(file);
+            self.opened_before = true;
+        }
+        self.read_lines(lines);
+        Ok(())
+    }
+
+    // truncated and written again past the old size between two updates
+    fn is_rewritten(&mut self) -> bool {
+        let Some(file) = &mut self.file else {
+            return false;
+        };
+        let mut current = vec![0; self.last_bytes.len()];
+        file.seek(SeekFrom::Start(self.position - self.last_bytes.len() as u64)).is_err()
+            || file.read_exact(&mut current).is_err()
+            || current != self.last_bytes
+    }
+
+    fn read_lines(&mut self, lines: &mut Vec<String>) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let mut buffer = Vec::new();
+        if file.seek(SeekFrom::Start(self.position)).is_err() || file.read_to_end(&mut buffer).is_err() {
+            return;
+        }
+        self.position += buffer.len() as u64;
+        self.last_bytes.extend_from_slice(&buffer);
+        self.last_bytes.drain(..self.last_bytes.len().saturating_sub(CHECKED_BYTES));
+
+        let text = std::mem::take(&mut self.partial) + &String::from_utf8_lossy(&buffer);
+        let mut parts: Vec<&str> = text.split('\n').collect();
+        self.partial = parts.pop().unwrap_or_default().to_string();
+        if self.skip_partial && !parts.is_empty() {
+            parts.remove(0);
+            self.skip_partial = false;
+        }
+        lines.extend(parts.iter().map(|line| line.to_string()));
+    }
+}
+
+#[cfg(unix)]
+fn get_file_id(metadata: &Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+// without inodes rotation is noticed as truncation
+#[cfg(not(unix))]
+fn get_file_id(_metadata: &Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+struct FollowedCommand {
+    child: Child,
+    lines: Receiver<String>,
+}
+
+pub struct LogInfo {
+    files: Vec<FollowedFile>,
+    command: String,
+    process: Option<FollowedCommand>,
+    restart_at: Option<Instant>,
+    filter: Option<Regex>,
+    max_lines: usize,
+    pub lines: VecDeque<String>,
+    // matched parts of lines are drawn with the color
+    pub highlights: Vec<(Regex, Color)>,
+    pub error: Option<String>,
+}
+
+impl LogInfo {
+    pub fn new(options: &LogTileOptions) -> Self {
+        let filter = (!options.filter.is_empty()).then(|| {
+            Regex::new(&options.filter).unwrap_or_else(|err| {
+                eprintln!("ERROR: Invalid log filter \"{}\": {err}", options.filter);
+                exit(1)
+            })
+        });
+
+        let highlights = options
+            .highlight
+            .iter()
+            .map(|highlight| {
+                let regex = Regex::new(&highlight.pattern).unwrap_or_else(|err| {
+                    eprintln!("ERROR: Invalid highlight pattern \"{}\": {err}", highlight.pattern);
+                    exit(1)
+                });
+                let color = Color::try_from(highlight.color.as_str()).unwrap_or_else(|_| {
+                    eprintln!("ERROR: Unknown highlight color \"{}\"", highlight.color);
+                    exit(1)
+                });
+                (regex, color)
+            })
+            .collect();
+
+        let mut log_info = LogInfo {
+            files: options.files.iter().map(|path| FollowedFile::new(path)).collect(),
+            command: options.command.clone(),
+            process: None,
+            restart_at: (!options.command.is_empty()).then(Instant::now),
+            filter,
+            max_lines: options.lines.max(1),
+            lines: VecDeque::new(),
+            highlights,
+            error: None,
+        };
+        log_info.update();
+        log_info
+    }
+
+    pub fn update(&mut self) {
+        if self.files.is_empty() && self.command.is_empty() {
+            self.error = Some("No files or command configured".to_string());
+            return;
+        }
+
+        let mut errors: Vec<String> = Vec::new();
+        let show_names = self.files.len() > 1;
+        let mut new_lines: Vec<String> = Vec::new();
+        for followed in &mut self.files {
+            let mut file_lines = Vec::new();
+            if let Err(err) = followed.update(&mut file_lines) {
+                errors.push(err);
+            }
+            new_lines.extend(file_lines.iter().map(|line| {
+                if show_names {
+                    format!("{}: {}", followed.name, clean_line(line))
+                } else {
+                    clean_line(line)
+                }
+            }));
+        }
+
+        if let Err(err) = self.update_command(&mut new_lines) {
+            errors.push(err);
+        }
+        self.error = (!errors.is_empty()).then(|| errors.join(", "));
+
+        for line in new_lines {
+            if self.filter.as_ref().is_some_and(|filter| !filter.is_match(&line)) {
+                continue;
+            }
+            if self.lines.len() == self.max_lines {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line);
+        }
+    }
+
+    fn update_command(&mut self, lines: &mut Vec<String>) -> Result<(), String> {
+        if self.command.is_empty() {
+            return Ok(());
+        }
+        if self.process.is_none() {
+            if self.restart_at.is_some_and(|restart_at| Instant::now() >= restart_at) {
+                self.restart_at = None;
+                match self.spawn() {
+                    Ok(process) => self.process = Some(process),
+                    Err(err) => {
+                        self.restart_at = Some(Instant::now() + COMMAND_RESTART_DELAY);
+                        return Err(format!("Can't run command: {err}"));
+                    }
+                }
+            } else {
+                return Err(format!("Command exited, restarting in {}s", COMMAND_RESTART_DELAY.as_secs()));
+            }
+        }
+
+        let Some(process) = &mut self.process else {
+            return Ok(());
+        };
+        lines.extend(process.lines.try_iter().map(|line| clean_line(&line)));
+        match process.child.try_wait() {
+            Ok(None) => Ok(()),
+            Ok(Some(status)) => {
+                self.process = None;
+                self.restart_at = Some(Instant::now() + COMMAND_RESTART_DELAY);
+                let code = status.code().map(|code| code.to_string()).unwrap_or_else(|| "signal".to_string());
+                Err(format!("Command exited with code {code}"))
+            }
+            Err(err) => Err(format!("Can't wait for command: {err}")),
+        }
+    }
+
+    fn spawn(&self) -> std::io::Result<FollowedCommand> {
+        // stderr would draw over the tiles, `2>&1` in the command keeps it
+        let mut child = shell_command(&self.command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(FollowedCommand { child, lines })
+    }
+}
+
+impl Drop for LogInfo {
+    fn drop(&mut self) {
+        if let Some(process) = &mut self.process {
+            kill_command(&mut process.child);
+        }
+    }
+}
+
+// colored output of tools like journalctl and tabs would break the tile layout
+fn clean_line(line: &str) -> String {
+    let mut cleaned = String::new();
+    let mut chars = line.chars();
+    while let Some(symbol) = chars.next() {
+        match symbol {
+            '\x1b' => {
+                // CSI sequence ends with a byte in @..~
+                if chars.next() == Some('[') {
+                    for symbol in chars.by_ref() {
+                        if ('@'..='~').contains(&symbol) {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\t' => cleaned.push_str("    "),
+            symbol if symbol.is_control() => {}
+            symbol => cleaned.push(symbol),
+        }
+    }
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::Path;
+
+    fn follow(files: &[PathBuf], filter: &str, lines: usize) -> LogInfo {
+        LogInfo::new(&LogTileOptions {
+            files: files.iter().map(|path| path.to_string_lossy().into_owned()).collect(),
+            filter: filter.to_string(),
+            lines,
+            ..LogTileOptions::default()
+        })
+    }
+
+    fn append(path: &Path, text: &str) {
+        OpenOptions::new().append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    fn take_lines(log_info: &mut LogInfo) -> Vec<String> {
+        log_info.update();
+        log_info.lines.drain(..).collect()
+    }
+
+    #[test]
+    fn follows_a_file_across_rotation_and_truncation() {
+        let fixture = Fixture::new();
+        fixture.write("app.log", "old 1\nold 2\n");
+        let path = fixture.path().join("app.log");
+        let mut log_info = follow(std::slice::from_ref(&path), "", 100);
+        assert_eq!(log_info.lines, ["old 1", "old 2"]);
+        log_info.lines.clear();
+
+        // a line is shown once its newline is written
+        append(&path, "new 3\npart");
+        assert_eq!(take_lines(&mut log_info), ["new 3"]);
+        append(&path, "ial\n");
+        assert_eq!(take_lines(&mut log_info), ["partial"]);
+
+        // the rest of the rotated file comes before the new file
+        std::fs::rename(&path, fixture.path().join("app.log.1")).unwrap();
+        append(&fixture.path().join("app.log.1"), "last old\n");
+        fixture.write("app.log", "rotated 1\n");
+        assert_eq!(take_lines(&mut log_info), ["last old", "rotated 1"]);
+
+        // truncated like `> app.log`
+        fixture.write("app.log", "t\n");
+        assert_eq!(take_lines(&mut log_info), ["t"]);
+        // truncated and written past the old size between two updates
+        fixture.write("app.log", "rewritten\n");
+        assert_eq!(take_lines(&mut log_info), ["rewritten"]);
+        assert_eq!(log_info.error, None);
+    }
+
+    #[test]
+    fn starts_big_files_at_their_tail() {
+        let fixture = Fixture::new();
+        let line = "x".repeat(99) + "\n";
+        fixture.write("big.log", &(line.repeat(1000) + "last\n"));
+        let log_info = follow(&[fixture.path().join("big.log")], "", 1000);
+        // the first line of the tail is cut and skipped
+        assert_eq!(log_info.lines.len(), (INITIAL_TAIL_BYTES as usize - 5) / 100 + 1);
+        assert_eq!(log_info.lines.back().map(String::as_str), Some("last"));
+    }
+
+    #[test]
+    fn filters_and_cleans_lines_of_several_files() {
+        let fixture = Fixture::new();
+        fixture.write("a.log", "keep \x1b[31mred\x1b[0m\tline\ndrop\n").write("b.log", "keep b\n");
+        let paths = [fixture.path().join("a.log"), fixture.path().join("b.log"), fixture.path().join("missing.log")];
+        let mut log_info = follow(&paths, "keep", 1);
+
+        // only the newest line fits
+        assert_eq!(log_info.lines, ["b.log: keep b"]);
+        assert!(log_info.error.as_deref().is_some_and(|err| err.contains("missing.log")));
+        append(&paths[0], "keep again\n");
+        assert_eq!(take_lines(&mut log_info), ["a.log: keep again"]);
+        assert_eq!(clean_line("keep \x1b[31mred\x1b[0m\tline\x07"), "keep red    line");
+    }
+}
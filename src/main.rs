@@ -3,8 +3,18 @@ use::std::env;
 mod app_config;
 mod file_config;
 use crate::app_config::{AppConfig, DeviceTile};
+use crate::file_config::{
+    BatteryTileOptions, CgroupsTileOptions, CommandTileOptions, ContainersTileOptions, CpuTileOptions,
+    DiskIoTileOptions, EventsTileOptions, FileConfig, FilesystemsTileOptions, GpuTileOptions, InterruptsTileOptions,
+    LogTileOptions, NetworkTileOptions, NumaTileOptions, PluginTileOptions, PowerTileOptions, PrometheusTileOptions,
+    SensorsTileOptions, SocketsTileOptions, SystemTileOptions, WatchTileOptions,
+};
 
 use std::io::{stdout, Write};
+use std::path::PathBuf;
+use std::process::exit;
+
+use serde_json::{Map, Value};
 
 use crossterm::event::{poll, read, Event, KeyEvent, KeyCode, KeyModifiers};
 use crossterm::{execute, cursor};
@@ -14,11 +24,41 @@ use crossterm::terminal::{
 
 use std::time::Duration;
 
+mod battery_info;
+mod cgroup_info;
+mod command_info;
+mod container_info;
 mod cpu_info;
+mod cpu_topology;
+mod disk_info;
+mod events_info;
+mod exporter;
+mod fs_info;
 mod gpu_info;
+mod history;
+mod http;
+mod interrupts_info;
+mod log_info;
+mod net_info;
+mod numa_info;
+mod patterns;
+mod plugin_info;
+mod power_info;
+mod prometheus_info;
+mod recorder;
+mod replay;
+mod run_mode;
+mod sensors_info;
+mod sockets_info;
+mod sysfs;
+mod system_info;
+#[cfg(test)]
+mod test_fixture;
 mod ui;
+mod watch_info;
 
-use crate::ui::LayoutBbox;
+use recorder::{RecordOptions, Recorder, Sources};
+use replay::Replay;
 use ui::{LayoutType, Ui};
 
 
@@ -26,67 +66,206 @@ fn print_usage_message() {
     println!("Usage: ");
     println!("tsm  [Options]");
     println!("Options:");
-    println!("  <config_name>   Read config with the given name. Config must be placed in ~/.config/tsm/<config_name>.json");
-    println!("  -h, --help      Print help message")
+    println!("  <config_name>          Read config with the given name. Config must be placed in ~/.config/tsm/<config_name>.json");
+    println!("  --watch-pid <pid>      Watch one process and its children");
+    println!("  --watch-name <regex>   Watch processes with matching name or command line and their children");
+    println!("  --record <file>        Record samples of the config collectors to a .csv file or a .jsonl file");
+    println!("  --record-interval <s>  Seconds between recorded samples (default 1)");
+    println!("  --record-rotate <when> Start a new file after a size like 100M or a time like 1h");
+    println!("  --headless             Record without drawing tiles, for cron and daemons");
+    println!("  --serve <address>      Serve metrics of the config collectors for Prometheus on <address>/metrics, like :9101");
+    println!("  --replay <file>        Show a recording in the tiles of the recorded collectors");
+    println!("  run -- <command>       Run a command and print its cpu, memory, I/O and gpu usage, see tsm run -h");
+    println!("  -h, --help             Print help message")
 }
 
 
 fn main() {
     //read and generate app config
     let args: Vec<String> = env::args().collect();
-  
-    let (screen_w, screen_h) = terminal::size().expect(
-        "Can't get terminal size"
-    );
 
+    // profiling a command doesn't need the terminal unless --tui is given
+    if args.get(1).is_some_and(|arg| arg == "run") {
+        if args.get(2).is_some_and(|arg| arg == "-h" || arg == "--help") {
+            run_mode::print_run_usage_message();
+            return;
+        }
+        exit(run_mode::run(&args[2..]));
+    }
+  
+    let mut config_name = String::new();
+    let mut watch_options: Option<Map<String, Value>> = None;
+    let mut record_path: Option<PathBuf> = None;
+    let mut record_interval = Duration::from_secs(1);
+    let mut record_rotation = None;
+    let mut headless = false;
+    let mut replay_path: Option<PathBuf> = None;
+    let mut serve_address: Option<String> = None;
 
-    let mut config = match args.len() - 1 {
-        0 => AppConfig::new(String::new(), screen_w, screen_h),
-        1 => match args[1].as_str() {
-            "-h" => {
+    let mut options = args.iter().skip(1);
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "-h" | "--help" => {
                 print_usage_message();
                 return;
+            }
+            "--watch-pid" => match options.next().map(|pid| pid.parse::<u32>()) {
+                Some(Ok(pid)) => watch_options = Some(Map::from_iter([("pid".to_string(), Value::from(pid))])),
+                _ => exit_with_usage("--watch-pid needs a pid"),
             },
-            "--help" => {
-                print_usage_message();
-                return;
+            "--watch-name" => match options.next() {
+                Some(name) => watch_options = Some(Map::from_iter([("name".to_string(), Value::from(name.clone()))])),
+                None => exit_with_usage("--watch-name needs a regex"),
+            },
+            "--record" => match options.next() {
+                Some(path) => record_path = Some(PathBuf::from(path)),
+                None => exit_with_usage("--record needs a file"),
+            },
+            "--record-interval" => match options.next().and_then(|interval| interval.parse::<f64>().ok()) {
+                Some(interval) if interval > 0.0 => record_interval = Duration::from_secs_f64(interval),
+                _ => exit_with_usage("--record-interval needs a number of seconds"),
+            },
+            "--record-rotate" => match options.next().map(|rotation| recorder::parse_rotation(rotation)) {
+                Some(Ok(rotation)) => record_rotation = Some(rotation),
+                Some(Err(err)) => exit_with_usage(&err),
+                None => exit_with_usage("--record-rotate needs a size or a time"),
+            },
+            "--headless" => headless = true,
+            "--serve" => match options.next() {
+                Some(address) => serve_address = Some(address.clone()),
+                None => exit_with_usage("--serve needs an address like :9101"),
+            },
+            "--replay" => match options.next() {
+                Some(path) => replay_path = Some(PathBuf::from(path)),
+                None => exit_with_usage("--replay needs a file"),
             },
-            _ =>  AppConfig::new(args[1].clone(), screen_w, screen_h),
- 
+            other if other.starts_with('-') => exit_with_usage(&format!("Unknown option \"{other}\"")),
+            name if config_name.is_empty() => config_name = name.to_string(),
+            _ => exit_with_usage("Invalid number of arguments"),
+        }
+    }
 
-        },
-                  _ => { 
-            eprintln!("ERROR: Invalid number of arguments");
-            print_usage_message();
-            return;
+    if replay_path.is_some() && (record_path.is_some() || headless || watch_options.is_some() || serve_address.is_some()) {
+        exit_with_usage("--replay can't be used with --record, --headless, --serve or --watch options");
+    }
+    let mut replay = replay_path.map(|path| {
+        Replay::open(&path).unwrap_or_else(|err| {
+            eprintln!("ERROR: {err}");
+            exit(1)
+        })
+    });
+
+    let file_config = match (watch_options, &mut replay) {
+        (Some(_), _) if !config_name.is_empty() => exit_with_usage("--watch-pid and --watch-name don't take a config"),
+        (Some(options), _) => FileConfig::single_tile("watch", options),
+        (None, Some(replay)) if config_name.is_empty() => replay.get_file_config(),
+        (None, Some(replay)) => {
+            let mut file_config = FileConfig::new(config_name);
+            replay.skip_tiles(&mut file_config);
+            file_config
         }
+        (None, None) => FileConfig::new(config_name),
     };
+
+    // collectors of the config tiles are recorded, with or without the tiles drawn
+    let mut recorder = record_path.map(|path| {
+        let options = RecordOptions {
+            path,
+            interval: record_interval,
+            rotation: record_rotation,
+        };
+        // the tiles sample their collectors already, only a headless recorder needs its own
+        Recorder::new(options, &file_config.devices, !headless && serve_address.is_none())
+    });
+    // recording goes on between scrapes
+    if let Some(address) = serve_address {
+        let served = exporter::serve(&address, &file_config.devices, || {
+            if let Some(recorder) = &mut recorder {
+                recorder.sample_if_due(Sources::default());
+            }
+        });
+        if let Err(err) = served {
+            eprintln!("ERROR: {err}");
+            exit(1);
+        }
+        return;
+    }
+    if headless {
+        match &mut recorder {
+            Some(recorder) => recorder.run_headless(),
+            None => exit_with_usage("--headless needs --record"),
+        }
+        return;
+    }
+
+    let (screen_w, screen_h) = terminal::size().expect(
+        "Can't get terminal size"
+    );
+    let config = AppConfig::from_file_config(file_config, screen_w, screen_h);
     println!("Config: {} loaded!", config.name);
 
+    run_ui(config, screen_w, screen_h, replay, |ui| {
+        if let Some(recorder) = &mut recorder {
+            recorder.sample_if_due(ui.get_record_sources());
+            ui.status = Some(recorder.get_status());
+        }
+        true
+    });
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("ERROR: {message}");
+    print_usage_message();
+    exit(1)
+}
+
+// draws tiles of the config until q is pressed or on_tick returns false
+fn run_ui(
+    mut config: AppConfig,
+    screen_w: u16,
+    screen_h: u16,
+    replay: Option<Replay>,
+    mut on_tick: impl FnMut(&mut Ui) -> bool,
+) {
     // start main loop    
     let mut stdout = stdout();
 
     let mut ui = Ui::new(screen_w, screen_h);
-    ui.create_layout(
-        config.tiles[0].name.clone(),
-        LayoutBbox {
-            top: config.tiles[0].row,
-            left: config.tiles[0].col,
-            width: config.tiles[0].width,
-            height: config.tiles[0].height,
-        },
-        LayoutType::Cpu,
-    );
-    ui.create_layout(
-        config.tiles[1].name.clone(),
-        LayoutBbox {
-            top: config.tiles[1].row,
-            left: config.tiles[1].col,
-            width: config.tiles[1].width,
-            height: config.tiles[1].height,
-        },
-        LayoutType::Gpu,
-    );
+    ui.replay = replay;
+    for (tile_index, device) in config.devices.iter().enumerate() {
+        let layout_type = match device.device_type.as_str() {
+            "cpu" => LayoutType::Cpu(device.parse_options::<CpuTileOptions>()),
+            "gpu" => LayoutType::Gpu(device.parse_options::<GpuTileOptions>()),
+            "network" => LayoutType::Network(device.parse_options::<NetworkTileOptions>()),
+            "disk_io" => LayoutType::DiskIo(device.parse_options::<DiskIoTileOptions>()),
+            "filesystems" => LayoutType::Filesystems(device.parse_options::<FilesystemsTileOptions>()),
+            "sensors" => LayoutType::Sensors(device.parse_options::<SensorsTileOptions>()),
+            "battery" => LayoutType::Battery(device.parse_options::<BatteryTileOptions>()),
+            "system" => LayoutType::System(device.parse_options::<SystemTileOptions>()),
+            "cgroups" => LayoutType::Cgroups(device.parse_options::<CgroupsTileOptions>()),
+            "containers" => LayoutType::Containers(device.parse_options::<ContainersTileOptions>()),
+            "numa" => LayoutType::Numa(device.parse_options::<NumaTileOptions>()),
+            "sockets" => LayoutType::Sockets(device.parse_options::<SocketsTileOptions>()),
+            "interrupts" => LayoutType::Interrupts(device.parse_options::<InterruptsTileOptions>()),
+            "power" => LayoutType::Power(device.parse_options::<PowerTileOptions>()),
+            "command" => LayoutType::Command(device.parse_options::<CommandTileOptions>()),
+            "plugin" => LayoutType::Plugin(device.parse_options::<PluginTileOptions>()),
+            "prometheus" => LayoutType::Prometheus(device.parse_options::<PrometheusTileOptions>()),
+            "log" => LayoutType::Log(device.parse_options::<LogTileOptions>()),
+            "events" => LayoutType::Events(device.parse_options::<EventsTileOptions>()),
+            "watch" => LayoutType::Watch(device.parse_options::<WatchTileOptions>()),
+            other => {
+                eprintln!("ERROR: Unknown device type \"{other}\"");
+                return;
+            }
+        };
+        ui.create_layout(
+            tile_index,
+            config.tiles[tile_index].name.clone(),
+            config.tiles[tile_index].bbox(),
+            layout_type,
+        );
+    }
 
     execute!(stdout, EnterAlternateScreen, cursor::Hide,).unwrap();
 
@@ -95,6 +274,10 @@ fn main() {
 
     loop {
         ui.update_all(&config.tiles);
+        if !on_tick(&mut ui) {
+            execute!(stdout, LeaveAlternateScreen, cursor::Show).unwrap();
+            break;
+        }
 
         stdout.flush().unwrap();
         if poll(Duration::from_millis(500)).unwrap() {
@@ -113,6 +296,7 @@ fn main() {
                     ui.height = height;
                     config.update_grid(width, height);
                 },
+                Event::Key(key_event) => ui.handle_key(key_event),
                 _ => (),
             }
         }
@@ -121,5 +305,4 @@ fn main() {
         
     }
     disable_raw_mode().unwrap();
-
 }
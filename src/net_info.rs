@@ -0,0 +1,265 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::file_config::NetworkTileOptions;
+use crate::history::History;
+use crate::patterns::is_included;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+
+// counters from one line of /proc/net/dev
+#[derive(Default, Clone, Copy)]
+pub struct NetCounters {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_errors: u64,
+    pub tx_dropped: u64,
+}
+
+pub struct NetInterfaceInfo {
+    pub name: String,
+    pub link_state: String,
+    // per second rates
+    pub rx_bytes: f64,
+    pub tx_bytes: f64,
+    pub rx_packets: f64,
+    pub tx_packets: f64,
+    // totals since boot
+    pub errors: u64,
+    pub dropped: u64,
+    pub rx_history: History,
+    pub tx_history: History,
+    // totals since the interface came up
+    pub counters: NetCounters,
+}
+
+pub struct NetInfo {
+    procfs_root: PathBuf,
+    sysfs_root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    last_update: Instant,
+    pub interfaces: Vec<NetInterfaceInfo>,
+}
+
+impl NetInfo {
+    pub fn new(options: &NetworkTileOptions) -> Self {
+        let mut net_info = NetInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            sysfs_root: PathBuf::from(&options.sysfs_root),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            last_update: Instant::now(),
+            interfaces: Vec::new(),
+        };
+        net_info.update();
+        net_info
+    }
+
+    // filled by `replay`, include and exclude patterns still apply
+    pub fn for_replay(options: &NetworkTileOptions) -> Self {
+        NetInfo {
+            procfs_root: PathBuf::new(),
+            sysfs_root: PathBuf::new(),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            last_update: Instant::now(),
+            interfaces: Vec::new(),
+        }
+    }
+
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("net", tile);
+        let mut interfaces: Vec<NetInterfaceInfo> = Vec::new();
+        for name in sample.get_instances(&collector) {
+            if !is_included(name, &self.include, &self.exclude) {
+                continue;
+            }
+            let mut interface = match self.interfaces.iter().position(|interface| interface.name == name) {
+                Some(position) => self.interfaces.swap_remove(position),
+                None => NetInterfaceInfo::new(name.to_string(), NetCounters::default()),
+            };
+            let get_number = |field: &str| sample.get_number(&format!("{collector}.{name}.{field}")).unwrap_or(0.0);
+            interface.link_state = sample.get_text(&format!("{collector}.{name}.link_state")).unwrap_or("unknown").to_string();
+            interface.rx_bytes = get_number("rx_bytes_per_second");
+            interface.tx_bytes = get_number("tx_bytes_per_second");
+            interface.rx_packets = get_number("rx_packets_per_second");
+            interface.tx_packets = get_number("tx_packets_per_second");
+            interface.errors = get_number("errors") as u64;
+            interface.dropped = get_number("dropped") as u64;
+            interface.rx_history.push(interface.rx_bytes);
+            interface.tx_history.push(interface.tx_bytes);
+            interfaces.push(interface);
+        }
+        self.interfaces = interfaces;
+    }
+
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        let content = fs::read_to_string(self.procfs_root.join("net/dev")).unwrap_or_default();
+        let mut interfaces: Vec<NetInterfaceInfo> = Vec::new();
+
+        for (name, counters) in parse_net_dev(&content) {
+            if !is_included(&name, &self.include, &self.exclude) {
+                continue;
+            }
+
+            let link_state = fs::read_to_string(self.sysfs_root.join(format!("class/net/{name}/operstate")))
+                .map(|state| state.trim().to_string())
+                .unwrap_or_else(|_err| "unknown".to_string());
+
+            // keep history of interfaces that were already shown
+            let mut interface = match self.interfaces.iter().position(|interface| interface.name == name) {
+                Some(position) => self.interfaces.swap_remove(position),
+                None => NetInterfaceInfo::new(name, counters),
+            };
+
+            let rate = |current: u64, previous: u64| {
+                // counters are reset when interface goes down
+                if elapsed > 0.0 && current >= previous {
+                    (current - previous) as f64 / elapsed
+                } else {
+                    0.0
+                }
+            };
+            let previous = interface.counters;
+            interface.rx_bytes = rate(counters.rx_bytes, previous.rx_bytes);
+            interface.tx_bytes = rate(counters.tx_bytes, previous.tx_bytes);
+            interface.rx_packets = rate(counters.rx_packets, previous.rx_packets);
+            interface.tx_packets = rate(counters.tx_packets, previous.tx_packets);
+            interface.errors = counters.rx_errors + counters.tx_errors;
+            interface.dropped = counters.rx_dropped + counters.tx_dropped;
+            interface.link_state = link_state;
+            interface.counters = counters;
+            interface.rx_history.push(interface.rx_bytes);
+            interface.tx_history.push(interface.tx_bytes);
+
+            interfaces.push(interface);
+        }
+        self.interfaces = interfaces;
+    }
+}
+
+impl NetInterfaceInfo {
+    fn new(name: String, counters: NetCounters) -> Self {
+        NetInterfaceInfo {
+            name,
+            link_state: String::new(),
+            rx_bytes: 0.0,
+            tx_bytes: 0.0,
+            rx_packets: 0.0,
+            tx_packets: 0.0,
+            errors: 0,
+            dropped: 0,
+            rx_history: History::default(),
+            tx_history: History::default(),
+            counters,
+        }
+    }
+}
+
+fn parse_net_dev(content: &str) -> Vec<(String, NetCounters)> {
+    let mut interfaces = Vec::new();
+
+    // first two lines are table header
+    for line in content.lines().skip(2) {
+        let Some((name, data)) = line.split_once(':') else {
+            continue;
+        };
+        let fields: Vec<u64> = data
+            .split_whitespace()
+            .map(|field| field.parse().unwrap_or(0))
+            .collect();
+        if fields.len() < 12 {
+            continue;
+        }
+
+        interfaces.push((
+            name.trim().to_string(),
+            NetCounters {
+                rx_bytes: fields[0],
+                rx_packets: fields[1],
+                rx_errors: fields[2],
+                rx_dropped: fields[3],
+                tx_bytes: fields[8],
+                tx_packets: fields[9],
+                tx_errors: fields[10],
+                tx_dropped: fields[11],
+            },
+        ));
+    }
+    interfaces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::time::Duration;
+
+    // rx bytes, packets, errors and drops, then the same for tx
+    fn write_net_dev(fixture: &Fixture, eth0: [u64; 8]) {
+        let [rx_bytes, rx_packets, rx_errors, rx_dropped, tx_bytes, tx_packets, tx_errors, tx_dropped] = eth0;
+        fixture.write(
+            "net/dev",
+            &format!(
+                "Inter-|   Receive                            |  Transmit\n \
+                 face |bytes packets errs drop fifo frame ...|bytes packets errs drop fifo colls ...\n    \
+                 lo: 5000 50 0 0 0 0 0 0 5000 50 0 0 0 0 0 0\n  \
+                 eth0: {rx_bytes} {rx_packets} {rx_errors} {rx_dropped} 0 0 0 0 \
+                 {tx_bytes} {tx_packets} {tx_errors} {tx_dropped} 0 0 0 0\n\
+                 veth1a2b: 1 1 0 0 0 0 0 0 1 1 0 0 0 0 0 0\n"
+            ),
+        );
+    }
+
+    #[test]
+    fn turns_net_dev_counters_into_rates() {
+        let fixture = Fixture::new();
+        write_net_dev(&fixture, [1000, 10, 1, 2, 2000, 20, 3, 4]);
+        fixture.write("class/net/eth0/operstate", "up\n");
+        let mut net_info = NetInfo::new(&NetworkTileOptions {
+            procfs_root: fixture.root(),
+            sysfs_root: fixture.root(),
+            ..NetworkTileOptions::default()
+        });
+
+        // lo and veth are excluded by default
+        assert_eq!(net_info.interfaces.len(), 1);
+        let eth0 = &net_info.interfaces[0];
+        assert_eq!((eth0.name.as_str(), eth0.link_state.as_str()), ("eth0", "up"));
+        assert_eq!((eth0.rx_bytes, eth0.errors, eth0.dropped), (0.0, 4, 6));
+
+        write_net_dev(&fixture, [5000, 30, 1, 2, 3000, 40, 3, 4]);
+        net_info.last_update -= Duration::from_secs(2);
+        net_info.update();
+        let eth0 = &net_info.interfaces[0];
+        assert!((eth0.rx_bytes - 2000.0).abs() < 10.0);
+        assert!((eth0.tx_bytes - 500.0).abs() < 10.0);
+        assert!((eth0.rx_packets - 10.0).abs() < 0.1);
+        assert_eq!(eth0.rx_history.values().len(), 2);
+
+        // counters start over when the interface goes down
+        write_net_dev(&fixture, [100, 1, 0, 0, 100, 1, 0, 0]);
+        fixture.write("class/net/eth0/operstate", "down\n");
+        net_info.update();
+        let eth0 = &net_info.interfaces[0];
+        assert_eq!((eth0.rx_bytes, eth0.tx_bytes, eth0.link_state.as_str()), (0.0, 0.0, "down"));
+    }
+
+    #[test]
+    fn skips_short_and_header_lines() {
+        let content = "header\nheader\n  eth1: 1 2 3\n  wlan0: 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16\n";
+        let interfaces = parse_net_dev(content);
+        let names: Vec<&str> = interfaces.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["wlan0"]);
+        assert_eq!((interfaces[0].1.rx_bytes, interfaces[0].1.tx_dropped), (1, 12));
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::cpu_info::{get_cpu_times, parse_proc_stat, CpuCounters};
+use crate::cpu_topology::parse_cpu_list;
+use crate::file_config::NumaTileOptions;
+use crate::sysfs::{get_sorted_dirs, read_number, read_trimmed};
+
+const NVIDIA_VENDOR_ID: &str = "0x10de";
+
+// allocation counters from numastat, in pages
+#[derive(Default, Clone, Copy)]
+struct NumaCounters {
+    numa_hit: u64,
+    numa_miss: u64,
+    numa_foreign: u64,
+}
+
+pub struct NumaNode {
+    path: PathBuf,
+    counters: Option<NumaCounters>,
+    pub id: u32,
+    pub cpus: Vec<usize>,
+    // bytes
+    pub memory_total: u64,
+    pub memory_free: u64,
+    // pages per second allocated on this node as intended, on this node instead of
+    // another one, and on another node instead of this one
+    pub numa_hit: f64,
+    pub numa_miss: f64,
+    pub numa_foreign: f64,
+    // average usage of node cpus, 0.0..100.0
+    pub cpu_usage: f64,
+    // PCI addresses of gpus attached to the node
+    pub gpus: Vec<String>,
+}
+
+pub struct NumaInfo {
+    procfs_root: PathBuf,
+    // per core counters of /proc/stat from the previous update
+    cpus_counters: BTreeMap<usize, CpuCounters>,
+    last_update: Instant,
+    pub nodes: Vec<NumaNode>,
+}
+
+impl NumaInfo {
+    pub fn new(options: &NumaTileOptions) -> Self {
+        let sysfs_root = PathBuf::from(&options.sysfs_root);
+        let gpus = if options.gpus { get_gpus(&sysfs_root) } else { Vec::new() };
+
+        let nodes = get_sorted_dirs(&sysfs_root.join("devices/system/node"), "node")
+            .into_iter()
+            .map(|path| {
+                let id = path
+                    .file_name()
+                    .and_then(|name| name.to_string_lossy().strip_prefix("node")?.parse().ok())
+                    .unwrap_or(0);
+                NumaNode {
+                    cpus: read_trimmed(&path.join("cpulist"))
+                        .map(|list| parse_cpu_list(&list))
+                        .unwrap_or_default(),
+                    path,
+                    counters: None,
+                    id,
+                    memory_total: 0,
+                    memory_free: 0,
+                    numa_hit: 0.0,
+                    numa_miss: 0.0,
+                    numa_foreign: 0.0,
+                    cpu_usage: 0.0,
+                    gpus: gpus
+                        .iter()
+                        .filter(|(_, node)| *node == id)
+                        .map(|(address, _)| address.clone())
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let mut numa_info = NumaInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            cpus_counters: BTreeMap::new(),
+            last_update: Instant::now(),
+            nodes,
+        };
+        numa_info.update();
+        numa_info
+    }
+
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+        let cpus_counters = parse_proc_stat(&fs::read_to_string(self.procfs_root.join("stat")).unwrap_or_default());
+        // offline cpus have no line, a cpu without a previous reading has no usage yet
+        let cpus_usage: BTreeMap<usize, f64> = cpus_counters
+            .iter()
+            .filter_map(|(cpu, counters)| {
+                let times = get_cpu_times(self.cpus_counters.get(cpu)?, counters)?;
+                Some((*cpu, times.busy() * 100.0))
+            })
+            .collect();
+        self.cpus_counters = cpus_counters;
+
+        for node in &mut self.nodes {
+            // "Node 0 MemTotal:       32658444 kB"
+            let meminfo = fs::read_to_string(node.path.join("meminfo")).unwrap_or_default();
+            for line in meminfo.lines() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let value = fields.get(3).and_then(|value| value.parse::<u64>().ok()).unwrap_or(0) * 1024;
+                match fields.get(2) {
+                    Some(&"MemTotal:") => node.memory_total = value,
+                    Some(&"MemFree:") => node.memory_free = value,
+                    _ => (),
+                }
+            }
+
+            let counters = read_numastat(&node.path.join("numastat"));
+            if let Some(previous) = node.counters {
+                if elapsed > 0.0 {
+                    let delta = |current: u64, previous: u64| current.saturating_sub(previous) as f64;
+                    node.numa_hit = delta(counters.numa_hit, previous.numa_hit) / elapsed;
+                    node.numa_miss = delta(counters.numa_miss, previous.numa_miss) / elapsed;
+                    node.numa_foreign = delta(counters.numa_foreign, previous.numa_foreign) / elapsed;
+                }
+            }
+            node.counters = Some(counters);
+
+            let node_usage: Vec<f64> = node.cpus.iter().filter_map(|cpu| cpus_usage.get(cpu).copied()).collect();
+            node.cpu_usage = if node_usage.is_empty() {
+                0.0
+            } else {
+                node_usage.iter().sum::<f64>() / node_usage.len() as f64
+            };
+        }
+    }
+}
+
+// "numa_hit 4231792", one counter per line
+fn read_numastat(path: &Path) -> NumaCounters {
+    let mut counters = NumaCounters::default();
+    let content = fs::read_to_string(path).unwrap_or_default();
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        let value = value.trim().parse().unwrap_or(0);
+        match key {
+            "numa_hit" => counters.numa_hit = value,
+            "numa_miss" => counters.numa_miss = value,
+            "numa_foreign" => counters.numa_foreign = value,
+            _ => (),
+        }
+    }
+    counters
+}
+
+// Nvidia display and 3D controllers with their NUMA node, -1 means the platform doesn't report it
+// other vendors are left out, on servers that is mostly the VGA of the BMC
+fn get_gpus(sysfs_root: &Path) -> Vec<(String, u32)> {
+    let entries = match fs::read_dir(sysfs_root.join("bus/pci/devices")) {
+        Ok(entries) => entries,
+        Err(_err) => return Vec::new(),
+    };
+    let mut gpus: Vec<(String, u32)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            // class is "0x030000" for VGA and "0x030200" for 3D controllers
+            let class = read_trimmed(&path.join("class"))?;
+            if !class.starts_with("0x03") || read_trimmed(&path.join("vendor"))? != NVIDIA_VENDOR_ID {
+                return None;
+            }
+            let node = read_number(&path.join("numa_node")).filter(|node| *node >= 0.0)?;
+            Some((entry.file_name().to_string_lossy().to_string(), node as u32))
+        })
+        .collect();
+    gpus.sort();
+    gpus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+
+    #[test]
+    fn node_cpu_usage_averages_the_node_cores_of_proc_stat() {
+        let fixture = Fixture::new();
+        fixture
+            .write("sys/devices/system/node/node0/cpulist", "0-1\n")
+            .write("sys/devices/system/node/node1/cpulist", "2-3\n")
+            .write("sys/devices/system/node/node0/meminfo", "Node 0 MemTotal:  1024 kB\nNode 0 MemFree:  512 kB\n")
+            .write("sys/devices/system/node/node0/numastat", "numa_hit 100\nnuma_miss 0\nnuma_foreign 0\n")
+            .write(
+                "proc/stat",
+                "cpu0 0 0 0 100 0 0 0 0 0 0\ncpu1 0 0 0 100 0 0 0 0 0 0\n\
+                 cpu2 0 0 0 100 0 0 0 0 0 0\ncpu3 0 0 0 100 0 0 0 0 0 0\n",
+            );
+        let options = NumaTileOptions {
+            gpus: false,
+            procfs_root: format!("{}/proc", fixture.root()),
+            sysfs_root: format!("{}/sys", fixture.root()),
+        };
+        let mut numa_info = NumaInfo::new(&options);
+        assert_eq!(numa_info.nodes.len(), 2);
+        assert_eq!(numa_info.nodes[0].memory_total, 1024 * 1024);
+        assert_eq!(numa_info.nodes[0].memory_free, 512 * 1024);
+        assert_eq!(numa_info.nodes[0].cpu_usage, 0.0);
+
+        // cpu0 fully busy, cpu1 idle, cpu2 half busy and cpu3 offline
+        fixture
+            .write("sys/devices/system/node/node0/numastat", "numa_hit 300\nnuma_miss 0\nnuma_foreign 0\n")
+            .write(
+                "proc/stat",
+                "cpu0 100 0 0 100 0 0 0 0 0 0\ncpu1 0 0 0 200 0 0 0 0 0 0\n\
+                 cpu2 0 0 50 150 0 0 0 0 0 0\n",
+            );
+        numa_info.last_update -= std::time::Duration::from_secs(2);
+        numa_info.update();
+        assert!((numa_info.nodes[0].cpu_usage - 50.0).abs() < 1e-9);
+        assert!((numa_info.nodes[1].cpu_usage - 50.0).abs() < 1e-9);
+        assert!((numa_info.nodes[0].numa_hit - 100.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn lists_nvidia_gpus_of_each_node() {
+        let fixture = Fixture::new();
+        let write_device = |address: &str, class: &str, vendor: &str, node: &str| {
+            fixture
+                .write(&format!("bus/pci/devices/{address}/class"), &format!("{class}\n"))
+                .write(&format!("bus/pci/devices/{address}/vendor"), &format!("{vendor}\n"))
+                .write(&format!("bus/pci/devices/{address}/numa_node"), &format!("{node}\n"));
+        };
+        write_device("0000:3b:00.0", "0x030200", "0x10de", "0");
+        write_device("0000:af:00.0", "0x030000", "0x10de", "1");
+        // VGA of the BMC, a network card and a gpu the platform doesn't place
+        write_device("0000:03:00.0", "0x030000", "0x1a03", "0");
+        write_device("0000:18:00.0", "0x020000", "0x10de", "0");
+        write_device("0000:d8:00.0", "0x030200", "0x10de", "-1");
+
+        assert_eq!(
+            get_gpus(fixture.path()),
+            [("0000:3b:00.0".to_string(), 0), ("0000:af:00.0".to_string(), 1)]
+        );
+    }
+}
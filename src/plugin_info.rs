@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::command_info::{kill_command, shell_command};
+use crate::file_config::PluginTileOptions;
+use crate::history::History;
+
+// restart delay doubles after every crash up to this value
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    Bar,
+    Graph,
+    Number,
+    Table,
+}
+
+#[derive(Deserialize)]
+pub struct PluginMetric {
+    pub name: String,
+    pub kind: MetricKind,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub unit: String,
+    // bar is drawn against max, graph is scaled to it when set
+    pub max: Option<f64>,
+    // table header
+    #[serde(default)]
+    pub columns: Vec<String>,
+}
+
+// lines sent by the plugin, see "Plugin protocol" in README
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum PluginMessage {
+    Hello { metrics: Vec<PluginMetric> },
+    Values { values: Map<String, Value> },
+    Error { message: String },
+}
+
+#[derive(Default)]
+pub struct PluginValue {
+    pub number: Option<f64>,
+    pub history: History,
+    pub rows: Vec<Vec<String>>,
+}
+
+struct PluginProcess {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    started: Instant,
+}
+
+pub struct PluginInfo {
+    command: String,
+    interval: Duration,
+    process: Option<PluginProcess>,
+    last_sample: Option<Instant>,
+    backoff: Duration,
+    restart_at: Option<Instant>,
+    pub metrics: Vec<PluginMetric>,
+    pub values: HashMap<String, PluginValue>,
+    // last error reported by the plugin or about the plugin
+    pub error: Option<String>,
+}
+
+impl PluginInfo {
+    pub fn new(options: &PluginTileOptions) -> Self {
+        let mut plugin_info = PluginInfo {
+            command: options.command.clone(),
+            interval: Duration::from_secs(options.interval),
+            process: None,
+            last_sample: None,
+            backoff: Duration::from_secs(1),
+            restart_at: Some(Instant::now()),
+            metrics: Vec::new(),
+            values: HashMap::new(),
+            error: None,
+        };
+        plugin_info.update();
+        plugin_info
+    }
+
+    pub fn update(&mut self) {
+        if self.command.is_empty() {
+            self.error = Some("No command configured".to_string());
+            return;
+        }
+        if self.process.is_none() {
+            if self.restart_at.is_some_and(|restart_at| Instant::now() >= restart_at) {
+                self.start();
+            }
+            return;
+        }
+
+        self.read_messages();
+        if self.check_exited() {
+            return;
+        }
+
+        if self.last_sample.is_none_or(|last_sample| last_sample.elapsed() >= self.interval) {
+            self.last_sample = Some(Instant::now());
+            if let Some(process) = &mut self.process {
+                // a dead plugin is noticed by check_exited on the next update
+                writeln!(process.stdin, r#"{{"type":"sample"}}"#).ok();
+                process.stdin.flush().ok();
+            }
+        }
+    }
+
+    fn start(&mut self) {
+        // stderr would draw over the tiles
+        let spawned = shell_command(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(err) => {
+                self.error = Some(format!("Can't start plugin: {err}"));
+                self.schedule_restart();
+                return;
+            }
+        };
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.process = Some(PluginProcess {
+            child,
+            stdin,
+            lines,
+            started: Instant::now(),
+        });
+        self.restart_at = None;
+        self.last_sample = None;
+    }
+
+    fn read_messages(&mut self) {
+        let Some(process) = &self.process else {
+            return;
+        };
+        let lines: Vec<String> = process.lines.try_iter().collect();
+        for line in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<PluginMessage>(&line) {
+                Ok(PluginMessage::Hello { metrics }) => {
+                    // history survives plugin restarts
+                    self.values.retain(|name, _| metrics.iter().any(|metric| &metric.name == name));
+                    self.metrics = metrics;
+                    self.error = None;
+                }
+                Ok(PluginMessage::Values { values }) => {
+                    for (name, value) in values {
+                        self.set_value(name, value);
+                    }
+                }
+                Ok(PluginMessage::Error { message }) => self.error = Some(message),
+                Err(err) => self.error = Some(format!("Invalid plugin message: {err}")),
+            }
+        }
+    }
+
+    fn set_value(&mut self, name: String, value: Value) {
+        let plugin_value = self.values.entry(name).or_default();
+        match value {
+            // table rows are arrays of cells
+            Value::Array(rows) => {
+                plugin_value.rows = rows
+                    .iter()
+                    .map(|row| match row {
+                        Value::Array(cells) => cells.iter().map(cell_to_string).collect(),
+                        cell => vec![cell_to_string(cell)],
+                    })
+                    .collect();
+            }
+            value => {
+                if let Some(number) = value.as_f64() {
+                    plugin_value.number = Some(number);
+                    plugin_value.history.push(number);
+                }
+            }
+        }
+    }
+
+    fn check_exited(&mut self) -> bool {
+        let Some(process) = &mut self.process else {
+            return true;
+        };
+        let status = match process.child.try_wait() {
+            Ok(Some(status)) => status,
+            Ok(None) => return false,
+            Err(err) => {
+                self.error = Some(format!("Can't wait for plugin: {err}"));
+                return false;
+            }
+        };
+
+        // plugin that worked for a while is restarted quickly again
+        if process.started.elapsed() > MAX_BACKOFF {
+            self.backoff = Duration::from_secs(1);
+        }
+        let code = status.code().map(|code| code.to_string()).unwrap_or_else(|| "signal".to_string());
+        self.error = Some(format!("Plugin exited with code {code}, restarting in {}s", self.backoff.as_secs()));
+        self.process = None;
+        self.schedule_restart();
+        true
+    }
+
+    fn schedule_restart(&mut self) {
+        self.restart_at = Some(Instant::now() + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+impl Drop for PluginInfo {
+    fn drop(&mut self) {
+        if let Some(process) = &mut self.process {
+            kill_command(&mut process.child);
+        }
+    }
+}
+
+fn cell_to_string(cell: &Value) -> String {
+    match cell {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn start(command: &str) -> PluginInfo {
+        PluginInfo::new(&PluginTileOptions {
+            command: command.to_string(),
+            interval: 1,
+        })
+    }
+
+    fn update_until(plugin_info: &mut PluginInfo, done: impl Fn(&PluginInfo) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(plugin_info) && Instant::now() < deadline {
+            plugin_info.update();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(done(plugin_info), "plugin didn't get there, error: {:?}", plugin_info.error);
+    }
+
+    #[test]
+    fn parses_hello_values_and_errors() {
+        let mut plugin_info = start(
+            r#"echo '{"type":"hello","metrics":[{"name":"load","kind":"graph","max":4},{"name":"top","kind":"table","columns":["pid","name"]}]}'
+               read line
+               echo '{"type":"values","values":{"load":1.5,"top":[[1,"init"],[2,null]],"unknown":"x"}}'
+               echo 'not json'
+               read line"#,
+        );
+        update_until(&mut plugin_info, |plugin_info| plugin_info.error.is_some());
+
+        let names: Vec<&str> = plugin_info.metrics.iter().map(|metric| metric.name.as_str()).collect();
+        assert_eq!(names, vec!["load", "top"]);
+        assert!(plugin_info.metrics[1].kind == MetricKind::Table);
+        assert_eq!(plugin_info.metrics[0].max, Some(4.0));
+        assert_eq!(plugin_info.values["load"].number, Some(1.5));
+        assert_eq!(plugin_info.values["top"].rows, vec![vec!["1", "init"], vec!["2", ""]]);
+        assert!(plugin_info.error.as_ref().unwrap().starts_with("Invalid plugin message"));
+    }
+
+    #[test]
+    fn restarts_crashed_plugin_with_growing_backoff() {
+        let mut plugin_info = start("exit 3");
+        update_until(&mut plugin_info, |plugin_info| plugin_info.process.is_none());
+        assert_eq!(plugin_info.error.as_deref(), Some("Plugin exited with code 3, restarting in 1s"));
+
+        // not restarted before the backoff passed
+        plugin_info.update();
+        assert!(plugin_info.process.is_none());
+
+        plugin_info.restart_at = Some(Instant::now());
+        plugin_info.update();
+        assert!(plugin_info.process.is_some());
+        update_until(&mut plugin_info, |plugin_info| plugin_info.process.is_none());
+        assert_eq!(plugin_info.error.as_deref(), Some("Plugin exited with code 3, restarting in 2s"));
+    }
+
+    #[test]
+    fn drop_stops_the_children_of_the_shell() {
+        let fixture = crate::test_fixture::Fixture::new();
+        let pid_path = fixture.path().join("pid");
+        let mut plugin_info = start(&format!("sleep 30 & echo $! > {}; wait", pid_path.display()));
+        update_until(&mut plugin_info, |_| std::fs::read_to_string(&pid_path).is_ok_and(|pid| pid.ends_with('\n')));
+        let pid = std::fs::read_to_string(&pid_path).unwrap().trim().to_string();
+        let started = Instant::now();
+        drop(plugin_info);
+        assert!(started.elapsed() < Duration::from_secs(5), "drop waited for the shell");
+
+        // gone, or a zombie waiting for init
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let is_running = || {
+            std::fs::read_to_string(format!("/proc/{pid}/stat"))
+                .is_ok_and(|stat| stat.rsplit(')').next().is_some_and(|fields| !fields.trim_start().starts_with('Z')))
+        };
+        while is_running() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!is_running());
+    }
+}
@@ -0,0 +1,330 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use nvml_wrapper::Nvml;
+
+use crate::file_config::PowerTileOptions;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+use crate::sysfs::{read_number, read_trimmed};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PowerSource {
+    Rapl,
+    Gpu,
+}
+
+pub struct PowerComponent {
+    source: PowerSource,
+    // RAPL zone directory or gpu index
+    path: PathBuf,
+    gpu_index: u32,
+    // uJ where the RAPL counter wraps to zero
+    max_energy_range: u64,
+    last_energy: Option<u64>,
+    // core and uncore are parts of package energy, dram is not
+    in_total: bool,
+    // "package-0", "package-0 core", "dram", "gpu0"
+    pub name: String,
+    pub is_subzone: bool,
+    pub watts: f64,
+    // W, from the zone power limit or gpu enforced limit
+    pub limit: Option<f64>,
+    // J since tsm started
+    pub energy: f64,
+}
+
+pub struct PowerInfo {
+    nvml: Option<Nvml>,
+    last_update: Instant,
+    pub components: Vec<PowerComponent>,
+    // W and J of packages, dram and gpus
+    pub total_watts: f64,
+    pub total_energy: f64,
+    // recorded time of the previous replayed sample
+    replayed_timestamp: Option<f64>,
+}
+
+impl PowerInfo {
+    pub fn new(options: &PowerTileOptions) -> Self {
+        let mut components = get_rapl_zones(&PathBuf::from(&options.sysfs_root).join("class/powercap"));
+
+        // gpu power is optional, tile works on machines without nvidia driver
+        let nvml = if options.gpus { Nvml::init().ok() } else { None };
+        if let Some(nvml) = &nvml {
+            for gpu_index in 0..nvml.device_count().unwrap_or(0) {
+                let limit = nvml
+                    .device_by_index(gpu_index)
+                    .and_then(|device| device.enforced_power_limit())
+                    .ok()
+                    .map(|limit| limit as f64 / 1000.0);
+                components.push(PowerComponent {
+                    source: PowerSource::Gpu,
+                    path: PathBuf::new(),
+                    gpu_index,
+                    max_energy_range: 0,
+                    last_energy: None,
+                    in_total: true,
+                    name: format!("gpu{gpu_index}"),
+                    is_subzone: false,
+                    watts: 0.0,
+                    limit,
+                    energy: 0.0,
+                });
+            }
+        }
+
+        let mut power_info = PowerInfo {
+            nvml,
+            last_update: Instant::now(),
+            components,
+            total_watts: 0.0,
+            total_energy: 0.0,
+            replayed_timestamp: None,
+        };
+        power_info.update();
+        power_info
+    }
+
+    // filled by `replay`, energy is summed up from the recorded power
+    pub fn for_replay() -> Self {
+        PowerInfo {
+            nvml: None,
+            last_update: Instant::now(),
+            components: Vec::new(),
+            total_watts: 0.0,
+            total_energy: 0.0,
+            replayed_timestamp: None,
+        }
+    }
+
+    // limits are not recorded, subzones are told by their "package-0 core" names
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("power", tile);
+        let elapsed = match self.replayed_timestamp {
+            Some(timestamp) if sample.timestamp >= timestamp => sample.timestamp - timestamp,
+            // seeking back starts the energy over
+            _ => {
+                self.components.clear();
+                0.0
+            }
+        };
+        self.replayed_timestamp = Some(sample.timestamp);
+
+        let mut components: Vec<PowerComponent> = Vec::new();
+        for name in sample.get_instances(&collector) {
+            let mut component = match self.components.iter().position(|component| component.name == name) {
+                Some(position) => self.components.swap_remove(position),
+                None => PowerComponent {
+                    source: PowerSource::Rapl,
+                    path: PathBuf::new(),
+                    gpu_index: 0,
+                    max_energy_range: 0,
+                    last_energy: None,
+                    in_total: !name.contains(' ') || name.ends_with(" dram"),
+                    name: name.to_string(),
+                    is_subzone: name.contains(' '),
+                    watts: 0.0,
+                    limit: None,
+                    energy: 0.0,
+                },
+            };
+            component.watts = sample.get_number(&format!("{collector}.{name}.watts")).unwrap_or(0.0);
+            component.energy += component.watts * elapsed;
+            components.push(component);
+        }
+        self.components = components;
+        self.total_watts = sample.get_number(&format!("{collector}.total_watts")).unwrap_or(0.0);
+        let counted = self.components.iter().filter(|component| component.in_total);
+        self.total_energy = counted.fold(0.0, |total, component| total + component.energy);
+    }
+
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        for component in &mut self.components {
+            match component.source {
+                PowerSource::Rapl => {
+                    let Some(energy) = read_number(&component.path.join("energy_uj")).map(|energy| energy as u64) else {
+                        continue;
+                    };
+                    let delta = component.last_energy.and_then(|last_energy| {
+                        match (energy.checked_sub(last_energy), component.max_energy_range) {
+                            (Some(delta), _) => Some(delta),
+                            // counter wraps at max_energy_range_uj, a wrap of unknown range is skipped
+                            (None, 0) => None,
+                            (None, max_energy_range) => Some(energy + max_energy_range.saturating_sub(last_energy)),
+                        }
+                    });
+                    if let Some(delta) = delta {
+                        let joules = delta as f64 / 1e6;
+                        component.energy += joules;
+                        if elapsed > 0.0 {
+                            component.watts = joules / elapsed;
+                        }
+                    }
+                    component.last_energy = Some(energy);
+                }
+                PowerSource::Gpu => {
+                    let power_usage = self
+                        .nvml
+                        .as_ref()
+                        .and_then(|nvml| nvml.device_by_index(component.gpu_index).ok())
+                        .and_then(|device| device.power_usage().ok());
+                    if let Some(power_usage) = power_usage {
+                        // mW, averaged by the driver over the last second
+                        component.watts = power_usage as f64 / 1000.0;
+                        component.energy += component.watts * elapsed;
+                    }
+                }
+            }
+        }
+
+        let counted = self.components.iter().filter(|component| component.in_total);
+        self.total_watts = counted.clone().fold(0.0, |total, component| total + component.watts);
+        self.total_energy = counted.fold(0.0, |total, component| total + component.energy);
+    }
+}
+
+// intel-rapl:0 is a package with subzones intel-rapl:0:0 (core), intel-rapl:0:1 (uncore) and
+// intel-rapl:0:2 (dram on servers). psys covers the whole platform and would be counted twice
+fn get_rapl_zones(powercap_dir: &Path) -> Vec<PowerComponent> {
+    let mut zone_dirs: Vec<PathBuf> = match std::fs::read_dir(powercap_dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("intel-rapl:"))
+            })
+            .collect(),
+        Err(_err) => Vec::new(),
+    };
+    zone_dirs.sort();
+
+    let mut zones: Vec<PowerComponent> = Vec::new();
+    for path in zone_dirs {
+        let Some(name) = read_trimmed(&path.join("name")) else {
+            continue;
+        };
+        if name == "psys" {
+            continue;
+        }
+        // energy_uj is readable only by root since the PLATYPUS fix
+        if read_number(&path.join("energy_uj")).is_none() {
+            continue;
+        }
+
+        // "intel-rapl:0:1" belongs to "intel-rapl:0"
+        let zone_id = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let parent = zone_id
+            .rsplit_once(':')
+            .filter(|(parent_id, _)| parent_id.contains(':'))
+            .map(|(parent_id, _)| read_trimmed(&powercap_dir.join(parent_id).join("name")).unwrap_or_default());
+        zones.push(PowerComponent {
+            source: PowerSource::Rapl,
+            gpu_index: 0,
+            max_energy_range: read_number(&path.join("max_energy_range_uj")).unwrap_or(0.0) as u64,
+            last_energy: None,
+            in_total: parent.is_none() || name == "dram",
+            is_subzone: parent.is_some(),
+            name: match parent {
+                Some(parent) => format!("{parent} {name}"),
+                None => name,
+            },
+            watts: 0.0,
+            limit: read_number(&path.join("constraint_0_power_limit_uw")).map(|limit| limit / 1e6),
+            energy: 0.0,
+            path,
+        });
+    }
+    zones
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::time::Duration;
+
+    fn write_zone(fixture: &Fixture, zone: &str, name: &str, energy_uj: u64) {
+        fixture
+            .write(&format!("class/powercap/{zone}/name"), &format!("{name}\n"))
+            .write(&format!("class/powercap/{zone}/energy_uj"), &format!("{energy_uj}\n"));
+    }
+
+    #[test]
+    fn reads_rapl_zones_and_counts_packages_and_dram() {
+        let fixture = Fixture::new();
+        write_zone(&fixture, "intel-rapl:0", "package-0", 1_000_000);
+        write_zone(&fixture, "intel-rapl:0:0", "core", 500_000);
+        write_zone(&fixture, "intel-rapl:0:1", "dram", 200_000);
+        // psys covers the packages and is not counted twice
+        write_zone(&fixture, "intel-rapl:1", "psys", 0);
+        fixture
+            .write("class/powercap/intel-rapl:0/constraint_0_power_limit_uw", "15000000\n")
+            .write("class/powercap/intel-rapl:0:1/max_energy_range_uj", "1000000\n")
+            // energy_uj is readable only by root
+            .write("class/powercap/intel-rapl:2/name", "package-1\n")
+            .write("class/powercap/intel-mmio:0/name", "package-0\n");
+
+        let mut power_info = PowerInfo::new(&PowerTileOptions {
+            gpus: false,
+            sysfs_root: fixture.root(),
+        });
+        let zones: Vec<(&str, bool, bool, Option<f64>)> = power_info
+            .components
+            .iter()
+            .map(|zone| (zone.name.as_str(), zone.is_subzone, zone.in_total, zone.limit))
+            .collect();
+        assert_eq!(
+            zones,
+            [
+                ("package-0", false, true, Some(15.0)),
+                ("package-0 core", true, false, None),
+                ("package-0 dram", true, true, None),
+            ]
+        );
+
+        // the dram counter wrapped at its max energy range
+        write_zone(&fixture, "intel-rapl:0", "package-0", 11_000_000);
+        write_zone(&fixture, "intel-rapl:0:0", "core", 5_500_000);
+        write_zone(&fixture, "intel-rapl:0:1", "dram", 100_000);
+        power_info.last_update -= Duration::from_secs(2);
+        power_info.update();
+
+        let energies: Vec<f64> = power_info.components.iter().map(|zone| zone.energy).collect();
+        assert_eq!(energies, [10.0, 5.0, 0.9]);
+        assert!((power_info.components[0].watts - 5.0).abs() < 0.1);
+        assert!((power_info.total_watts - 5.45).abs() < 0.1);
+        assert!((power_info.total_energy - 10.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn skips_a_wrap_without_a_known_range() {
+        let fixture = Fixture::new();
+        write_zone(&fixture, "intel-rapl:0", "package-0", 5_000_000);
+        let mut power_info = PowerInfo::new(&PowerTileOptions {
+            gpus: false,
+            sysfs_root: fixture.root(),
+        });
+
+        write_zone(&fixture, "intel-rapl:0", "package-0", 7_000_000);
+        power_info.last_update -= Duration::from_secs(2);
+        power_info.update();
+        assert_eq!(power_info.total_energy, 2.0);
+        let watts = power_info.total_watts;
+
+        // max_energy_range_uj is missing, the whole counter would show up as a spike
+        write_zone(&fixture, "intel-rapl:0", "package-0", 1_000_000);
+        power_info.last_update -= Duration::from_secs(2);
+        power_info.update();
+        assert_eq!((power_info.total_energy, power_info.total_watts), (2.0, watts));
+
+        write_zone(&fixture, "intel-rapl:0", "package-0", 1_500_000);
+        power_info.last_update -= Duration::from_secs(2);
+        power_info.update();
+        assert_eq!(power_info.total_energy, 2.5);
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::process::exit;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::file_config::{PrometheusDisplay, PrometheusSeries, PrometheusTileOptions};
+use crate::history::History;
+
+// scrapes run on a worker thread, a slow endpoint delays only this tile
+
+enum LabelMatcher {
+    Equal(String, String),
+    NotEqual(String, String),
+    Regex(String, Regex),
+    NotRegex(String, Regex),
+}
+
+impl LabelMatcher {
+    // same syntax as PromQL selectors: code="200", method!="GET", path=~"/api/.*", path!~"/debug.*"
+    fn parse(matcher: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid label matcher \"{matcher}\"");
+        // the operator is the first one after the name, the value may contain "=" and "~" too
+        let (name, rest) = matcher.split_at(matcher.find(['=', '!']).ok_or_else(invalid)?);
+        let (operator, value) = ["=~", "!~", "!=", "="]
+            .into_iter()
+            .find_map(|operator| Some((operator, rest.strip_prefix(operator)?)))
+            .ok_or_else(invalid)?;
+        let name = name.trim().to_string();
+        let value = value.trim().trim_matches('"').to_string();
+        // regex must match the whole label value like in PromQL
+        let regex = || {
+            Regex::new(&format!("^(?:{value})$")).map_err(|err| format!("Invalid regex in \"{matcher}\": {err}"))
+        };
+        Ok(match operator {
+            "=~" => LabelMatcher::Regex(name, regex()?),
+            "!~" => LabelMatcher::NotRegex(name, regex()?),
+            "!=" => LabelMatcher::NotEqual(name, value),
+            _ => LabelMatcher::Equal(name, value),
+        })
+    }
+
+    // missing label matches as empty value
+    fn matches(&self, labels: &[(String, String)]) -> bool {
+        let get = |name: &str| {
+            labels
+                .iter()
+                .find(|(label, _)| label == name)
+                .map(|(_, value)| value.as_str())
+                .unwrap_or_default()
+        };
+        match self {
+            LabelMatcher::Equal(name, value) => get(name) == value,
+            LabelMatcher::NotEqual(name, value) => get(name) != value,
+            LabelMatcher::Regex(name, regex) => regex.is_match(get(name)),
+            LabelMatcher::NotRegex(name, regex) => !regex.is_match(get(name)),
+        }
+    }
+}
+
+struct Selector {
+    series: PrometheusSeries,
+    matchers: Vec<LabelMatcher>,
+}
+
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+impl Sample {
+    // like "{code="200",method="GET"}"
+    fn labels_text(&self) -> String {
+        if self.labels.is_empty() {
+            return String::new();
+        }
+        let labels: Vec<String> = self.labels.iter().map(|(name, value)| format!("{name}=\"{value}\"")).collect();
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+pub struct PrometheusRow {
+    // metric name with labels, same between scrapes
+    id: String,
+    // configured label replaces the metric name, labels are kept to tell several matched series apart
+    pub title: String,
+    pub display: PrometheusDisplay,
+    pub unit: String,
+    pub max: Option<f64>,
+    // per second for counters, empty until the second scrape
+    pub value: Option<f64>,
+    pub is_rate: bool,
+    pub history: History,
+}
+
+// sent by the worker thread, the time the scrape started and the exposition or the error
+type Scrape = (Instant, Result<String, String>);
+
+pub struct PrometheusInfo {
+    scrapes: Receiver<Scrape>,
+    selectors: Vec<Selector>,
+    // start of the previous successful scrape, counters are divided by the time between scrapes
+    last_scrape: Option<Instant>,
+    // counter values from the previous scrape by series id
+    counters: HashMap<String, f64>,
+    // false until the first scrape is done
+    pub loaded: bool,
+    pub rows: Vec<PrometheusRow>,
+    pub error: Option<String>,
+}
+
+impl PrometheusInfo {
+    pub fn new(options: &PrometheusTileOptions) -> Self {
+        let selectors = options
+            .series
+            .iter()
+            .map(|series| {
+                let matchers = series
+                    .labels
+                    .iter()
+                    .map(|matcher| LabelMatcher::parse(matcher))
+                    .collect::<Result<Vec<LabelMatcher>, String>>()
+                    .unwrap_or_else(|err| {
+                        eprintln!("ERROR: {err}");
+                        exit(1)
+                    });
+                Selector {
+                    series: series.clone(),
+                    matchers,
+                }
+            })
+            .collect();
+
+        let url = options.url.clone();
+        let interval = Duration::from_secs(options.interval);
+        let timeout = Duration::from_secs(options.timeout);
+        let (scrape_sender, scrapes) = mpsc::channel();
+        std::thread::spawn(move || scrape(&url, interval, timeout, scrape_sender));
+
+        PrometheusInfo {
+            scrapes,
+            selectors,
+            last_scrape: None,
+            counters: HashMap::new(),
+            loaded: false,
+            rows: Vec::new(),
+            error: None,
+        }
+    }
+
+    // takes the scrapes the worker made since the previous update, never waits for the endpoint
+    pub fn update(&mut self) {
+        while let Ok((started, exposition)) = self.scrapes.try_recv() {
+            self.loaded = true;
+            match exposition {
+                Ok(exposition) => {
+                    self.error = None;
+                    self.update_rows(&exposition, started);
+                }
+                Err(err) => self.error = Some(err),
+            }
+        }
+    }
+
+    fn update_rows(&mut self, exposition: &str, started: Instant) {
+        let elapsed = self.last_scrape.map(|last_scrape| started.duration_since(last_scrape).as_secs_f64());
+        self.last_scrape = Some(started);
+        let (types, samples) = parse_exposition(exposition);
+
+        let mut rows: Vec<PrometheusRow> = Vec::new();
+        let mut counters: HashMap<String, f64> = HashMap::new();
+        for selector in &self.selectors {
+            let matched: Vec<&Sample> = samples
+                .iter()
+                .filter(|sample| sample.name == selector.series.metric)
+                .filter(|sample| selector.matchers.iter().all(|matcher| matcher.matches(&sample.labels)))
+                .collect();
+
+            for sample in &matched {
+                let id = format!("{}{}", sample.name, sample.labels_text());
+                let title = match selector.series.label.as_str() {
+                    "" => id.clone(),
+                    label if matched.len() > 1 => format!("{label}{}", sample.labels_text()),
+                    label => label.to_string(),
+                };
+                let is_rate = is_counter(&types, &sample.name);
+                let value = if is_rate {
+                    counters.insert(id.clone(), sample.value);
+                    match (self.counters.get(&id), elapsed) {
+                        // counter reset starts from zero
+                        (Some(previous), Some(elapsed)) if elapsed > 0.0 => {
+                            let delta = if sample.value >= *previous { sample.value - previous } else { sample.value };
+                            Some(delta / elapsed)
+                        }
+                        _ => None,
+                    }
+                } else {
+                    Some(sample.value)
+                };
+
+                // keep history of the same series between scrapes
+                let mut history = match self.rows.iter().position(|row| row.id == id) {
+                    Some(position) => self.rows.swap_remove(position).history,
+                    None => History::default(),
+                };
+                if let Some(value) = value {
+                    history.push(value);
+                }
+                rows.push(PrometheusRow {
+                    id,
+                    title,
+                    display: selector.series.display,
+                    unit: selector.series.unit.clone(),
+                    max: selector.series.max,
+                    value,
+                    is_rate,
+                    history,
+                });
+            }
+        }
+        self.rows = rows;
+        self.counters = counters;
+    }
+}
+
+// ends when the tile is dropped
+fn scrape(url: &str, interval: Duration, timeout: Duration, scrapes: Sender<Scrape>) {
+    loop {
+        let started = Instant::now();
+        let exposition = match crate::http::get_url(url, timeout) {
+            Ok(response) if response.status == 200 => Ok(response.body),
+            Ok(response) => Err(format!("{url} returned status {}", response.status)),
+            Err(err) => Err(err),
+        };
+        if scrapes.send((started, exposition)).is_err() {
+            return;
+        }
+        std::thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+}
+
+// histogram and summary _sum, _count and _bucket series grow like counters
+fn is_counter(types: &HashMap<String, String>, name: &str) -> bool {
+    if types.get(name).is_some_and(|metric_type| metric_type == "counter") {
+        return true;
+    }
+    ["_sum", "_count", "_bucket"].iter().any(|suffix| {
+        name.strip_suffix(suffix).is_some_and(|family| {
+            types
+                .get(family)
+                .is_some_and(|metric_type| metric_type == "histogram" || metric_type == "summary")
+        })
+    })
+}
+
+// text exposition format:
+// # TYPE http_requests_total counter
+// http_requests_total{method="post",code="200"} 1027 1395066363000
+fn parse_exposition(content: &str) -> (HashMap<String, String>, Vec<Sample>) {
+    let mut types = HashMap::new();
+    let mut samples = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut fields = comment.split_whitespace();
+            if let (Some("TYPE"), Some(name), Some(metric_type)) = (fields.next(), fields.next(), fields.next()) {
+                types.insert(name.to_string(), metric_type.to_string());
+            }
+            continue;
+        }
+        if let Some(sample) = parse_sample(line) {
+            samples.push(sample);
+        }
+    }
+    (types, samples)
+}
+
+fn parse_sample(line: &str) -> Option<Sample> {
+    let name_end = line.find(|symbol: char| symbol == '{' || symbol.is_whitespace())?;
+    let name = line[..name_end].to_string();
+    let mut rest = &line[name_end..];
+
+    let mut labels = Vec::new();
+    if let Some(labels_text) = rest.strip_prefix('{') {
+        let (parsed_labels, labels_end) = parse_labels(labels_text)?;
+        labels = parsed_labels;
+        rest = &labels_text[labels_end..];
+    }
+
+    // optional timestamp after the value is ignored
+    let value = rest.split_whitespace().next()?;
+    let value = match value {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        value => value.parse().ok()?,
+    };
+    Some(Sample { name, labels, value })
+}
+
+// returns labels and the position after the closing brace, values may contain escaped quotes
+fn parse_labels(text: &str) -> Option<(Vec<(String, String)>, usize)> {
+    let mut labels = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    loop {
+        while chars.next_if(|(_, symbol)| symbol.is_whitespace() || *symbol == ',').is_some() {}
+        let (position, symbol) = chars.next()?;
+        if symbol == '}' {
+            return Some((labels, position + 1));
+        }
+
+        let mut name = symbol.to_string();
+        while let Some((_, symbol)) = chars.next_if(|(_, symbol)| *symbol != '=') {
+            name.push(symbol);
+        }
+        chars.next()?;
+        if chars.next()?.1 != '"' {
+            return None;
+        }
+
+        let mut value = String::new();
+        loop {
+            match chars.next()?.1 {
+                '"' => break,
+                '\\' => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    escaped => value.push(escaped),
+                },
+                symbol => value.push(symbol),
+            }
+        }
+        labels.push((name.trim().to_string(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    const EXPOSITION: &str = r#"# HELP http_requests_total Requests served.
+# TYPE http_requests_total counter
+http_requests_total{method="post",code="200"} 1027 1395066363000
+http_requests_total{method="get",code="500",path="/a\"b"} 3
+# TYPE queue_length gauge
+queue_length 12.5
+# TYPE latency_seconds histogram
+latency_seconds_bucket{le="+Inf"} 40
+latency_seconds_sum 8
+"#;
+
+    fn series(metric: &str, labels: &[&str]) -> PrometheusSeries {
+        PrometheusSeries {
+            metric: metric.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            ..PrometheusSeries::default()
+        }
+    }
+
+    // answers every scrape with the same exposition
+    fn serve_metrics() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut request_line = String::new();
+                BufReader::new(&stream).read_line(&mut request_line).unwrap();
+                let (status, body) = match request_line.split_whitespace().nth(1).unwrap_or_default() {
+                    "/metrics" => ("200 OK", EXPOSITION),
+                    _ => ("404 Not Found", ""),
+                };
+                let mut stream = stream;
+                write!(stream, "HTTP/1.1 {status}\r\nContent-Length: {}\r\n\r\n{body}", body.len()).ok();
+            }
+        });
+        format!("http://{address}")
+    }
+
+    fn wait_for_scrape(prometheus_info: &mut PrometheusInfo) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !prometheus_info.loaded && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+            prometheus_info.update();
+        }
+    }
+
+    #[test]
+    fn parses_the_text_exposition_format() {
+        let (types, samples) = parse_exposition(EXPOSITION);
+
+        assert!(is_counter(&types, "http_requests_total"));
+        assert!(is_counter(&types, "latency_seconds_sum"));
+        assert!(!is_counter(&types, "queue_length"));
+        let parsed: Vec<(String, f64)> =
+            samples.iter().map(|sample| (format!("{}{}", sample.name, sample.labels_text()), sample.value)).collect();
+        assert_eq!(
+            parsed,
+            [
+                ("http_requests_total{method=\"post\",code=\"200\"}".to_string(), 1027.0),
+                ("http_requests_total{method=\"get\",code=\"500\",path=\"/a\"b\"}".to_string(), 3.0),
+                ("queue_length".to_string(), 12.5),
+                ("latency_seconds_bucket{le=\"+Inf\"}".to_string(), 40.0),
+                ("latency_seconds_sum".to_string(), 8.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_labels_like_promql() {
+        let labels = vec![("path".to_string(), "/api/users".to_string()), ("code".to_string(), "200".to_string())];
+        let matches = |matcher: &str| LabelMatcher::parse(matcher).unwrap().matches(&labels);
+
+        assert!(matches("code=\"200\""));
+        assert!(matches("code!=\"500\""));
+        assert!(matches("path=~\"/api/.*\""));
+        // the whole value has to match
+        assert!(!matches("path=~\"api\""));
+        assert!(matches("path!~\"/debug.*\""));
+        // missing label is an empty value
+        assert!(matches("method=\"\""));
+        // "=~" in the value is not the operator
+        let equal = LabelMatcher::parse("path=\"a=~b\"");
+        assert!(matches!(equal, Ok(LabelMatcher::Equal(name, value)) if name == "path" && value == "a=~b"));
+        assert!(LabelMatcher::parse("path").is_err());
+        assert!(LabelMatcher::parse("path=~\"(\"").is_err());
+    }
+
+    #[test]
+    fn scrapes_on_a_worker_thread() {
+        let mut prometheus_info = PrometheusInfo::new(&PrometheusTileOptions {
+            url: format!("{}/metrics", serve_metrics()),
+            series: vec![series("queue_length", &[]), series("http_requests_total", &["code=\"200\""])],
+            ..PrometheusTileOptions::default()
+        });
+        assert!(!prometheus_info.loaded);
+        wait_for_scrape(&mut prometheus_info);
+
+        assert_eq!(prometheus_info.error, None);
+        let rows: Vec<(&str, Option<f64>, bool)> =
+            prometheus_info.rows.iter().map(|row| (row.title.as_str(), row.value, row.is_rate)).collect();
+        // counters need a second scrape for a rate
+        assert_eq!(
+            rows,
+            [
+                ("queue_length", Some(12.5), false),
+                ("http_requests_total{method=\"post\",code=\"200\"}", None, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_failed_scrapes() {
+        let mut prometheus_info = PrometheusInfo::new(&PrometheusTileOptions {
+            url: format!("{}/missing", serve_metrics()),
+            ..PrometheusTileOptions::default()
+        });
+        wait_for_scrape(&mut prometheus_info);
+        assert!(prometheus_info.error.as_deref().is_some_and(|err| err.ends_with("/missing returned status 404")));
+    }
+
+    #[test]
+    fn turns_counters_into_rates_and_starts_over_on_reset() {
+        let mut prometheus_info = PrometheusInfo::new(&PrometheusTileOptions {
+            url: format!("{}/metrics", serve_metrics()),
+            series: vec![series("requests_total", &[])],
+            ..PrometheusTileOptions::default()
+        });
+        let started = Instant::now();
+        let rate = |prometheus_info: &mut PrometheusInfo, value: u32, seconds: u64| {
+            let exposition = format!("# TYPE requests_total counter\nrequests_total {value}\n");
+            prometheus_info.update_rows(&exposition, started + Duration::from_secs(seconds));
+            prometheus_info.rows[0].value
+        };
+
+        assert_eq!(rate(&mut prometheus_info, 100, 0), None);
+        assert_eq!(rate(&mut prometheus_info, 150, 5), Some(10.0));
+        // restarted process counts from zero again
+        assert_eq!(rate(&mut prometheus_info, 20, 10), Some(4.0));
+        assert_eq!(prometheus_info.rows[0].history.values().len(), 2);
+    }
+}
@@ -0,0 +1,752 @@
+// `--record <file>`: every sample of the collectors with a timestamp, as CSV or JSON lines
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossterm::style::Color;
+use nvml_wrapper::Nvml;
+use serde_json::{json, Value};
+
+use crate::battery_info::PowerSupplyInfo;
+use crate::cgroup_info::CgroupsInfo;
+use crate::cpu_info::CpuInfo;
+use crate::disk_info::DiskInfo;
+use crate::file_config::FileDevice;
+use crate::fs_info::FsInfo;
+use crate::gpu_info::GpuAll;
+use crate::net_info::NetInfo;
+use crate::power_info::PowerInfo;
+use crate::replay::split_csv_line;
+use crate::sensors_info::{SensorKind, SensorsInfo};
+use crate::system_info::SystemInfo;
+use crate::ui::format_local_date_time;
+
+pub enum Rotation {
+    // bytes
+    Size(u64),
+    Time(Duration),
+}
+
+// "100M", "1G" or "30m", "12h", "1d"
+pub fn parse_rotation(text: &str) -> Result<Rotation, String> {
+    let error = || format!("Invalid rotation \"{text}\", use a size like 100M or a time like 1h");
+    let split = text.find(|symbol: char| !symbol.is_ascii_digit()).ok_or_else(error)?;
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().map_err(|_| error())?;
+    if number == 0 {
+        return Err(error());
+    }
+    // too large numbers are an error instead of wrapping around
+    let scaled = |scale: u64| number.checked_mul(scale).ok_or_else(error);
+    match unit {
+        "K" => Ok(Rotation::Size(scaled(1 << 10)?)),
+        "M" => Ok(Rotation::Size(scaled(1 << 20)?)),
+        "G" => Ok(Rotation::Size(scaled(1 << 30)?)),
+        "m" => Ok(Rotation::Time(Duration::from_secs(scaled(60)?))),
+        "h" => Ok(Rotation::Time(Duration::from_secs(scaled(3600)?))),
+        "d" => Ok(Rotation::Time(Duration::from_secs(scaled(86400)?))),
+        _ => Err(error()),
+    }
+}
+
+pub struct RecordOptions {
+    pub path: PathBuf,
+    pub interval: Duration,
+    pub rotation: Option<Rotation>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RecordFormat {
+    Csv,
+    JsonLines,
+}
+
+// values of one sample named like "cpu.3.usage_percent" or "net.eth0.rx_bytes_per_second",
+// collector before the first dot, field after the last one
+pub type Record = Vec<(String, Value)>;
+
+fn number(value: f64) -> Value {
+    json!((value * 100.0).round() / 100.0)
+}
+
+// tile types with collectors that are recorded, cpu and gpu are recorded without their tiles too
+pub const RECORDED_TILES: [&str; 10] = [
+    "cpu", "gpu", "network", "disk_io", "filesystems", "sensors", "power", "system", "battery", "cgroups",
+];
+
+// "gpu" for the first tile of a type, "gpu#1" for the second one, so older recordings keep their keys
+pub fn get_collector_name(collector: &str, tile: usize) -> String {
+    match tile {
+        0 => collector.to_string(),
+        tile => format!("{collector}#{tile}"),
+    }
+}
+
+// collectors a record is made of, borrowed from the tiles on the screen or from Collectors,
+// one per tile in config order
+#[derive(Default, Clone)]
+pub struct Sources<'a> {
+    pub cpu: Vec<&'a CpuInfo>,
+    pub gpus: Vec<&'a GpuAll>,
+    pub network: Vec<&'a NetInfo>,
+    pub disks: Vec<&'a DiskInfo>,
+    pub filesystems: Vec<&'a FsInfo>,
+    pub sensors: Vec<&'a SensorsInfo>,
+    pub power: Vec<&'a PowerInfo>,
+    pub system: Vec<&'a SystemInfo>,
+    pub batteries: Vec<&'a PowerSupplyInfo>,
+    pub cgroups: Vec<&'a CgroupsInfo>,
+}
+
+impl<'a> Sources<'a> {
+    // collectors of other are used for the types self has none of
+    pub fn or(self, other: Sources<'a>) -> Sources<'a> {
+        fn or<T>(first: Vec<T>, second: Vec<T>) -> Vec<T> {
+            if first.is_empty() {
+                second
+            } else {
+                first
+            }
+        }
+        Sources {
+            cpu: or(self.cpu, other.cpu),
+            gpus: or(self.gpus, other.gpus),
+            network: or(self.network, other.network),
+            disks: or(self.disks, other.disks),
+            filesystems: or(self.filesystems, other.filesystems),
+            sensors: or(self.sensors, other.sensors),
+            power: or(self.power, other.power),
+            system: or(self.system, other.system),
+            batteries: or(self.batteries, other.batteries),
+            cgroups: or(self.cgroups, other.cgroups),
+        }
+    }
+
+    pub fn get_record(&self) -> Record {
+        let mut record = Record::new();
+        let mut push = |key: String, value: Value| record.push((key, value));
+
+        for (tile, cpu) in self.cpu.iter().enumerate() {
+            let (name, ram, swap) = (
+                get_collector_name("cpu", tile),
+                get_collector_name("ram", tile),
+                get_collector_name("swap", tile),
+            );
+            for index in 0..cpu.cpu_count {
+                push(format!("{name}.{index}.usage_percent"), number(cpu.get_cpu_usage(index)));
+                if let Some(frequency) = cpu.get_frequency(index) {
+                    push(format!("{name}.{index}.frequency_mhz"), number(frequency));
+                }
+            }
+            let (ram_used, ram_total) = cpu.get_ram_usage();
+            let (swap_used, swap_total) = cpu.get_swap_usage();
+            push(format!("{ram}.used_mb"), json!(ram_used));
+            push(format!("{ram}.total_mb"), json!(ram_total));
+            push(format!("{swap}.used_mb"), json!(swap_used));
+            push(format!("{swap}.total_mb"), json!(swap_total));
+        }
+
+        for (tile, gpus) in self.gpus.iter().enumerate() {
+            let name = get_collector_name("gpu", tile);
+            for gpu in 0..gpus.device_count {
+                let (memory_used, memory_total) = gpus.get_memory_info(gpu);
+                // name is the title of the tile since the first recordings, model and uuid were added later
+                push(format!("{name}.{gpu}.name"), json!(gpus.get_title(gpu)));
+                push(format!("{name}.{gpu}.model"), json!(gpus.get_name(gpu)));
+                push(format!("{name}.{gpu}.uuid"), json!(gpus.get_uuid(gpu)));
+                push(format!("{name}.{gpu}.mig"), json!(gpus.is_mig(gpu)));
+                push(format!("{name}.{gpu}.memory_used_mb"), number(memory_used));
+                push(format!("{name}.{gpu}.memory_total_mb"), number(memory_total));
+                push(format!("{name}.{gpu}.utilization_percent"), json!(gpus.get_utilization_rate_info(gpu)));
+                push(format!("{name}.{gpu}.temperature_celsius"), json!(gpus.get_temperature(gpu)));
+            }
+        }
+
+        for (tile, network) in self.network.iter().enumerate() {
+            let name = get_collector_name("net", tile);
+            for interface in &network.interfaces {
+                let prefix = format!("{name}.{}", interface.name);
+                push(format!("{prefix}.link_state"), json!(interface.link_state));
+                push(format!("{prefix}.rx_bytes_per_second"), number(interface.rx_bytes));
+                push(format!("{prefix}.tx_bytes_per_second"), number(interface.tx_bytes));
+                push(format!("{prefix}.rx_packets_per_second"), number(interface.rx_packets));
+                push(format!("{prefix}.tx_packets_per_second"), number(interface.tx_packets));
+                push(format!("{prefix}.errors"), json!(interface.errors));
+                push(format!("{prefix}.dropped"), json!(interface.dropped));
+            }
+        }
+
+        for (tile, disks) in self.disks.iter().enumerate() {
+            let name = get_collector_name("disk", tile);
+            for device in &disks.devices {
+                let prefix = format!("{name}.{}", device.name);
+                push(format!("{prefix}.read_bytes_per_second"), number(device.read_bytes));
+                push(format!("{prefix}.write_bytes_per_second"), number(device.write_bytes));
+                push(format!("{prefix}.read_iops"), number(device.read_iops));
+                push(format!("{prefix}.write_iops"), number(device.write_iops));
+                push(format!("{prefix}.latency_ms"), number(device.latency));
+                push(format!("{prefix}.queue_depth"), number(device.queue_depth));
+                push(format!("{prefix}.busy_percent"), number(device.busy * 100.0));
+            }
+        }
+
+        for (tile, filesystems) in self.filesystems.iter().enumerate() {
+            let name = get_collector_name("fs", tile);
+            for filesystem in &filesystems.filesystems {
+                let prefix = format!("{name}.{}", filesystem.mount_point);
+                push(format!("{prefix}.used_bytes"), json!(filesystem.used));
+                push(format!("{prefix}.total_bytes"), json!(filesystem.total));
+                push(format!("{prefix}.available_bytes"), json!(filesystem.available));
+                push(format!("{prefix}.inodes_used"), json!(filesystem.inodes_used));
+                push(format!("{prefix}.inodes_total"), json!(filesystem.inodes_total));
+                push(format!("{prefix}.stale"), json!(filesystem.stale));
+            }
+        }
+
+        for (tile, sensors) in self.sensors.iter().enumerate() {
+            let name = get_collector_name("sensor", tile);
+            for chip in &sensors.chips {
+                for reading in &chip.readings {
+                    let unit = match reading.kind {
+                        SensorKind::Temperature => "celsius",
+                        SensorKind::Fan => "rpm",
+                        SensorKind::Voltage => "volts",
+                    };
+                    push(format!("{name}.{}/{}.{unit}", chip.name, reading.label), number(reading.value));
+                }
+            }
+        }
+
+        for (tile, power) in self.power.iter().enumerate() {
+            let name = get_collector_name("power", tile);
+            for component in &power.components {
+                push(format!("{name}.{}.watts", component.name), number(component.watts));
+            }
+            push(format!("{name}.total_watts"), number(power.total_watts));
+        }
+
+        for (tile, system) in self.system.iter().enumerate() {
+            let name = get_collector_name("system", tile);
+            push(format!("{name}.load_1"), number(system.load[0]));
+            push(format!("{name}.load_5"), number(system.load[1]));
+            push(format!("{name}.load_15"), number(system.load[2]));
+            push(format!("{name}.tasks_running"), json!(system.tasks_running));
+            push(format!("{name}.tasks_blocked"), json!(system.tasks_blocked));
+            push(format!("{name}.context_switches_per_second"), number(system.context_switches));
+            push(format!("{name}.interrupts_per_second"), number(system.interrupts));
+        }
+
+        for (tile, batteries) in self.batteries.iter().enumerate() {
+            let name = get_collector_name("battery", tile);
+            for battery in &batteries.batteries {
+                let prefix = format!("{name}.{}", battery.name);
+                push(format!("{prefix}.status"), json!(battery.status));
+                push(format!("{prefix}.capacity_percent"), number(battery.capacity * 100.0));
+                push(format!("{prefix}.power_watts"), json!(battery.power.map(number)));
+            }
+        }
+
+        for (tile, cgroups) in self.cgroups.iter().enumerate() {
+            let name = get_collector_name("cgroup", tile);
+            for cgroup in cgroups.parent.iter().chain(&cgroups.children) {
+                let prefix = format!("{name}.{}", cgroup.name);
+                push(format!("{prefix}.cpu_cores"), number(cgroup.cpu_usage));
+                push(format!("{prefix}.memory_bytes"), json!(cgroup.memory_current));
+                push(format!("{prefix}.throttled_percent"), number(cgroup.throttled * 100.0));
+                push(format!("{prefix}.io_read_bytes_per_second"), number(cgroup.io_read));
+                push(format!("{prefix}.io_write_bytes_per_second"), number(cgroup.io_write));
+            }
+        }
+        record
+    }
+}
+
+// collectors owned by the recorder or the exporter, one per tile with the options of that tile
+pub struct Collectors {
+    pub cpu: Vec<CpuInfo>,
+    pub gpus: Vec<GpuAll>,
+    pub network: Vec<NetInfo>,
+    pub disks: Vec<DiskInfo>,
+    pub filesystems: Vec<FsInfo>,
+    pub sensors: Vec<SensorsInfo>,
+    pub power: Vec<PowerInfo>,
+    pub system: Vec<SystemInfo>,
+    pub batteries: Vec<PowerSupplyInfo>,
+    pub cgroups: Vec<CgroupsInfo>,
+}
+
+impl Collectors {
+    // only the given tile types, cpu and gpu without their tiles too, the others when the config has their tile
+    pub fn new(devices: &[FileDevice], device_types: &[&str]) -> Self {
+        let find = |device_type: &str| -> Vec<&FileDevice> {
+            if !device_types.contains(&device_type) {
+                return Vec::new();
+            }
+            devices.iter().filter(|device| device.device_type == device_type).collect()
+        };
+        // a default collector when the config has no tile of the type
+        let with_default = |device_type: &str| -> Vec<Option<&FileDevice>> {
+            match find(device_type) {
+                tiles if tiles.is_empty() && device_types.contains(&device_type) => vec![None],
+                tiles => tiles.into_iter().map(Some).collect(),
+            }
+        };
+        Collectors {
+            cpu: with_default("cpu")
+                .into_iter()
+                .map(|device| CpuInfo::new(&device.map(FileDevice::parse_options).unwrap_or_default()))
+                .collect(),
+            // gpus only with a working driver
+            gpus: match with_default("gpu") {
+                tiles if !tiles.is_empty() && Nvml::init().is_ok() => tiles
+                    .into_iter()
+                    .map(|device| GpuAll::new(&device.map(FileDevice::parse_options).unwrap_or_default()))
+                    .collect(),
+                _ => Vec::new(),
+            },
+            network: find("network").iter().map(|device| NetInfo::new(&device.parse_options())).collect(),
+            disks: find("disk_io").iter().map(|device| DiskInfo::new(&device.parse_options())).collect(),
+            filesystems: find("filesystems").iter().map(|device| FsInfo::new(&device.parse_options())).collect(),
+            sensors: find("sensors").iter().map(|device| SensorsInfo::new(&device.parse_options())).collect(),
+            power: find("power").iter().map(|device| PowerInfo::new(&device.parse_options())).collect(),
+            system: find("system").iter().map(|device| SystemInfo::new(&device.parse_options())).collect(),
+            batteries: find("battery").iter().map(|device| PowerSupplyInfo::new(&device.parse_options())).collect(),
+            cgroups: find("cgroups").iter().map(|device| CgroupsInfo::new(&device.parse_options())).collect(),
+        }
+    }
+
+    pub fn update(&mut self) {
+        self.cpu.iter_mut().for_each(CpuInfo::update);
+        self.gpus.iter_mut().for_each(GpuAll::update);
+        self.network.iter_mut().for_each(NetInfo::update);
+        self.disks.iter_mut().for_each(DiskInfo::update);
+        self.filesystems.iter_mut().for_each(FsInfo::update);
+        self.sensors.iter_mut().for_each(SensorsInfo::update);
+        self.power.iter_mut().for_each(PowerInfo::update);
+        self.system.iter_mut().for_each(SystemInfo::update);
+        self.batteries.iter_mut().for_each(PowerSupplyInfo::update);
+        self.cgroups.iter_mut().for_each(CgroupsInfo::update);
+    }
+
+    pub fn get_sources(&self) -> Sources<'_> {
+        Sources {
+            cpu: self.cpu.iter().collect(),
+            gpus: self.gpus.iter().collect(),
+            network: self.network.iter().collect(),
+            disks: self.disks.iter().collect(),
+            filesystems: self.filesystems.iter().collect(),
+            sensors: self.sensors.iter().collect(),
+            power: self.power.iter().collect(),
+            system: self.system.iter().collect(),
+            batteries: self.batteries.iter().collect(),
+            cgroups: self.cgroups.iter().collect(),
+        }
+    }
+}
+
+struct RecordFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    opened: Instant,
+    size: u64,
+    // CSV header, empty for JSON lines
+    columns: Vec<String>,
+}
+
+// every key of the record has a column, values missing from the record are left empty
+fn has_columns(columns: &[String], record: &Record) -> bool {
+    record.iter().all(|(key, _)| columns.contains(key))
+}
+
+pub struct Recorder {
+    options: RecordOptions,
+    format: RecordFormat,
+    // only for the recorded tile types that are not on the screen
+    collectors: Collectors,
+    // tile types of the config without recorded values, like "log"
+    pub not_recorded: Vec<String>,
+    file: Option<RecordFile>,
+    last_sample: Instant,
+    pub error: Option<String>,
+}
+
+impl Recorder {
+    // shared_tiles are sampled by the tiles on the screen and given to sample_if_due, so values match what is shown
+    pub fn new(options: RecordOptions, devices: &[FileDevice], shared_tiles: bool) -> Self {
+        let format = match options.path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => RecordFormat::Csv,
+            _ => RecordFormat::JsonLines,
+        };
+        let owned_tiles: Vec<&str> = RECORDED_TILES
+            .into_iter()
+            .filter(|device_type| !shared_tiles || !devices.iter().any(|device| device.device_type == *device_type))
+            .collect();
+        let mut not_recorded: Vec<String> = Vec::new();
+        for device in devices {
+            if !RECORDED_TILES.contains(&device.device_type.as_str()) && !not_recorded.contains(&device.device_type) {
+                not_recorded.push(device.device_type.clone());
+            }
+        }
+        Recorder {
+            options,
+            format,
+            collectors: Collectors::new(devices, &owned_tiles),
+            not_recorded,
+            file: None,
+            // cpu usage needs two updates, the first sample is taken after one interval
+            last_sample: Instant::now(),
+            error: None,
+        }
+    }
+
+    // "REC metrics.csv" while recording, the error when the file can't be written
+    pub fn get_status(&self) -> (String, Color) {
+        if let Some(err) = &self.error {
+            return (format!("REC failed: {err}"), Color::Red);
+        }
+        let path = self.file.as_ref().map(|file| &file.path).unwrap_or(&self.options.path);
+        let mut status = format!("REC {}", path.file_name().unwrap_or_default().to_string_lossy());
+        if !self.not_recorded.is_empty() {
+            status += &format!(" (not recorded: {})", self.not_recorded.join(", "));
+        }
+        (status, Color::Green)
+    }
+
+    // shared are the collectors of the tiles, updated by them
+    pub fn sample_if_due(&mut self, shared: Sources) {
+        if self.last_sample.elapsed() < self.options.interval {
+            return;
+        }
+        self.last_sample = Instant::now();
+        self.collectors.update();
+        let record = shared.or(self.collectors.get_sources()).get_record();
+        self.error = self.write(&record).err();
+    }
+
+    // for cron and daemons, stops only when killed
+    pub fn run_headless(&mut self) {
+        if !self.not_recorded.is_empty() {
+            eprintln!("WARNING: {} tiles are not recorded", self.not_recorded.join(", "));
+        }
+        let mut last_error = None;
+        loop {
+            self.sample_if_due(Sources::default());
+            if self.error != last_error {
+                if let Some(err) = &self.error {
+                    eprintln!("ERROR: {err}");
+                }
+                last_error = self.error.clone();
+            }
+            std::thread::sleep(self.options.interval.saturating_sub(self.last_sample.elapsed()));
+        }
+    }
+
+    fn write(&mut self, record: &Record) -> Result<(), String> {
+        let is_due = match (&self.file, &self.options.rotation) {
+            (None, _) => true,
+            (Some(file), Some(Rotation::Size(size))) => file.size >= *size,
+            (Some(file), Some(Rotation::Time(time))) => file.opened.elapsed() >= *time,
+            (Some(_), None) => false,
+        };
+        // CSV columns are fixed by the header, interfaces, mounts or gpus appearing later start a new file
+        let columns_changed = self.format == RecordFormat::Csv
+            && self.file.as_ref().is_some_and(|file| !has_columns(&file.columns, record));
+        if is_due || columns_changed {
+            // a failed file is opened again on the next sample
+            self.file = None;
+            self.file = Some(self.open(record)?);
+        }
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+
+        let now = SystemTime::now();
+        let timestamp = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        let time = format_local_date_time(now);
+        let line = match self.format {
+            RecordFormat::Csv => {
+                let mut fields = vec![format!("{timestamp:.3}"), time];
+                for column in &file.columns[2..] {
+                    let value = record.iter().find(|(key, _)| key == column).map(|(_, value)| value);
+                    fields.push(format_csv_value(value.unwrap_or(&Value::Null)));
+                }
+                fields.join(",")
+            }
+            // keys are kept in collection order, the replay shows instances in that order
+            RecordFormat::JsonLines => {
+                let mut fields = vec![format!("\"timestamp\":{timestamp:.3}"), format!("\"time\":{}", json!(time))];
+                fields.extend(record.iter().map(|(key, value)| format!("{}:{value}", json!(key))));
+                format!("{{{}}}", fields.join(","))
+            }
+        };
+
+        // flushed every sample so a crash or a power loss keeps everything before it
+        writeln!(file.writer, "{line}")
+            .and_then(|_| file.writer.flush())
+            .map_err(|err| format!("{}: {err}", file.path.display()))?;
+        file.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    // "metrics.2024-03-18-14-03-27.csv", "metrics.2024-03-18-14-03-27-1.csv" for the second file of that second
+    fn get_timestamped_path(&self, attempt: usize) -> PathBuf {
+        let stem = self.options.path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut time = format_local_date_time(SystemTime::now()).replace([' ', ':'], "-");
+        if attempt > 0 {
+            time += &format!("-{attempt}");
+        }
+        let name = match self.options.path.extension() {
+            Some(extension) => format!("{stem}.{time}.{}", extension.to_string_lossy()),
+            None => format!("{stem}.{time}"),
+        };
+        self.options.path.with_file_name(name)
+    }
+
+    fn open(&self, record: &Record) -> Result<RecordFile, String> {
+        // a CSV file with other columns, from an older config or before a new interface, is left as is
+        let path = self
+            .options
+            .rotation
+            .is_none()
+            .then(|| self.options.path.clone())
+            .into_iter()
+            .chain((0..).map(|attempt| self.get_timestamped_path(attempt)))
+            .find(|path| match (self.format, read_csv_header(path)) {
+                (RecordFormat::Csv, Some(columns)) => has_columns(&columns, record),
+                _ => true,
+            })
+            .unwrap_or_else(|| self.options.path.clone());
+
+        // appended, a restarted tsm keeps the data from before
+        let columns = match self.format {
+            RecordFormat::Csv => read_csv_header(&path).unwrap_or_else(|| {
+                let mut columns = vec!["timestamp".to_string(), "time".to_string()];
+                columns.extend(record.iter().map(|(key, _)| key.clone()));
+                columns
+            }),
+            RecordFormat::JsonLines => Vec::new(),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if self.format == RecordFormat::Csv && size > 0 && read_csv_header(&path).is_none() {
+            return Err(format!("{} is not a tsm recording", path.display()));
+        }
+
+        let mut record_file = RecordFile {
+            path,
+            writer: BufWriter::new(file),
+            opened: Instant::now(),
+            size,
+            columns,
+        };
+        if self.format == RecordFormat::Csv && size == 0 {
+            let header: Vec<String> = record_file.columns.iter().map(|column| format_csv_field(column)).collect();
+            let header = header.join(",");
+            writeln!(record_file.writer, "{header}").map_err(|err| format!("{}: {err}", record_file.path.display()))?;
+            record_file.size += header.len() as u64 + 1;
+        }
+        Ok(record_file)
+    }
+}
+
+fn read_csv_header(path: &Path) -> Option<Vec<String>> {
+    let mut header = String::new();
+    BufReader::new(File::open(path).ok()?).read_line(&mut header).ok()?;
+    let columns = split_csv_line(header.trim_end());
+    (columns.len() > 2 && columns[0] == "timestamp").then_some(columns)
+}
+
+fn format_csv_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => format_csv_field(text),
+        value => value.to_string(),
+    }
+}
+
+// column names too, a mount point can have a comma or a quote
+fn format_csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use serde_json::Map;
+
+    fn device(device_type: &str) -> FileDevice {
+        FileDevice {
+            device_type: device_type.to_string(),
+            row: 0,
+            col: 0,
+            width: 1,
+            height: 1,
+            options: Map::new(),
+        }
+    }
+
+    #[test]
+    fn parses_rotation_sizes_and_times() {
+        assert!(matches!(parse_rotation("100M"), Ok(Rotation::Size(size)) if size == 100 << 20));
+        assert!(matches!(parse_rotation("2d"), Ok(Rotation::Time(time)) if time == Duration::from_secs(172800)));
+        for invalid in ["", "M", "0h", "10", "10x", "-1h", "99999999999999999999G", "18446744073709551615d"] {
+            assert!(parse_rotation(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn reports_tiles_without_recorded_values() {
+        let fixture = Fixture::new();
+        let options = RecordOptions {
+            path: fixture.path().join("metrics.csv"),
+            interval: Duration::from_secs(1),
+            rotation: None,
+        };
+        let devices = [device("cpu"), device("log"), device("events"), device("log")];
+        let recorder = Recorder::new(options, &devices, true);
+        assert_eq!(recorder.not_recorded, vec!["log", "events"]);
+        // the cpu tile on the screen is sampled instead
+        assert!(recorder.collectors.cpu.is_empty());
+        assert_eq!(recorder.get_status().0, "REC metrics.csv (not recorded: log, events)");
+    }
+
+    #[test]
+    fn appends_csv_rows_under_one_header() {
+        let fixture = Fixture::new();
+        let path = fixture.path().join("metrics.csv");
+        let record: Record = vec![
+            ("cpu.0.usage_percent".to_string(), json!(12.5)),
+            ("net.eth0.link_state".to_string(), json!("up")),
+            ("gpu.0.name".to_string(), json!("0: A100, 40GB")),
+        ];
+        for _ in 0..2 {
+            let options = RecordOptions {
+                path: path.clone(),
+                interval: Duration::from_secs(1),
+                rotation: None,
+            };
+            let mut recorder = Recorder::new(options, &[], true);
+            recorder.write(&record).unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "timestamp,time,cpu.0.usage_percent,net.eth0.link_state,gpu.0.name");
+        assert!(lines[1].ends_with(",12.5,up,\"0: A100, 40GB\""));
+        assert!(lines[2].ends_with(",12.5,up,\"0: A100, 40GB\""));
+    }
+
+    #[test]
+    fn quotes_columns_and_reads_them_back() {
+        let fixture = Fixture::new();
+        let path = fixture.path().join("metrics.csv");
+        let record: Record = vec![("fs./mnt/a,\"b\".used_bytes".to_string(), json!(1))];
+        for _ in 0..2 {
+            let options = RecordOptions {
+                path: path.clone(),
+                interval: Duration::from_secs(1),
+                rotation: None,
+            };
+            let mut recorder = Recorder::new(options, &[], true);
+            recorder.write(&record).unwrap();
+            assert_eq!(recorder.file.as_ref().unwrap().path, path);
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "timestamp,time,\"fs./mnt/a,\"\"b\"\".used_bytes\"");
+        assert_eq!(read_csv_header(&path).unwrap()[2], "fs./mnt/a,\"b\".used_bytes");
+    }
+
+    #[test]
+    fn starts_a_new_csv_file_when_columns_change() {
+        let fixture = Fixture::new();
+        let path = fixture.path().join("metrics.csv");
+        let options = RecordOptions {
+            path: path.clone(),
+            interval: Duration::from_secs(1),
+            rotation: None,
+        };
+        let mut recorder = Recorder::new(options, &[], true);
+        let mut record: Record = vec![("net.eth0.errors".to_string(), json!(0))];
+        recorder.write(&record).unwrap();
+        record.push(("net.wg0.errors".to_string(), json!(1)));
+        recorder.write(&record).unwrap();
+        // values going away keep the file
+        recorder.write(&record[1..].to_vec()).unwrap();
+
+        let new_path = recorder.file.as_ref().unwrap().path.clone();
+        assert_ne!(new_path, path);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let content = std::fs::read_to_string(&new_path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "timestamp,time,net.eth0.errors,net.wg0.errors");
+        assert!(lines[2].ends_with(",,1"));
+    }
+
+    #[test]
+    fn refuses_to_append_to_other_files() {
+        let fixture = Fixture::new();
+        fixture.write("notes.csv", "name,value\n");
+        let options = RecordOptions {
+            path: fixture.path().join("notes.csv"),
+            interval: Duration::from_secs(1),
+            rotation: None,
+        };
+        let mut recorder = Recorder::new(options, &[], true);
+        let err = recorder.write(&vec![("ram.used_mb".to_string(), json!(1))]).unwrap_err();
+        assert!(err.ends_with("notes.csv is not a tsm recording"));
+    }
+
+    #[test]
+    fn records_filesystems_of_the_config() {
+        let fixture = Fixture::new();
+        fixture.write("self/mounts", &format!("/dev/sda1 {} ext4 rw 0 0\n", fixture.root()));
+        let mut filesystems = device("filesystems");
+        filesystems.options.insert("procfs_root".to_string(), json!(fixture.root()));
+
+        let collectors = Collectors::new(&[filesystems], &["filesystems"]);
+        assert!(collectors.cpu.is_empty() && collectors.gpus.is_empty());
+        let record = collectors.get_sources().get_record();
+        let keys: Vec<String> = record.iter().map(|(key, _)| key.clone()).collect();
+        let prefix = format!("fs.{}", fixture.root());
+        assert_eq!(keys[0], format!("{prefix}.used_bytes"));
+        assert!(keys.contains(&format!("{prefix}.stale")));
+    }
+
+    #[test]
+    fn records_every_tile_of_a_type() {
+        let first = Fixture::new();
+        first.write("self/mounts", &format!("/dev/sda1 {} ext4 rw 0 0\n", first.root()));
+        let second = Fixture::new();
+        second.write("self/mounts", &format!("/dev/sdb1 {} ext4 rw 0 0\n", second.root()));
+        let devices: Vec<FileDevice> = [&first, &second]
+            .iter()
+            .map(|fixture| {
+                let mut filesystems = device("filesystems");
+                filesystems.options.insert("procfs_root".to_string(), json!(fixture.root()));
+                filesystems
+            })
+            .collect();
+
+        let collectors = Collectors::new(&devices, &["filesystems"]);
+        assert_eq!(collectors.filesystems.len(), 2);
+        let record = collectors.get_sources().get_record();
+        let keys: Vec<String> = record.iter().map(|(key, _)| key.clone()).collect();
+        assert!(keys.contains(&format!("fs.{}.used_bytes", first.root())));
+        assert!(keys.contains(&format!("fs#1.{}.used_bytes", second.root())));
+    }
+}
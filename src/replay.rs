@@ -0,0 +1,572 @@
+// `--replay <file>`: a recording of `--record` drives the tiles of the recorded collectors
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crossterm::event::{KeyCode, KeyEvent};
+use serde_json::{Map, Value};
+
+use crate::file_config::{FileConfig, FileDevice};
+use crate::recorder::RECORDED_TILES;
+use crate::ui::format_local_date_time;
+
+// recorded collector and the tile that shows it, ram and swap are shown by the cpu tile
+const COLLECTOR_TILES: [(&str, &str); 10] = [
+    ("cpu", "cpu"),
+    ("gpu", "gpu"),
+    ("net", "network"),
+    ("disk", "disk_io"),
+    ("fs", "filesystems"),
+    ("sensor", "sensors"),
+    ("power", "power"),
+    ("system", "system"),
+    ("battery", "battery"),
+    ("cgroup", "cgroups"),
+];
+// tiles in a column on the right of the cpu tiles before a second column is used
+const COLUMN_TILES: usize = 4;
+
+const SPEEDS: [f64; 9] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0];
+// samples before a seek position given to the tiles to fill their graphs
+const HISTORY_SAMPLES: usize = 64;
+
+// values of one recorded sample, keys like "cpu.3.usage_percent"
+pub struct RecordedSample {
+    pub timestamp: f64,
+    keys: Vec<String>,
+    values: HashMap<String, Value>,
+}
+
+impl RecordedSample {
+    fn new(timestamp: f64, values: Vec<(String, Value)>) -> Self {
+        RecordedSample {
+            timestamp,
+            keys: values.iter().map(|(key, _)| key.clone()).collect(),
+            values: values.into_iter().collect(),
+        }
+    }
+
+    pub fn get_number(&self, key: &str) -> Option<f64> {
+        self.values.get(key).and_then(Value::as_f64)
+    }
+
+    pub fn get_text(&self, key: &str) -> Option<&str> {
+        self.values.get(key).and_then(Value::as_str)
+    }
+
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.values.get(key).and_then(Value::as_bool).unwrap_or(false)
+    }
+
+    // "eth0", "lo" for "net", in recording order
+    pub fn get_instances(&self, collector: &str) -> Vec<&str> {
+        let mut instances: Vec<&str> = Vec::new();
+        for key in &self.keys {
+            let Some(rest) = key.strip_prefix(collector).and_then(|rest| rest.strip_prefix('.')) else {
+                continue;
+            };
+            // interface names may contain dots, the field never does
+            if let Some((instance, _)) = rest.rsplit_once('.') {
+                if !instances.contains(&instance) {
+                    instances.push(instance);
+                }
+            }
+        }
+        instances
+    }
+
+    // "cpu", "net", "gpu#1", in recording order
+    pub fn get_collectors(&self) -> Vec<&str> {
+        let mut collectors: Vec<&str> = Vec::new();
+        for key in &self.keys {
+            if let Some((collector, _)) = key.split_once('.') {
+                if !collectors.contains(&collector) {
+                    collectors.push(collector);
+                }
+            }
+        }
+        collectors
+    }
+
+    // cpus and gpus are numbered from 0
+    pub fn get_count(&self, collector: &str, field: &str) -> usize {
+        (0..).take_while(|index| self.values.contains_key(&format!("{collector}.{index}.{field}"))).count()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RecordFormat {
+    Csv,
+    JsonLines,
+}
+
+// samples are read from the file when they are shown, only their offsets are kept
+struct Recording {
+    file: File,
+    format: RecordFormat,
+    columns: Vec<String>,
+    // timestamp and offset of every sample
+    index: Vec<(f64, u64)>,
+}
+
+impl Recording {
+    fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let format = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => RecordFormat::Csv,
+            _ => RecordFormat::JsonLines,
+        };
+
+        let mut recording = Recording {
+            file: file.try_clone().map_err(|err| err.to_string())?,
+            format,
+            columns: Vec::new(),
+            index: Vec::new(),
+        };
+        let mut reader = BufReader::new(file);
+        let mut offset = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(read) => {
+                    // appended by a restarted tsm, the same header again
+                    if format == RecordFormat::Csv && line.starts_with("timestamp,") {
+                        recording.columns = split_csv_line(line.trim_end());
+                    } else if let Some(sample) = recording.parse_line(&line) {
+                        recording.index.push((sample.timestamp, offset));
+                    }
+                    offset += read as u64;
+                }
+                Err(err) => return Err(format!("{}: {err}", path.display())),
+            }
+        }
+        // the last line of a crashed recording can be cut, it's skipped by the parser
+        if recording.index.is_empty() {
+            return Err(format!("{} has no samples", path.display()));
+        }
+        recording.index.sort_by(|first, second| first.0.total_cmp(&second.0));
+        Ok(recording)
+    }
+
+    fn parse_line(&self, line: &str) -> Option<RecordedSample> {
+        let mut values: Vec<(String, Value)> = match self.format {
+            RecordFormat::Csv => {
+                let fields = split_csv_line(line.trim_end());
+                if fields.len() != self.columns.len() {
+                    return None;
+                }
+                self.columns.iter().cloned().zip(fields.iter().map(|field| parse_csv_value(field))).collect()
+            }
+            RecordFormat::JsonLines => serde_json::from_str::<Map<String, Value>>(line).ok()?.into_iter().collect(),
+        };
+        let timestamp = values.iter().find(|(key, _)| key == "timestamp")?.1.as_f64()?;
+        values.retain(|(key, _)| key != "timestamp" && key != "time");
+        Some(RecordedSample::new(timestamp, values))
+    }
+
+    fn read_sample(&mut self, position: usize) -> Option<RecordedSample> {
+        let (_, offset) = self.index[position];
+        self.file.seek(SeekFrom::Start(offset)).ok()?;
+        let mut line = String::new();
+        BufReader::new(&self.file).read_line(&mut line).ok()?;
+        self.parse_line(&line)
+    }
+}
+
+pub struct Replay {
+    recording: Recording,
+    position: usize,
+    // recorded time of the playback, moves with the speed
+    cursor: f64,
+    last_tick: Instant,
+    paused: bool,
+    speed: usize,
+    // last position given to the tiles
+    shown: Option<usize>,
+    // types of the config tiles without recorded data, they are left out
+    pub skipped_tiles: Vec<String>,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let recording = Recording::open(path)?;
+        Ok(Replay {
+            cursor: recording.index[0].0,
+            recording,
+            position: 0,
+            last_tick: Instant::now(),
+            paused: false,
+            speed: 2,
+            shown: None,
+            skipped_tiles: Vec::new(),
+        })
+    }
+
+    // a tile for every recorded collector, cpu tiles on the left and the others in up to two columns on the right
+    pub fn get_file_config(&mut self) -> FileConfig {
+        let first = self.recording.read_sample(0);
+        let collectors = first.as_ref().map(RecordedSample::get_collectors).unwrap_or_default();
+        // "gpu#1" is the second gpu tile
+        let device_types: Vec<&str> = collectors
+            .iter()
+            .filter_map(|collector| {
+                let name = collector.split_once('#').map_or(*collector, |(name, _)| name);
+                COLLECTOR_TILES.iter().find(|(recorded, _)| *recorded == name).map(|(_, device_type)| *device_type)
+            })
+            .collect();
+        let cpus = device_types.iter().filter(|device_type| **device_type == "cpu").count().max(1);
+        let others: Vec<&str> = device_types.into_iter().filter(|device_type| *device_type != "cpu").collect();
+        let columns = if others.len() > COLUMN_TILES { 2 } else { 1 };
+        let rows = others.len().div_ceil(columns).max(cpus);
+
+        let tile = |device_type: &str, row: usize, col: usize, height: usize| FileDevice {
+            device_type: device_type.to_string(),
+            row: row as u16,
+            col: col as u16,
+            width: 1,
+            height: height as u16,
+            options: Map::new(),
+        };
+        // the last cpu tile takes the rows left
+        let mut devices: Vec<FileDevice> =
+            (0..cpus).map(|row| tile("cpu", row, 0, if row + 1 == cpus { rows - row } else { 1 })).collect();
+        let column_rows = others.len().div_ceil(columns).max(1);
+        devices.extend(
+            others
+                .iter()
+                .enumerate()
+                .map(|(ind, device_type)| tile(device_type, ind % column_rows, 1 + ind / column_rows, 1)),
+        );
+        FileConfig {
+            name: "replay".to_string(),
+            symbol: "|".to_string(),
+            devices,
+        }
+    }
+
+    // tiles without recorded data would show this machine, they are left out and named in the status line
+    pub fn skip_tiles(&mut self, file_config: &mut FileConfig) {
+        for device in &file_config.devices {
+            let device_type = &device.device_type;
+            if !RECORDED_TILES.contains(&device_type.as_str()) && !self.skipped_tiles.contains(device_type) {
+                self.skipped_tiles.push(device_type.clone());
+            }
+        }
+        file_config.devices.retain(|device| RECORDED_TILES.contains(&device.device_type.as_str()));
+    }
+
+    // samples to give to the tiles since the previous call, oldest first
+    pub fn advance(&mut self) -> Vec<RecordedSample> {
+        let index = &self.recording.index;
+        if !self.paused {
+            self.cursor += self.last_tick.elapsed().as_secs_f64() * SPEEDS[self.speed];
+        }
+        self.last_tick = Instant::now();
+        self.cursor = self.cursor.clamp(index[0].0, index[index.len() - 1].0);
+        self.position = index.partition_point(|(timestamp, _)| *timestamp <= self.cursor).saturating_sub(1);
+        if self.position == index.len() - 1 {
+            self.paused = true;
+        }
+
+        let first = match self.shown {
+            Some(shown) if shown == self.position => return Vec::new(),
+            Some(shown) if shown < self.position => (shown + 1).max(self.position.saturating_sub(HISTORY_SAMPLES)),
+            // seeking back refills the graphs from the new position
+            _ => self.position.saturating_sub(HISTORY_SAMPLES),
+        };
+        self.shown = Some(self.position);
+        (first..=self.position).filter_map(|position| self.recording.read_sample(position)).collect()
+    }
+
+    fn seek(&mut self, seconds: f64) {
+        self.cursor += seconds;
+    }
+
+    fn step(&mut self, forward: bool) {
+        let index = &self.recording.index;
+        let position = if forward {
+            (self.position + 1).min(index.len() - 1)
+        } else {
+            self.position.saturating_sub(1)
+        };
+        self.cursor = index[position].0;
+        self.paused = true;
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        let index = &self.recording.index;
+        match key.code {
+            KeyCode::Char(' ') => {
+                // playing at the end starts over
+                if self.paused && self.position == index.len() - 1 {
+                    self.cursor = index[0].0;
+                }
+                self.paused = !self.paused;
+            }
+            KeyCode::Right => self.seek(60.0),
+            KeyCode::Left => self.seek(-60.0),
+            KeyCode::PageDown => self.seek(600.0),
+            KeyCode::PageUp => self.seek(-600.0),
+            KeyCode::Home => self.cursor = index[0].0,
+            KeyCode::End => self.cursor = index[index.len() - 1].0,
+            KeyCode::Char('.') => self.step(true),
+            KeyCode::Char(',') => self.step(false),
+            KeyCode::Char('+') | KeyCode::Char('=') => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            KeyCode::Char('-') => self.speed = self.speed.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    // "PLAY x4 2024-03-18 03:12:05 1234/86400", then the tiles left out
+    pub fn get_status(&self) -> String {
+        let (timestamp, _) = self.recording.index[self.position];
+        let time = UNIX_EPOCH + Duration::from_secs_f64(timestamp.max(0.0));
+        let skipped = if self.skipped_tiles.is_empty() {
+            String::new()
+        } else {
+            format!("  not replayed: {}", self.skipped_tiles.join(", "))
+        };
+        format!(
+            "{} x{} {} {}/{}  space ←→ PgUp PgDn , . + -{}",
+            if self.paused { "PAUSE" } else { "PLAY" },
+            SPEEDS[self.speed],
+            format_local_date_time(time),
+            self.position + 1,
+            self.recording.index.len(),
+            skipped
+        )
+    }
+}
+
+// fields of a line written by the recorder, quoted when they contain commas or quotes
+pub fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(symbol) = chars.next() {
+        match symbol {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            symbol => field.push(symbol),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn parse_csv_value(field: &str) -> Value {
+    if field.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(number) = field.parse::<f64>() {
+        return Value::from(number);
+    }
+    match field {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(field.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu_info::GpuAll;
+    use crate::power_info::PowerInfo;
+    use crate::sensors_info::{SensorKind, SensorsInfo};
+    use crate::test_fixture::Fixture;
+
+    #[test]
+    fn replays_gpus_of_recordings_without_model() {
+        let fixture = Fixture::new();
+        fixture.write(
+            "old.jsonl",
+            "{\"timestamp\":100.0,\"time\":\"2024-03-18 03:12:05\",\"cpu.0.usage_percent\":5,\
+             \"gpu.0.name\":\"0: NVIDIA A100\",\"gpu.0.mig\":false,\"gpu.0.memory_used_mb\":512,\
+             \"gpu.0.memory_total_mb\":40960,\"gpu.0.utilization_percent\":30,\"gpu.0.temperature_celsius\":41}\n",
+        );
+        let mut replay = Replay::open(&fixture.path().join("old.jsonl")).unwrap();
+        let samples = replay.advance();
+        let mut gpus = GpuAll::for_replay();
+        gpus.replay(&samples[0], 0);
+
+        assert_eq!(gpus.device_count, 1);
+        assert_eq!(gpus.get_title(0), "0: NVIDIA A100");
+        assert_eq!(gpus.get_name(0), "");
+        assert_eq!(gpus.get_memory_info(0), (512.0, 40960.0));
+        assert_eq!(gpus.get_utilization_rate_info(0), Some(30.0));
+    }
+
+    #[test]
+    fn lays_out_a_tile_for_every_recorded_collector() {
+        let fixture = Fixture::new();
+        fixture.write(
+            "all.jsonl",
+            "{\"timestamp\":100.0,\"cpu.0.usage_percent\":5,\"ram.used_bytes\":1,\"gpu.0.name\":\"0: A100\",\
+             \"gpu#1.0.name\":\"1: A100\",\"net.eth0.rx_bytes_per_second\":1,\"fs./.used_bytes\":1,\
+             \"sensor.coretemp/Core 0.celsius\":45,\"power.package-0.watts\":20,\"power.total_watts\":20,\
+             \"system.load_1\":0.5,\"battery.BAT0.capacity_percent\":80,\"cgroup./.cpu_cores\":1}\n",
+        );
+        let mut replay = Replay::open(&fixture.path().join("all.jsonl")).unwrap();
+        let file_config = replay.get_file_config();
+        let tiles: Vec<(&str, u16, u16)> =
+            file_config.devices.iter().map(|device| (device.device_type.as_str(), device.row, device.col)).collect();
+
+        assert_eq!(
+            tiles,
+            [
+                ("cpu", 0, 0),
+                ("gpu", 0, 1),
+                ("gpu", 1, 1),
+                ("network", 2, 1),
+                ("filesystems", 3, 1),
+                ("sensors", 4, 1),
+                ("power", 0, 2),
+                ("system", 1, 2),
+                ("battery", 2, 2),
+                ("cgroups", 3, 2),
+            ]
+        );
+        assert_eq!(file_config.devices[0].height, 5);
+    }
+
+    #[test]
+    fn replays_sensors_and_sums_up_energy() {
+        let fixture = Fixture::new();
+        fixture.write(
+            "power.jsonl",
+            "{\"timestamp\":100.0,\"sensor.coretemp/Core 0.celsius\":45,\"sensor.nct6775/fan1.rpm\":900,\
+             \"power.package-0.watts\":20,\"power.package-0 core.watts\":15,\"power.total_watts\":20}\n\
+             {\"timestamp\":110.0,\"sensor.coretemp/Core 0.celsius\":50,\"sensor.nct6775/fan1.rpm\":950,\
+             \"power.package-0.watts\":30,\"power.package-0 core.watts\":25,\"power.total_watts\":30}\n",
+        );
+        let mut replay = Replay::open(&fixture.path().join("power.jsonl")).unwrap();
+        replay.handle_key(KeyEvent::from(KeyCode::End));
+        let mut sensors = SensorsInfo::for_replay(&Default::default());
+        let mut power = PowerInfo::for_replay();
+        for sample in replay.advance() {
+            sensors.replay(&sample, 0);
+            power.replay(&sample, 0);
+        }
+
+        let chips: Vec<(&str, &str, f64)> = sensors
+            .chips
+            .iter()
+            .flat_map(|chip| {
+                let name = chip.name.as_str();
+                chip.readings.iter().map(move |reading| (name, reading.label.as_str(), reading.value))
+            })
+            .collect();
+        assert_eq!(chips, [("coretemp", "Core 0", 50.0), ("nct6775", "fan1", 950.0)]);
+        assert!(sensors.chips[1].readings[0].kind == SensorKind::Fan);
+        assert_eq!(power.total_watts, 30.0);
+        // only the package counts, the core is a part of it
+        assert_eq!(power.total_energy, 300.0);
+        assert!(power.components[1].is_subzone);
+    }
+
+    // 200 samples 10s apart, paused so the cursor moves only with the keys
+    fn open_paused(fixture: &Fixture) -> Replay {
+        let lines: String = (0..200)
+            .map(|position| format!("{{\"timestamp\":{},\"system.load_1\":{position}}}\n", 1000 + position * 10))
+            .collect();
+        fixture.write("long.jsonl", &lines);
+        let mut replay = Replay::open(&fixture.path().join("long.jsonl")).unwrap();
+        replay.handle_key(KeyEvent::from(KeyCode::Char(' ')));
+        replay
+    }
+
+    fn press(replay: &mut Replay, code: KeyCode) -> Vec<f64> {
+        replay.handle_key(KeyEvent::from(code));
+        replay.advance().iter().filter_map(|sample| sample.get_number("system.load_1")).collect()
+    }
+
+    #[test]
+    fn pause_keeps_the_position() {
+        let fixture = Fixture::new();
+        let mut replay = open_paused(&fixture);
+        assert_eq!(replay.advance().len(), 1);
+        replay.last_tick -= Duration::from_secs(60);
+        assert!(replay.advance().is_empty());
+        assert!(replay.get_status().starts_with("PAUSE x1 "));
+
+        replay.handle_key(KeyEvent::from(KeyCode::Char(' ')));
+        replay.last_tick -= Duration::from_secs(60);
+        assert_eq!(replay.advance().len(), 6);
+        assert_eq!(replay.position, 6);
+    }
+
+    #[test]
+    fn seeks_by_minutes_and_clamps_to_the_recording() {
+        let fixture = Fixture::new();
+        let mut replay = open_paused(&fixture);
+        replay.advance();
+        assert_eq!(press(&mut replay, KeyCode::Right), [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        press(&mut replay, KeyCode::PageDown);
+        assert_eq!(replay.position, 66);
+        press(&mut replay, KeyCode::Left);
+        assert_eq!(replay.position, 60);
+        press(&mut replay, KeyCode::PageUp);
+        press(&mut replay, KeyCode::PageUp);
+        assert_eq!(replay.position, 0);
+
+        for _ in 0..4 {
+            press(&mut replay, KeyCode::PageDown);
+        }
+        assert_eq!(replay.position, 199);
+        assert!(replay.paused);
+        // playing at the end starts over
+        press(&mut replay, KeyCode::Char(' '));
+        assert_eq!((replay.position, replay.paused), (0, false));
+    }
+
+    #[test]
+    fn steps_one_sample_and_limits_the_speed() {
+        let fixture = Fixture::new();
+        let mut replay = open_paused(&fixture);
+        replay.advance();
+        assert_eq!(press(&mut replay, KeyCode::Char(',')), Vec::<f64>::new());
+        assert_eq!(replay.position, 0);
+        assert_eq!(press(&mut replay, KeyCode::Char('.')), [1.0]);
+        assert_eq!(press(&mut replay, KeyCode::Char('.')), [2.0]);
+        assert_eq!(replay.position, 2);
+        assert!(replay.paused);
+
+        for _ in 0..20 {
+            replay.handle_key(KeyEvent::from(KeyCode::Char('+')));
+        }
+        assert_eq!(SPEEDS[replay.speed], 64.0);
+        for _ in 0..20 {
+            replay.handle_key(KeyEvent::from(KeyCode::Char('-')));
+        }
+        assert_eq!(SPEEDS[replay.speed], 0.25);
+    }
+
+    #[test]
+    fn seeking_back_refills_the_history() {
+        let fixture = Fixture::new();
+        let mut replay = open_paused(&fixture);
+        replay.advance();
+        // the graphs get the samples before the new position, not the whole skipped range
+        let samples = press(&mut replay, KeyCode::End);
+        assert_eq!(samples.len(), HISTORY_SAMPLES + 1);
+        assert_eq!((samples[0], samples[HISTORY_SAMPLES]), (135.0, 199.0));
+
+        let samples = press(&mut replay, KeyCode::PageUp);
+        assert_eq!(samples.len(), HISTORY_SAMPLES + 1);
+        assert_eq!((samples[0], samples[HISTORY_SAMPLES]), (75.0, 139.0));
+
+        // close to the start there is less history
+        let samples = press(&mut replay, KeyCode::Home);
+        assert_eq!(samples, [0.0]);
+    }
+}
@@ -0,0 +1,535 @@
+// `tsm run -- <command>`: runs a command, samples its process tree and prints a summary like `time -v`
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossterm::terminal;
+use nvml_wrapper::Nvml;
+use serde_json::{json, Map, Value};
+
+use crate::app_config::AppConfig;
+use crate::cpu_info::CpuInfo;
+use crate::file_config::{CpuTileOptions, FileConfig, FileDevice, GpuTileOptions, WatchTileOptions};
+use crate::gpu_info::GpuAll;
+use crate::ui::{format_bytes, format_count};
+use crate::watch_info::WatchInfo;
+
+struct RunOptions {
+    tui: bool,
+    interval: Duration,
+    output: Option<PathBuf>,
+    command: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColumnKind {
+    Percent,
+    Bytes,
+    // bytes per second, the summary adds the total
+    Rate,
+    Count,
+}
+
+// one value of every sample, a column of the output file and a row of the summary
+struct Column {
+    // header in the output file, like "rss_bytes"
+    key: String,
+    // row name in the summary
+    label: String,
+    kind: ColumnKind,
+    sum: f64,
+    peak: f64,
+    count: usize,
+    // integral over time for rates
+    total: f64,
+}
+
+impl Column {
+    fn new(key: &str, label: &str, kind: ColumnKind) -> Self {
+        Column {
+            key: key.to_string(),
+            label: label.to_string(),
+            kind,
+            sum: 0.0,
+            peak: 0.0,
+            count: 0,
+            total: 0.0,
+        }
+    }
+
+    fn push(&mut self, value: f64, elapsed: f64) {
+        self.sum += value;
+        self.peak = self.peak.max(value);
+        self.count += 1;
+        self.total += value * elapsed;
+    }
+
+    fn format(&self, value: f64) -> String {
+        match self.kind {
+            ColumnKind::Percent => format!("{value:.1}%"),
+            ColumnKind::Bytes => format_bytes(value),
+            ColumnKind::Rate => format!("{}/s", format_bytes(value)),
+            ColumnKind::Count => format_count(value),
+        }
+    }
+}
+
+enum OutputFormat {
+    Csv,
+    JsonLines,
+}
+
+struct Profiler {
+    watch: WatchInfo,
+    cpu: CpuInfo,
+    gpus: Option<GpuAll>,
+    columns: Vec<Column>,
+    interval: Duration,
+    last_sample: Instant,
+    samples: usize,
+    output: Option<(BufWriter<File>, OutputFormat)>,
+}
+
+impl Profiler {
+    fn new(pid: u32, interval: Duration, output: Option<&Path>) -> Result<Self, String> {
+        let watch = WatchInfo::new(&WatchTileOptions {
+            pid: Some(pid),
+            pss: false,
+            history: false,
+            ..WatchTileOptions::default()
+        });
+        let cpu = CpuInfo::new(&CpuTileOptions::default());
+        // GpuAll expects a working driver
+        let gpus = Nvml::init().is_ok().then(|| GpuAll::new(&GpuTileOptions::default()));
+
+        let mut columns = vec![
+            Column::new("cpu_percent", "CPU", ColumnKind::Percent),
+            Column::new("rss_bytes", "RSS", ColumnKind::Bytes),
+            Column::new("threads", "Threads", ColumnKind::Count),
+            Column::new("read_bytes_per_second", "Read", ColumnKind::Rate),
+            Column::new("write_bytes_per_second", "Write", ColumnKind::Rate),
+            Column::new("system_cpu_percent", "System CPU", ColumnKind::Percent),
+            Column::new("system_ram_used_bytes", "System RAM used", ColumnKind::Bytes),
+        ];
+        if let Some(gpus) = &gpus {
+            columns.push(Column::new("gpu_memory_bytes", "GPU memory", ColumnKind::Bytes));
+            for index in 0..gpus.device_count {
+                columns.push(Column::new(
+                    &format!("gpu{index}_utilization_percent"),
+                    &format!("GPU{index} utilization"),
+                    ColumnKind::Percent,
+                ));
+                columns.push(Column::new(
+                    &format!("gpu{index}_memory_used_bytes"),
+                    &format!("GPU{index} memory used"),
+                    ColumnKind::Bytes,
+                ));
+            }
+        }
+
+        let output = match output {
+            Some(path) => {
+                let file = File::create(path).map_err(|err| format!("Can't create {}: {err}", path.display()))?;
+                let format = match path.extension().and_then(|extension| extension.to_str()) {
+                    Some("csv") => OutputFormat::Csv,
+                    _ => OutputFormat::JsonLines,
+                };
+                let mut writer = BufWriter::new(file);
+                if let OutputFormat::Csv = format {
+                    let keys: Vec<&str> = columns.iter().map(|column| column.key.as_str()).collect();
+                    writeln!(writer, "timestamp,elapsed,{}", keys.join(",")).ok();
+                }
+                Some((writer, format))
+            }
+            None => None,
+        };
+
+        Ok(Profiler {
+            watch,
+            cpu,
+            gpus,
+            columns,
+            interval,
+            last_sample: Instant::now(),
+            samples: 0,
+            output,
+        })
+    }
+
+    fn sample_if_due(&mut self, started: Instant) {
+        if self.last_sample.elapsed() < self.interval {
+            return;
+        }
+        let elapsed = self.last_sample.elapsed().as_secs_f64();
+        self.last_sample = Instant::now();
+
+        self.watch.update();
+        // the tree is gone between the exit and the next wait
+        if !self.watch.is_running() {
+            return;
+        }
+        self.cpu.update();
+        if let Some(gpus) = &mut self.gpus {
+            gpus.update();
+        }
+
+        let cpu_count = self.cpu.cpu_count.max(1);
+        let system_cpu = (0..cpu_count).fold(0.0, |total, index| total + self.cpu.get_cpu_usage(index)) / cpu_count as f64;
+        let mut values = vec![
+            self.watch.cpu,
+            self.watch.rss,
+            self.watch.threads as f64,
+            self.watch.read_rate,
+            self.watch.write_rate,
+            system_cpu,
+            self.cpu.get_ram_usage().0 as f64 * 1024.0 * 1024.0,
+        ];
+        if let Some(gpus) = &self.gpus {
+            values.push(self.watch.gpu_memory.unwrap_or(0.0));
+            for index in 0..gpus.device_count {
+                values.push(gpus.get_utilization_rate_info(index).unwrap_or(0.0));
+                // MiB
+                values.push(gpus.get_memory_info(index).0 * 1024.0 * 1024.0);
+            }
+        }
+
+        for (column, value) in self.columns.iter_mut().zip(&values) {
+            column.push(*value, elapsed);
+        }
+        self.samples += 1;
+        self.write_sample(&values, started.elapsed().as_secs_f64());
+    }
+
+    fn write_sample(&mut self, values: &[f64], elapsed: f64) {
+        let Some((writer, format)) = &mut self.output else {
+            return;
+        };
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        match format {
+            OutputFormat::Csv => {
+                let values: Vec<String> = values.iter().map(|value| format!("{value:.2}")).collect();
+                writeln!(writer, "{timestamp:.3},{elapsed:.3},{}", values.join(",")).ok();
+            }
+            OutputFormat::JsonLines => {
+                let mut line = Map::new();
+                line.insert("timestamp".to_string(), json!(timestamp));
+                line.insert("elapsed".to_string(), json!(elapsed));
+                for (column, value) in self.columns.iter().zip(values) {
+                    line.insert(column.key.clone(), json!(value));
+                }
+                writeln!(writer, "{}", Value::Object(line)).ok();
+            }
+        }
+        // a killed tsm keeps what was sampled so far
+        writer.flush().ok();
+    }
+
+    fn print_summary(&self, command_line: &str, status: &str, elapsed: f64) {
+        eprintln!();
+        eprintln!("Command:  {command_line}");
+        eprintln!("Status:   {status}");
+        eprintln!("Elapsed:  {elapsed:.2}s");
+        eprintln!("Samples:  {}", self.samples);
+        if self.samples == 0 {
+            return;
+        }
+        eprintln!("{:<24}{:>12}{:>12}{:>12}", "", "average", "peak", "total");
+        for column in &self.columns {
+            let total = match column.kind {
+                ColumnKind::Rate => format_bytes(column.total),
+                _ => String::new(),
+            };
+            eprintln!(
+                "{:<24}{:>12}{:>12}{:>12}",
+                column.label,
+                column.format(column.sum / column.count.max(1) as f64),
+                column.format(column.peak),
+                total
+            );
+        }
+    }
+}
+
+pub fn print_run_usage_message() {
+    println!("Usage: ");
+    println!("tsm run [Options] -- <command> [arguments]");
+    println!("Options:");
+    println!("  --tui                  Show the process, its output, cpus and gpus while the command runs");
+    println!("  --interval <seconds>   Seconds between samples (default 1)");
+    println!("  --output <file>        Write samples to a .csv file or a .jsonl file");
+}
+
+fn parse_args(args: &[String]) -> Result<RunOptions, String> {
+    let mut options = RunOptions {
+        tui: false,
+        interval: Duration::from_secs(1),
+        output: None,
+        command: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tui" => options.tui = true,
+            "--interval" => {
+                let interval = args
+                    .next()
+                    .and_then(|interval| interval.parse::<f64>().ok())
+                    .filter(|interval| *interval > 0.0)
+                    .ok_or("--interval needs a number of seconds")?;
+                options.interval = Duration::from_secs_f64(interval);
+            }
+            "--output" => options.output = Some(PathBuf::from(args.next().ok_or("--output needs a file")?)),
+            "--" => {
+                options.command = args.cloned().collect();
+                break;
+            }
+            // command without `--`
+            command if !command.starts_with('-') => {
+                options.command = std::iter::once(arg).chain(args).cloned().collect();
+                break;
+            }
+            other => return Err(format!("Unknown option \"{other}\"")),
+        }
+    }
+    if options.command.is_empty() {
+        return Err("No command to run".to_string());
+    }
+    Ok(options)
+}
+
+// watched tree and its output on top, cpus and gpus below
+fn get_run_config(pid: u32, log_path: &Path, screen_width: u16, screen_height: u16) -> AppConfig {
+    let tile = |device_type: &str, row: u16, col: u16, width: u16, options: Value| FileDevice {
+        device_type: device_type.to_string(),
+        row,
+        col,
+        width,
+        height: 1,
+        options: match options {
+            Value::Object(options) => options,
+            _ => Map::new(),
+        },
+    };
+    let mut devices = vec![
+        tile("watch", 0, 0, 1, json!({ "pid": pid })),
+        tile("log", 0, 1, 1, json!({ "files": [log_path] })),
+    ];
+    if Nvml::init().is_ok() {
+        devices.push(tile("cpu", 1, 0, 1, json!({})));
+        devices.push(tile("gpu", 1, 1, 1, json!({})));
+    } else {
+        devices.push(tile("cpu", 1, 0, 2, json!({})));
+    }
+    let file_config = FileConfig {
+        name: "run".to_string(),
+        symbol: "|".to_string(),
+        devices,
+    };
+    AppConfig::from_file_config(file_config, screen_width, screen_height)
+}
+
+// like a shell: the exit code of the command, 128 + signal number when it was killed
+fn get_exit_code(status: &ExitStatus) -> (i32, String) {
+    if let Some(code) = status.code() {
+        return (code, format!("exited with code {code}"));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return (128 + signal, format!("killed by signal {signal}"));
+        }
+    }
+    (1, "unknown".to_string())
+}
+
+// Ctrl-C stops the command, tsm stays to print the summary like `time` does
+#[cfg(unix)]
+fn ignore_interrupts() {
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+    }
+}
+
+#[cfg(not(unix))]
+fn ignore_interrupts() {}
+
+// output of the command in a new directory only the user can enter, nothing there can be a planted symlink
+fn create_log_file() -> Result<(PathBuf, File), String> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+    let directory = std::env::temp_dir().join(format!("tsm-run-{}-{nanos}", std::process::id()));
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(&directory)
+        .map_err(|err| format!("Can't create {}: {err}", directory.display()))?;
+    let path = directory.join("output.log");
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|err| format!("Can't create {}: {err}", path.display()))?;
+    Ok((path, file))
+}
+
+fn spawn(options: &RunOptions, log: Option<File>) -> Result<Child, String> {
+    let mut command = Command::new(&options.command[0]);
+    command.args(&options.command[1..]);
+    // output of the command would draw over the tiles, it's shown by the log tile instead
+    if let Some(log) = log {
+        let log_stderr = log.try_clone().map_err(|err| err.to_string())?;
+        command.stdin(Stdio::null()).stdout(log).stderr(log_stderr);
+    }
+    command
+        .spawn()
+        .map_err(|err| format!("Can't run {}: {err}", options.command[0]))
+}
+
+// returns the exit code for tsm
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("ERROR: {err}");
+            print_run_usage_message();
+            return 2;
+        }
+    };
+    let (log_path, log) = match options.tui.then(create_log_file).transpose() {
+        Ok(Some((log_path, log))) => (Some(log_path), Some(log)),
+        Ok(None) => (None, None),
+        Err(err) => {
+            eprintln!("ERROR: {err}");
+            return 1;
+        }
+    };
+
+    let started = Instant::now();
+    let mut child = match spawn(&options, log) {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!("ERROR: {err}");
+            return 127;
+        }
+    };
+    ignore_interrupts();
+
+    let mut profiler = match Profiler::new(child.id(), options.interval, options.output.as_deref()) {
+        Ok(profiler) => profiler,
+        Err(err) => {
+            eprintln!("ERROR: {err}");
+            child.kill().ok();
+            child.wait().ok();
+            return 1;
+        }
+    };
+
+    // leaving the tui with q keeps sampling until the command exits
+    if let Some(log_path) = &log_path {
+        match terminal::size() {
+            Ok((screen_width, screen_height)) => {
+                let config = get_run_config(child.id(), log_path, screen_width, screen_height);
+                crate::run_ui(config, screen_width, screen_height, None, |_ui| {
+                    profiler.sample_if_due(started);
+                    matches!(child.try_wait(), Ok(None))
+                });
+            }
+            Err(err) => eprintln!("ERROR: Can't get terminal size: {err}"),
+        }
+    }
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {
+                profiler.sample_if_due(started);
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Err(err) => {
+                eprintln!("ERROR: Can't wait for the command: {err}");
+                return 1;
+            }
+        }
+    };
+
+    let (code, status) = get_exit_code(&status);
+    profiler.print_summary(&options.command.join(" "), &status, started.elapsed().as_secs_f64());
+    if let Some(log_path) = &log_path {
+        eprintln!("Output:   {}", log_path.display());
+    }
+    if let Some(output) = &options.output {
+        eprintln!("Samples written to {}", output.display());
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<RunOptions, String> {
+        parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>())
+    }
+
+    #[test]
+    fn takes_the_command_after_the_options() {
+        let options = parse(&["--tui", "--interval", "0.5", "--", "python3", "--version"]).unwrap();
+        assert!(options.tui);
+        assert_eq!(options.interval, Duration::from_millis(500));
+        assert_eq!(options.command, ["python3", "--version"]);
+
+        // without `--` the first argument that isn't an option starts the command
+        let options = parse(&["--output", "run.csv", "make", "-j8"]).unwrap();
+        assert_eq!(options.output, Some(PathBuf::from("run.csv")));
+        assert_eq!(options.command, ["make", "-j8"]);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(parse(&["--interval", "soon", "ls"]).err().unwrap(), "--interval needs a number of seconds");
+        assert_eq!(parse(&["--interval", "0", "ls"]).err().unwrap(), "--interval needs a number of seconds");
+        assert_eq!(parse(&["--interval"]).err().unwrap(), "--interval needs a number of seconds");
+        assert_eq!(parse(&["--verbose", "ls"]).err().unwrap(), "Unknown option \"--verbose\"");
+        assert_eq!(parse(&["--tui", "--"]).err().unwrap(), "No command to run");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_code_is_the_code_or_128_plus_the_signal() {
+        use std::os::unix::process::ExitStatusExt;
+
+        assert_eq!(get_exit_code(&ExitStatus::from_raw(3 << 8)), (3, "exited with code 3".to_string()));
+        assert_eq!(get_exit_code(&ExitStatus::from_raw(9)), (137, "killed by signal 9".to_string()));
+    }
+
+    #[test]
+    fn column_keeps_average_peak_and_total() {
+        let mut column = Column::new("read_bytes_per_second", "Read", ColumnKind::Rate);
+        column.push(100.0, 1.0);
+        column.push(300.0, 0.5);
+        column.push(200.0, 2.0);
+        assert_eq!(column.sum / column.count as f64, 200.0);
+        assert_eq!(column.peak, 300.0);
+        // rates are integrated over the time between samples
+        assert_eq!(column.total, 100.0 + 150.0 + 400.0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn log_file_is_new_in_a_private_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (path, _file) = create_log_file().unwrap();
+        let directory = path.parent().unwrap().to_path_buf();
+        assert_eq!(std::fs::metadata(&directory).unwrap().permissions().mode() & 0o777, 0o700);
+        assert!(OpenOptions::new().write(true).create_new(true).open(&path).is_err());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_config::SensorsTileOptions;
+use crate::patterns::is_included;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+use crate::sysfs::{get_sorted_dirs, read_number, read_trimmed};
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum SensorKind {
+    // °C
+    Temperature,
+    // RPM
+    Fan,
+    // V
+    Voltage,
+}
+
+pub struct SensorReading {
+    pub label: String,
+    pub kind: SensorKind,
+    pub value: f64,
+    // kernel provided thresholds, used as the scale of the bar
+    pub max: Option<f64>,
+    pub crit: Option<f64>,
+}
+
+pub struct SensorChip {
+    pub name: String,
+    pub readings: Vec<SensorReading>,
+}
+
+pub struct SensorsInfo {
+    sysfs_root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    thermal_zones: bool,
+    pub chips: Vec<SensorChip>,
+}
+
+impl SensorsInfo {
+    pub fn new(options: &SensorsTileOptions) -> Self {
+        let mut sensors_info = SensorsInfo {
+            sysfs_root: PathBuf::from(&options.sysfs_root),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            thermal_zones: options.thermal_zones,
+            chips: Vec::new(),
+        };
+        sensors_info.update();
+        sensors_info
+    }
+
+    // filled by `replay`, include and exclude patterns still apply
+    pub fn for_replay(options: &SensorsTileOptions) -> Self {
+        SensorsInfo {
+            sysfs_root: PathBuf::new(),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            thermal_zones: options.thermal_zones,
+            chips: Vec::new(),
+        }
+    }
+
+    // "sensor.coretemp/Core 0.celsius", thresholds are not recorded
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("sensor", tile);
+        let units = [("celsius", SensorKind::Temperature), ("rpm", SensorKind::Fan), ("volts", SensorKind::Voltage)];
+        let mut chips: Vec<SensorChip> = Vec::new();
+        for instance in sample.get_instances(&collector) {
+            let Some((name, label)) = instance.split_once('/') else {
+                continue;
+            };
+            if !is_included(name, &self.include, &self.exclude) {
+                continue;
+            }
+            for (unit, kind) in units {
+                let Some(value) = sample.get_number(&format!("{collector}.{instance}.{unit}")) else {
+                    continue;
+                };
+                let reading = SensorReading {
+                    label: label.to_string(),
+                    kind,
+                    value,
+                    max: None,
+                    crit: None,
+                };
+                match chips.iter_mut().find(|chip| chip.name == name) {
+                    Some(chip) => chip.readings.push(reading),
+                    None => chips.push(SensorChip {
+                        name: name.to_string(),
+                        readings: vec![reading],
+                    }),
+                }
+            }
+        }
+        self.chips = chips;
+    }
+
+    pub fn update(&mut self) {
+        let mut chips: Vec<SensorChip> = Vec::new();
+        // hwmon names before numbering, "nvme" for "nvme #2"
+        let mut chip_names: Vec<String> = Vec::new();
+
+        for hwmon_dir in get_sorted_dirs(&self.sysfs_root.join("class/hwmon"), "hwmon") {
+            let name = read_trimmed(&hwmon_dir.join("name")).unwrap_or_else(|| "unknown".to_string());
+            if !is_included(&name, &self.include, &self.exclude) {
+                continue;
+            }
+
+            let mut readings = Vec::new();
+            readings.extend(read_hwmon_readings(&hwmon_dir, "temp", SensorKind::Temperature));
+            readings.extend(read_hwmon_readings(&hwmon_dir, "fan", SensorKind::Fan));
+            readings.extend(read_hwmon_readings(&hwmon_dir, "in", SensorKind::Voltage));
+            if readings.is_empty() {
+                continue;
+            }
+
+            // several nvme drives have the same chip name
+            let chip_name = match chip_names.iter().filter(|chip_name| **chip_name == name).count() {
+                0 => name.clone(),
+                count => format!("{} #{}", name, count + 1),
+            };
+            chip_names.push(name);
+            chips.push(SensorChip { name: chip_name, readings });
+        }
+
+        if self.thermal_zones && is_included("thermal", &self.include, &self.exclude) {
+            let readings = self.read_thermal_zones();
+            if !readings.is_empty() {
+                chips.push(SensorChip {
+                    name: "thermal".to_string(),
+                    readings,
+                });
+            }
+        }
+        self.chips = chips;
+    }
+
+    fn read_thermal_zones(&self) -> Vec<SensorReading> {
+        let mut readings = Vec::new();
+
+        for zone_dir in get_sorted_dirs(&self.sysfs_root.join("class/thermal"), "thermal_zone") {
+            let Some(temperature) = read_number(&zone_dir.join("temp")) else {
+                continue;
+            };
+            let label = read_trimmed(&zone_dir.join("type")).unwrap_or_else(|| "zone".to_string());
+
+            // trip_point_<N>_type is one of active, passive, hot or critical
+            let mut max = None;
+            let mut crit = None;
+            for trip_index in 0.. {
+                let Some(trip_type) = read_trimmed(&zone_dir.join(format!("trip_point_{trip_index}_type"))) else {
+                    break;
+                };
+                let trip_temperature = read_number(&zone_dir.join(format!("trip_point_{trip_index}_temp")));
+                match trip_type.as_str() {
+                    "critical" => crit = trip_temperature.map(|value| value / 1000.0),
+                    "hot" => max = trip_temperature.map(|value| value / 1000.0),
+                    _ => (),
+                }
+            }
+
+            readings.push(SensorReading {
+                label,
+                kind: SensorKind::Temperature,
+                value: temperature / 1000.0,
+                max,
+                crit,
+            });
+        }
+        readings
+    }
+}
+
+// hwmon files are <prefix><N>_input, <prefix><N>_label, <prefix><N>_max and <prefix><N>_crit
+fn read_hwmon_readings(hwmon_dir: &Path, prefix: &str, kind: SensorKind) -> Vec<SensorReading> {
+    // millidegrees, RPM and millivolts
+    let scale = match kind {
+        SensorKind::Temperature => 1000.0,
+        SensorKind::Fan => 1.0,
+        SensorKind::Voltage => 1000.0,
+    };
+
+    let mut indices: Vec<u32> = match fs::read_dir(hwmon_dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                file_name
+                    .strip_prefix(prefix)?
+                    .strip_suffix("_input")?
+                    .parse::<u32>()
+                    .ok()
+            })
+            .collect(),
+        Err(_err) => Vec::new(),
+    };
+    indices.sort();
+
+    let mut readings = Vec::new();
+    for index in indices {
+        let file = |suffix: &str| hwmon_dir.join(format!("{prefix}{index}_{suffix}"));
+        let Some(value) = read_number(&file("input")) else {
+            continue;
+        };
+        readings.push(SensorReading {
+            label: read_trimmed(&file("label")).unwrap_or_else(|| format!("{prefix}{index}")),
+            value: value / scale,
+            max: read_number(&file("max")).map(|max| max / scale),
+            crit: read_number(&file("crit")).map(|crit| crit / scale),
+            kind,
+        });
+    }
+    readings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+
+    fn read_sensors(fixture: &Fixture, exclude: &[&str]) -> SensorsInfo {
+        SensorsInfo::new(&SensorsTileOptions {
+            exclude: exclude.iter().map(|pattern| pattern.to_string()).collect(),
+            sysfs_root: fixture.root(),
+            ..SensorsTileOptions::default()
+        })
+    }
+
+    // chip name, label, value, max and crit
+    type Reading<'a> = (&'a str, &'a str, f64, Option<f64>, Option<f64>);
+
+    fn get_readings(sensors_info: &SensorsInfo) -> Vec<Reading<'_>> {
+        sensors_info
+            .chips
+            .iter()
+            .flat_map(|chip| {
+                let name = chip.name.as_str();
+                chip.readings
+                    .iter()
+                    .map(move |reading| (name, reading.label.as_str(), reading.value, reading.max, reading.crit))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_hwmon_chips_and_labels() {
+        let fixture = Fixture::new();
+        fixture
+            .write("class/hwmon/hwmon10/name", "nct6775\n")
+            .write("class/hwmon/hwmon10/fan2_input", "900\n")
+            .write("class/hwmon/hwmon10/in0_input", "1200\n")
+            .write("class/hwmon/hwmon10/in0_label", "Vcore\n")
+            .write("class/hwmon/hwmon2/name", "coretemp\n")
+            .write("class/hwmon/hwmon2/temp1_input", "45000\n")
+            .write("class/hwmon/hwmon2/temp1_label", "Package id 0\n")
+            .write("class/hwmon/hwmon2/temp1_max", "80000\n")
+            .write("class/hwmon/hwmon2/temp1_crit", "100000\n")
+            .write("class/hwmon/hwmon2/temp10_input", "41000\n")
+            .write("class/hwmon/hwmon2/temp2_input", "43000\n")
+            // chips without readings are left out
+            .write("class/hwmon/hwmon3/name", "acpi_fan\n");
+
+        let sensors_info = read_sensors(&fixture, &["thermal"]);
+        assert_eq!(
+            get_readings(&sensors_info),
+            [
+                ("coretemp", "Package id 0", 45.0, Some(80.0), Some(100.0)),
+                ("coretemp", "temp2", 43.0, None, None),
+                ("coretemp", "temp10", 41.0, None, None),
+                ("nct6775", "fan2", 900.0, None, None),
+                ("nct6775", "Vcore", 1.2, None, None),
+            ]
+        );
+        assert!(sensors_info.chips[1].readings[0].kind == SensorKind::Fan);
+        assert!(sensors_info.chips[1].readings[1].kind == SensorKind::Voltage);
+    }
+
+    #[test]
+    fn numbers_chips_with_the_same_name() {
+        let fixture = Fixture::new();
+        fixture
+            .write("class/hwmon/hwmon0/name", "nvme\n")
+            .write("class/hwmon/hwmon0/temp1_input", "38000\n")
+            .write("class/hwmon/hwmon1/name", "nvme\n")
+            .write("class/hwmon/hwmon1/temp1_input", "40000\n")
+            .write("class/hwmon/hwmon2/name", "nvme\n")
+            .write("class/hwmon/hwmon2/temp1_input", "42000\n")
+            // a longer name with the same start is another chip
+            .write("class/hwmon/hwmon3/name", "nvme_pci\n")
+            .write("class/hwmon/hwmon3/temp1_input", "44000\n")
+            .write("class/hwmon/hwmon4/name", "nvme\n")
+            .write("class/hwmon/hwmon4/temp1_input", "46000\n");
+
+        let names: Vec<String> = read_sensors(&fixture, &[]).chips.into_iter().map(|chip| chip.name).collect();
+        assert_eq!(names, ["nvme", "nvme #2", "nvme #3", "nvme_pci", "nvme #4"]);
+    }
+
+    #[test]
+    fn reads_thermal_zones_with_trip_points() {
+        let fixture = Fixture::new();
+        fixture
+            .write("class/thermal/thermal_zone0/type", "x86_pkg_temp\n")
+            .write("class/thermal/thermal_zone0/temp", "52000\n")
+            .write("class/thermal/thermal_zone0/trip_point_0_type", "passive\n")
+            .write("class/thermal/thermal_zone0/trip_point_0_temp", "90000\n")
+            .write("class/thermal/thermal_zone0/trip_point_1_type", "hot\n")
+            .write("class/thermal/thermal_zone0/trip_point_1_temp", "95000\n")
+            .write("class/thermal/thermal_zone0/trip_point_2_type", "critical\n")
+            .write("class/thermal/thermal_zone0/trip_point_2_temp", "105000\n")
+            // cooling devices are in the same class
+            .write("class/thermal/cooling_device0/type", "Processor\n");
+
+        assert_eq!(
+            get_readings(&read_sensors(&fixture, &[])),
+            [("thermal", "x86_pkg_temp", 52.0, Some(95.0), Some(105.0))]
+        );
+        assert!(read_sensors(&fixture, &["thermal"]).chips.is_empty());
+    }
+}
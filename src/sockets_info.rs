@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::file_config::{SocketsSort, SocketsTileOptions};
+use crate::patterns::is_included;
+use crate::sysfs::read_trimmed;
+
+// index is the state number from /proc/net/tcp
+pub const TCP_STATES: [&str; 12] = [
+    "", "ESTAB", "SYN_SENT", "SYN_RECV", "FIN_WAIT1", "FIN_WAIT2", "TIME_WAIT", "CLOSE", "CLOSE_WAIT", "LAST_ACK",
+    "LISTEN", "CLOSING",
+];
+const TCP_LISTEN: usize = 10;
+// unconnected udp sockets are reported as TCP_CLOSE
+const UDP_UNCONNECTED: usize = 7;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SocketProtocol {
+    Tcp,
+    Udp,
+}
+
+struct SocketEntry {
+    protocol: SocketProtocol,
+    local_port: u16,
+    state: usize,
+    inode: u64,
+}
+
+// sockets of one process, sockets without a visible owner are collected with pid None
+pub struct SocketOwner {
+    pub pid: Option<u32>,
+    pub name: String,
+    // tcp sockets except listening ones
+    pub tcp: usize,
+    // connected udp sockets
+    pub udp: usize,
+    // like "22/tcp" and "53/udp"
+    pub listening: Vec<String>,
+}
+
+pub struct SocketsInfo {
+    procfs_root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    show_unowned: bool,
+    sort_by: SocketsSort,
+    interval: Duration,
+    last_refresh: Option<Instant>,
+    // socket count for every TCP_STATES entry
+    pub tcp_states: [usize; TCP_STATES.len()],
+    pub udp_count: usize,
+    pub owners: Vec<SocketOwner>,
+}
+
+impl SocketsInfo {
+    pub fn new(options: &SocketsTileOptions) -> Self {
+        let mut sockets_info = SocketsInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            show_unowned: options.show_unowned,
+            sort_by: options.sort_by,
+            interval: Duration::from_secs(options.interval),
+            last_refresh: None,
+            tcp_states: [0; TCP_STATES.len()],
+            udp_count: 0,
+            owners: Vec::new(),
+        };
+        sockets_info.update();
+        sockets_info
+    }
+
+    // walking every /proc/<pid>/fd is expensive, so it's done once per interval
+    pub fn update(&mut self) {
+        if self.last_refresh.is_some_and(|last_refresh| last_refresh.elapsed() < self.interval) {
+            return;
+        }
+        self.last_refresh = Some(Instant::now());
+
+        let mut sockets: Vec<SocketEntry> = Vec::new();
+        for (file, protocol) in [
+            ("tcp", SocketProtocol::Tcp),
+            ("tcp6", SocketProtocol::Tcp),
+            ("udp", SocketProtocol::Udp),
+            ("udp6", SocketProtocol::Udp),
+        ] {
+            let content = fs::read_to_string(self.procfs_root.join("net").join(file)).unwrap_or_default();
+            sockets.extend(content.lines().skip(1).filter_map(|line| parse_socket_line(line, protocol)));
+        }
+
+        self.tcp_states = [0; TCP_STATES.len()];
+        self.udp_count = 0;
+        for socket in &sockets {
+            match socket.protocol {
+                SocketProtocol::Tcp => self.tcp_states[socket.state.min(TCP_STATES.len() - 1)] += 1,
+                SocketProtocol::Udp => self.udp_count += 1,
+            }
+        }
+
+        let inode_owners = self.get_inode_owners();
+        let mut owners: HashMap<Option<u32>, SocketOwner> = HashMap::new();
+        for socket in &sockets {
+            // TIME_WAIT sockets have no inode and no owner anymore
+            let pid = inode_owners.get(&socket.inode).copied();
+            let owner = owners.entry(pid).or_insert_with(|| SocketOwner {
+                pid,
+                name: match pid {
+                    Some(pid) => read_trimmed(&self.procfs_root.join(format!("{pid}/comm"))).unwrap_or_default(),
+                    None => "?".to_string(),
+                },
+                tcp: 0,
+                udp: 0,
+                listening: Vec::new(),
+            });
+
+            match (socket.protocol, socket.state) {
+                (SocketProtocol::Tcp, TCP_LISTEN) => owner.listening.push(format!("{}/tcp", socket.local_port)),
+                (SocketProtocol::Tcp, _) => owner.tcp += 1,
+                (SocketProtocol::Udp, UDP_UNCONNECTED) => owner.listening.push(format!("{}/udp", socket.local_port)),
+                (SocketProtocol::Udp, _) => owner.udp += 1,
+            }
+        }
+
+        let mut owners: Vec<SocketOwner> = owners
+            .into_values()
+            // "?" would match every one letter name as a pattern
+            .filter(|owner| match owner.pid {
+                Some(_) => is_included(&owner.name, &self.include, &self.exclude),
+                None => self.show_unowned,
+            })
+            .collect();
+        for owner in &mut owners {
+            // ipv4 and ipv6 sockets often listen on the same port
+            owner.listening.sort_by_key(|port| port.split('/').next().and_then(|port| port.parse::<u16>().ok()));
+            owner.listening.dedup();
+        }
+        match self.sort_by {
+            SocketsSort::Connections => owners.sort_by_key(|owner| std::cmp::Reverse(owner.tcp + owner.udp)),
+            SocketsSort::Pid => owners.sort_by_key(|owner| owner.pid),
+            SocketsSort::Name => owners.sort_by(|first, second| first.name.cmp(&second.name)),
+        }
+        self.owners = owners;
+    }
+
+    // fd links look like "socket:[12345]"
+    fn get_inode_owners(&self) -> HashMap<u64, u32> {
+        let mut inode_owners = HashMap::new();
+        let Ok(entries) = fs::read_dir(&self.procfs_root) else {
+            return inode_owners;
+        };
+        for entry in entries.flatten() {
+            let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            // fds of other users' processes are not readable without privileges
+            let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+                continue;
+            };
+            for fd in fds.flatten() {
+                let Ok(target) = fs::read_link(fd.path()) else {
+                    continue;
+                };
+                let inode = target
+                    .to_string_lossy()
+                    .strip_prefix("socket:[")
+                    .and_then(|inode| inode.strip_suffix(']'))
+                    .and_then(|inode| inode.parse::<u64>().ok());
+                if let Some(inode) = inode {
+                    inode_owners.entry(inode).or_insert(pid);
+                }
+            }
+        }
+        inode_owners
+    }
+}
+
+// "0: 0100007F:BC8F 00000000:0000 0A 00000000:00000000 00:00000000 00000000 65534 0 1032 1 ..."
+fn parse_socket_line(line: &str, protocol: SocketProtocol) -> Option<SocketEntry> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (_, local_port) = fields.get(1)?.rsplit_once(':')?;
+    Some(SocketEntry {
+        protocol,
+        local_port: u16::from_str_radix(local_port, 16).ok()?,
+        state: usize::from_str_radix(fields.get(3)?, 16).ok()?,
+        inode: fields.get(9)?.parse().ok()?,
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+
+    const HEADER: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  inode\n";
+
+    fn socket_line(local_port: u16, state: usize, inode: u64) -> String {
+        let addresses = format!("0100007F:{local_port:04X} 0100007F:9C40");
+        format!("   0: {addresses} {state:02X} 00000000:00000000 00:00000000 00000000  1000 0 {inode} 1\n")
+    }
+
+    #[test]
+    fn counts_tcp_states_and_groups_sockets_by_owner() {
+        let fixture = Fixture::new();
+        let tcp = [
+            socket_line(22, 0x0A, 1001),
+            socket_line(22, 0x01, 1002),
+            socket_line(443, 0x06, 0),
+            socket_line(51000, 0x01, 1003),
+        ];
+        let udp = [socket_line(53, 0x07, 1005), socket_line(40000, 0x01, 1006)];
+        fixture
+            .write("net/tcp", &(HEADER.to_string() + &tcp.concat()))
+            .write("net/tcp6", &(HEADER.to_string() + &socket_line(22, 0x0A, 1004)))
+            .write("net/udp", &(HEADER.to_string() + &udp.concat()));
+        fixture.write_process(100, 1, "sshd", 0).write_socket_fds(100, &[1001, 1002, 1004]);
+        fixture.write_process(200, 1, "curl", 0).write_socket_fds(200, &[1003, 1006]);
+        fixture.write_process(300, 1, "dnsmasq", 0).write_socket_fds(300, &[1005]);
+
+        let sockets_info = SocketsInfo::new(&SocketsTileOptions {
+            sort_by: SocketsSort::Pid,
+            procfs_root: fixture.root(),
+            ..SocketsTileOptions::default()
+        });
+        let states: Vec<(&str, usize)> = TCP_STATES
+            .iter()
+            .zip(sockets_info.tcp_states)
+            .filter(|(_, count)| *count > 0)
+            .map(|(state, count)| (*state, count))
+            .collect();
+        assert_eq!(states, [("ESTAB", 2), ("TIME_WAIT", 1), ("LISTEN", 2)]);
+        assert_eq!(sockets_info.udp_count, 2);
+
+        let owners: Vec<(Option<u32>, &str, usize, usize, String)> = sockets_info
+            .owners
+            .iter()
+            .map(|owner| (owner.pid, owner.name.as_str(), owner.tcp, owner.udp, owner.listening.join(" ")))
+            .collect();
+        // TIME_WAIT sockets have no owner, the ipv4 and ipv6 listeners of sshd are one port
+        assert_eq!(
+            owners,
+            [
+                (None, "?", 1, 0, String::new()),
+                (Some(100), "sshd", 1, 0, "22/tcp".to_string()),
+                (Some(200), "curl", 1, 1, String::new()),
+                (Some(300), "dnsmasq", 0, 0, "53/udp".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_owners_by_name() {
+        let fixture = Fixture::new();
+        fixture.write("net/tcp", &(HEADER.to_string() + &socket_line(22, 0x0A, 1001) + &socket_line(443, 0x06, 0)));
+        fixture.write_process(100, 1, "sshd", 0).write_socket_fds(100, &[1001]);
+        fixture.write_process(200, 1, "X", 0).write_socket_fds(200, &[1002]);
+        fixture.write("net/udp", &(HEADER.to_string() + &socket_line(40000, 0x01, 1002)));
+        let get_names = |options: SocketsTileOptions| -> Vec<String> {
+            let sockets_info = SocketsInfo::new(&SocketsTileOptions {
+                sort_by: SocketsSort::Name,
+                procfs_root: fixture.root(),
+                ..options
+            });
+            // states are counted before the filter
+            assert_eq!(sockets_info.tcp_states[6], 1);
+            sockets_info.owners.iter().map(|owner| owner.name.clone()).collect()
+        };
+
+        let hide_unowned = SocketsTileOptions {
+            show_unowned: false,
+            ..SocketsTileOptions::default()
+        };
+        assert_eq!(get_names(hide_unowned), ["X", "sshd"]);
+        // patterns don't apply to the unowned sockets, and one letter names don't match them
+        let exclude_sshd = SocketsTileOptions {
+            exclude: vec!["ssh*".to_string()],
+            ..SocketsTileOptions::default()
+        };
+        assert_eq!(get_names(exclude_sshd), ["?", "X"]);
+        let include_one_letter = SocketsTileOptions {
+            include: vec!["?".to_string()],
+            show_unowned: false,
+            ..SocketsTileOptions::default()
+        };
+        assert_eq!(get_names(include_one_letter), ["X"]);
+    }
+}
@@ -0,0 +1,32 @@
+// helpers for reading procfs and sysfs files
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// entries like hwmon0..hwmonN sorted by number instead of by name
+pub fn get_sorted_dirs(path: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut dirs: Vec<(u32, PathBuf)> = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| {
+                let index = entry
+                    .file_name()
+                    .to_string_lossy()
+                    .strip_prefix(prefix)?
+                    .parse::<u32>()
+                    .ok()?;
+                Some((index, entry.path()))
+            })
+            .collect(),
+        Err(_err) => Vec::new(),
+    };
+    dirs.sort_by_key(|(index, _)| *index);
+    dirs.into_iter().map(|(_, path)| path).collect()
+}
+
+pub fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|content| content.trim().to_string())
+}
+
+pub fn read_number(path: &Path) -> Option<f64> {
+    read_trimmed(path)?.parse::<f64>().ok()
+}
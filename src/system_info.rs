@@ -0,0 +1,266 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::file_config::SystemTileOptions;
+use crate::recorder::get_collector_name;
+use crate::replay::RecordedSample;
+
+// one line of /proc/pressure/<resource>, percent of time stalled
+#[derive(Default, Clone, Copy)]
+pub struct PressureLine {
+    pub avg10: f64,
+    pub avg60: f64,
+}
+
+pub struct Pressure {
+    pub resource: &'static str,
+    pub some: Option<PressureLine>,
+    pub full: Option<PressureLine>,
+}
+
+pub struct SystemInfo {
+    procfs_root: PathBuf,
+    last_update: Instant,
+    // totals since boot
+    pub context_switches_total: Option<u64>,
+    pub interrupts_total: Option<u64>,
+    pub cpu_count: usize,
+    pub load: [f64; 3],
+    pub uptime: f64,
+    pub tasks_total: u64,
+    pub tasks_running: u64,
+    pub tasks_blocked: u64,
+    // per second
+    pub context_switches: f64,
+    pub interrupts: f64,
+    // empty on kernels without PSI
+    pub pressure: Vec<Pressure>,
+}
+
+impl SystemInfo {
+    pub fn new(options: &SystemTileOptions) -> Self {
+        let mut system_info = SystemInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            last_update: Instant::now(),
+            context_switches_total: None,
+            interrupts_total: None,
+            cpu_count: 1,
+            load: [0.0; 3],
+            uptime: 0.0,
+            tasks_total: 0,
+            tasks_running: 0,
+            tasks_blocked: 0,
+            context_switches: 0.0,
+            interrupts: 0.0,
+            pressure: Vec::new(),
+        };
+        system_info.update();
+        system_info
+    }
+
+    // filled by `replay`
+    pub fn for_replay() -> Self {
+        SystemInfo {
+            procfs_root: PathBuf::new(),
+            last_update: Instant::now(),
+            context_switches_total: None,
+            interrupts_total: None,
+            cpu_count: 1,
+            load: [0.0; 3],
+            uptime: 0.0,
+            tasks_total: 0,
+            tasks_running: 0,
+            tasks_blocked: 0,
+            context_switches: 0.0,
+            interrupts: 0.0,
+            pressure: Vec::new(),
+        }
+    }
+
+    // uptime, total tasks and pressure are not recorded, cpus are counted from the cpu values
+    pub fn replay(&mut self, sample: &RecordedSample, tile: usize) {
+        let collector = get_collector_name("system", tile);
+        let get_number = |field: &str| sample.get_number(&format!("{collector}.{field}")).unwrap_or(0.0);
+        self.load = [get_number("load_1"), get_number("load_5"), get_number("load_15")];
+        self.tasks_running = get_number("tasks_running") as u64;
+        self.tasks_blocked = get_number("tasks_blocked") as u64;
+        self.context_switches = get_number("context_switches_per_second");
+        self.interrupts = get_number("interrupts_per_second");
+        self.cpu_count = sample.get_count("cpu", "usage_percent").max(1);
+    }
+
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        // "0.14 0.09 0.06 1/71 4407", running and total tasks are taken from /proc/stat below
+        let loadavg = self.read("loadavg");
+        let fields: Vec<&str> = loadavg.split_whitespace().collect();
+        for (ind, load) in self.load.iter_mut().enumerate() {
+            *load = fields.get(ind).and_then(|field| field.parse().ok()).unwrap_or(0.0);
+        }
+        self.tasks_total = fields
+            .get(3)
+            .and_then(|field| field.split_once('/'))
+            .and_then(|(_, total)| total.parse().ok())
+            .unwrap_or(0);
+
+        self.uptime = self
+            .read("uptime")
+            .split_whitespace()
+            .next()
+            .and_then(|uptime| uptime.parse().ok())
+            .unwrap_or(0.0);
+
+        let stat = self.read("stat");
+        let mut cpu_count = 0;
+        for line in stat.lines() {
+            let mut fields = line.split_whitespace();
+            let name = fields.next().unwrap_or_default();
+            let value = fields.next().and_then(|value| value.parse::<u64>().ok());
+            match name {
+                "ctxt" => self.context_switches = Self::rate(&mut self.context_switches_total, value, elapsed),
+                // first number is the total of all interrupts
+                "intr" => self.interrupts = Self::rate(&mut self.interrupts_total, value, elapsed),
+                "procs_running" => self.tasks_running = value.unwrap_or(0),
+                "procs_blocked" => self.tasks_blocked = value.unwrap_or(0),
+                name if name.starts_with("cpu") && name != "cpu" => cpu_count += 1,
+                _ => (),
+            }
+        }
+        self.cpu_count = cpu_count.max(1);
+
+        self.pressure = ["cpu", "memory", "io"]
+            .into_iter()
+            .filter_map(|resource| {
+                let content = fs::read_to_string(self.procfs_root.join(format!("pressure/{resource}"))).ok()?;
+                Some(parse_pressure(resource, &content))
+            })
+            .collect();
+    }
+
+    fn read(&self, file: &str) -> String {
+        fs::read_to_string(self.procfs_root.join(file)).unwrap_or_default()
+    }
+
+    fn rate(previous: &mut Option<u64>, current: Option<u64>, elapsed: f64) -> f64 {
+        let rate = match (*previous, current) {
+            (Some(previous), Some(current)) if elapsed > 0.0 => current.saturating_sub(previous) as f64 / elapsed,
+            _ => 0.0,
+        };
+        *previous = current;
+        rate
+    }
+}
+
+// "some avg10=2.09 avg60=2.20 avg300=1.71 total=20239253"
+fn parse_pressure(resource: &'static str, content: &str) -> Pressure {
+    let mut pressure = Pressure {
+        resource,
+        some: None,
+        full: None,
+    };
+
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next();
+        let mut pressure_line = PressureLine::default();
+        for field in fields {
+            match field.split_once('=') {
+                Some(("avg10", value)) => pressure_line.avg10 = value.parse().unwrap_or(0.0),
+                Some(("avg60", value)) => pressure_line.avg60 = value.parse().unwrap_or(0.0),
+                _ => (),
+            }
+        }
+        match kind {
+            Some("some") => pressure.some = Some(pressure_line),
+            Some("full") => pressure.full = Some(pressure_line),
+            _ => (),
+        }
+    }
+    pressure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::time::Duration;
+
+    fn write_stat(fixture: &Fixture, ctxt: u64, intr: u64) {
+        fixture.write(
+            "stat",
+            &format!(
+                "cpu  10 0 10 100 0 0 0 0 0 0\ncpu0 5 0 5 50 0 0 0 0 0 0\ncpu1 5 0 5 50 0 0 0 0 0 0\n\
+                 intr {intr} 1 2 3\nctxt {ctxt}\nprocs_running 3\nprocs_blocked 1\n"
+            ),
+        );
+    }
+
+    #[test]
+    fn reads_load_tasks_and_pressure() {
+        let fixture = Fixture::new();
+        write_stat(&fixture, 1000, 500);
+        fixture
+            .write("loadavg", "0.14 0.09 0.06 3/71 4407\n")
+            .write("uptime", "3600.52 7000.10\n")
+            .write("pressure/memory", "some avg10=2.09 avg60=2.20 avg300=1.71 total=20239253\n\
+                                      full avg10=1.00 avg60=0.50 avg300=0.25 total=10000\n")
+            // older kernels have no full line for cpu
+            .write("pressure/cpu", "some avg10=0.50 avg60=0.25 avg300=0.10 total=5000\n");
+        let system_info = SystemInfo::new(&SystemTileOptions {
+            procfs_root: fixture.root(),
+            ..SystemTileOptions::default()
+        });
+
+        assert_eq!(system_info.load, [0.14, 0.09, 0.06]);
+        assert_eq!(system_info.uptime, 3600.52);
+        assert_eq!((system_info.tasks_total, system_info.tasks_running, system_info.tasks_blocked), (71, 3, 1));
+        assert_eq!(system_info.cpu_count, 2);
+        assert_eq!((system_info.context_switches, system_info.interrupts), (0.0, 0.0));
+
+        let resources: Vec<&str> = system_info.pressure.iter().map(|pressure| pressure.resource).collect();
+        assert_eq!(resources, ["cpu", "memory"]);
+        let cpu = &system_info.pressure[0];
+        assert_eq!(cpu.some.map(|some| (some.avg10, some.avg60)), Some((0.5, 0.25)));
+        assert!(cpu.full.is_none());
+        assert_eq!(system_info.pressure[1].full.map(|full| full.avg10), Some(1.0));
+    }
+
+    #[test]
+    fn parses_pressure_with_only_some() {
+        let pressure = parse_pressure("io", "some avg10=12.5 avg60=3.00 avg300=1.00 total=1\n");
+        assert_eq!(pressure.some.map(|some| (some.avg10, some.avg60)), Some((12.5, 3.0)));
+        assert!(pressure.full.is_none());
+        assert!(parse_pressure("io", "").some.is_none());
+    }
+
+    #[test]
+    fn turns_ctxt_and_intr_into_rates() {
+        let fixture = Fixture::new();
+        write_stat(&fixture, 1000, 500);
+        let mut system_info = SystemInfo::new(&SystemTileOptions {
+            procfs_root: fixture.root(),
+            ..SystemTileOptions::default()
+        });
+
+        write_stat(&fixture, 5000, 2500);
+        system_info.last_update -= Duration::from_secs(2);
+        system_info.update();
+        assert!((system_info.context_switches - 2000.0).abs() < 20.0);
+        assert!((system_info.interrupts - 1000.0).abs() < 10.0);
+
+        // counters that went backwards don't give a rate, the next one is taken from the new value
+        write_stat(&fixture, 100, 2600);
+        system_info.last_update -= Duration::from_secs(2);
+        system_info.update();
+        assert_eq!(system_info.context_switches, 0.0);
+        assert!((system_info.interrupts - 50.0).abs() < 1.0);
+
+        write_stat(&fixture, 300, 2600);
+        system_info.last_update -= Duration::from_secs(2);
+        system_info.update();
+        assert!((system_info.context_switches - 100.0).abs() < 1.0);
+    }
+}
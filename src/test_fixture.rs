@@ -0,0 +1,61 @@
+// fake procfs, sysfs and cgroupfs trees for the parser tests, removed when dropped
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+pub struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let root = std::env::temp_dir().join(format!("tsm-test-{}-{id}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        Fixture { root }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn root(&self) -> String {
+        self.root.to_string_lossy().into_owned()
+    }
+
+    // "net/dev" under the root, parents are created
+    pub fn write(&self, path: &str, content: &str) -> &Self {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        self
+    }
+
+    // "<pid>/stat", status, comm and cmdline of a sleeping process with 10 ticks of cpu time and 2 threads
+    pub fn write_process(&self, pid: u32, ppid: u32, name: &str, rss_kib: u64) -> &Self {
+        let stat = format!("{pid} ({name}) S {ppid} {pid} {pid} 0 -1 4194560 0 0 0 0 7 3 0 0 20 0 2 0 100 0 0");
+        self.write(&format!("{pid}/stat"), &stat)
+            .write(&format!("{pid}/status"), &format!("Name:\t{name}\nVmRSS:\t    {rss_kib} kB\n"))
+            .write(&format!("{pid}/comm"), &format!("{name}\n"))
+            .write(&format!("{pid}/cmdline"), &format!("{name}\0"))
+    }
+
+    // "<pid>/fd" with a link to every socket inode
+    #[cfg(unix)]
+    pub fn write_socket_fds(&self, pid: u32, inodes: &[u64]) -> &Self {
+        let fd_dir = self.root.join(format!("{pid}/fd"));
+        fs::create_dir_all(&fd_dir).unwrap();
+        for (fd, inode) in inodes.iter().enumerate() {
+            std::os::unix::fs::symlink(format!("socket:[{inode}]"), fd_dir.join(fd.to_string())).unwrap();
+        }
+        self
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
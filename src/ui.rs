@@ -1,36 +1,110 @@
 use crossterm::cursor::MoveTo;
+use crossterm::event::{KeyCode, KeyEvent};
 use crossterm::style::{Color, Print, ResetColor, SetForegroundColor, Stylize};
 use crossterm::{execute, terminal};
 
 use std::io::{stdout, Stdout};
+use std::mem::{discriminant, Discriminant};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
 
 use crate::gpu_info;
 use gpu_info::GpuAll;
 
 use crate::cpu_info;
-use cpu_info::CpuInfo;
+use cpu_info::{CpuInfo, CpuTimes};
+
+use crate::cpu_topology::{read_online_cpus, read_topology, CoreKind, CoreTopology};
+
+use crate::recorder::Sources;
+use crate::replay::{RecordedSample, Replay};
+
+use crate::net_info;
+use net_info::NetInfo;
+
+use crate::disk_info;
+use disk_info::DiskInfo;
+
+use crate::fs_info;
+use fs_info::FsInfo;
+
+use crate::sensors_info;
+use sensors_info::{SensorKind, SensorsInfo};
+
+use crate::battery_info;
+use battery_info::PowerSupplyInfo;
+
+use crate::cgroup_info;
+use cgroup_info::CgroupsInfo;
+
+use crate::system_info;
+use system_info::SystemInfo;
+
+use crate::container_info;
+use container_info::{ContainerAction, ContainersInfo};
+
+use crate::numa_info;
+use numa_info::NumaInfo;
+
+use crate::sockets_info;
+use sockets_info::{SocketsInfo, TCP_STATES};
+
+use crate::interrupts_info;
+use interrupts_info::InterruptsInfo;
+
+use crate::power_info;
+use power_info::PowerInfo;
+
+use crate::command_info;
+use command_info::{CommandInfo, CommandOutput};
+
+use crate::plugin_info;
+use plugin_info::{MetricKind, PluginInfo};
+
+use crate::prometheus_info;
+use prometheus_info::PrometheusInfo;
+
+use crate::log_info;
+use log_info::LogInfo;
+
+use crate::events_info;
+use events_info::{EventKind, EventsInfo};
+
+use crate::watch_info;
+use watch_info::WatchInfo;
 
 use crate::DeviceTile;
+use crate::file_config::{
+    BatteryTileOptions, CgroupsTileOptions, CommandFormat, CommandTileOptions, ContainersTileOptions, CpuGrouping,
+    CpuTileOptions, DiskIoTileOptions, EventsTileOptions, FilesystemsTileOptions, GpuTileOptions, InterruptsTileOptions,
+    LogTileOptions, NetworkTileOptions, NumaTileOptions, PluginTileOptions, PowerTileOptions, PrometheusDisplay,
+    PrometheusTileOptions, SensorsTileOptions, SocketsTileOptions, SystemTileOptions, WatchTileOptions,
+};
+use crate::history::History;
 
 extern crate unicode_width;
 use unicode_width::UnicodeWidthStr;
 
 
+// progress outside 0.0..1.0 is drawn as an empty or a full bar, lead and trail wider than the tile are cut
 pub fn calculate_progress_bar(
     width: u16,
     lead: String,
     progress_data: f64,
     trail: String,
 ) -> String {
+    let progress_data = if progress_data.is_nan() { 0.0 } else { progress_data.clamp(0.0, 1.0) };
     let mut progress_string = lead.to_owned();
     let mut symbol = String::from("|"); //  🐱  TODO pass symbol from app config
     
     let symbol_width: usize = symbol.width();
 
-    
-    let progress_bar_width = width  
-        - lead.as_str().width() as u16 
-        - trail.as_str().width() as u16;
+    let text_width = lead.as_str().width() + trail.as_str().width();
+    if text_width >= width as usize {
+        return truncate_to_width(&(lead + &trail), width);
+    }
+    let progress_bar_width = width as usize - text_width;
         
     
     //progress_bar_width = progress_bar_width / symbol_width as u16;
@@ -55,8 +129,8 @@ pub fn calculate_progress_bar(
     
     
     
-    if progress_bar_width as usize - load_width != 0 {
-    	let empty_width = progress_bar_width as usize - load_width.div_ceil(symbol_width) * symbol_width - 1;
+    if progress_bar_width - load_width != 0 {
+    	let empty_width = progress_bar_width.saturating_sub(load_width.div_ceil(symbol_width) * symbol_width);
     	progress_string += &" ".repeat(empty_width);
     }; 
         
@@ -64,11 +138,169 @@ pub fn calculate_progress_bar(
     progress_string
 }
 
-#[derive(Default, PartialEq)]
+// bar built from several colored parts, shares are 0.0..1.0 and drawn in the given order
+pub fn calculate_stacked_bar(width: u16, lead: String, segments: &[(f64, Color)], trail: String) -> String {
+    let text_width = lead.as_str().width() + trail.as_str().width();
+    if text_width >= width as usize {
+        return truncate_to_width(&(lead + &trail), width);
+    }
+    let bar_width = width as usize - text_width;
+
+    let mut bar_string = lead;
+    let mut filled = 0;
+    let mut cumulative = 0.0;
+    for (share, color) in segments {
+        // rounding cumulative share keeps the total length stable, the whole bar is 1.0 at most
+        let share = if share.is_nan() { 0.0 } else { share.clamp(0.0, 1.0) };
+        cumulative = (cumulative + share).min(1.0);
+        let end = ((cumulative * bar_width as f64).round() as usize).min(bar_width);
+        if end > filled {
+            bar_string += &"|".repeat(end - filled).with(*color).to_string();
+            filled = end;
+        }
+    }
+    bar_string += &" ".repeat(bar_width - filled);
+    bar_string + &trail
+}
+
+pub fn calculate_sparkline(width: u16, lead: String, history: &History, max_value: f64) -> String {
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let graph_width = (width as usize).saturating_sub(lead.as_str().width());
+    let values = history.values();
+    let skip = values.len().saturating_sub(graph_width);
+
+    // newest sample is on the right side
+    let mut graph = " ".repeat(graph_width - (values.len() - skip));
+    for value in values.iter().skip(skip) {
+        let level = if max_value > 0.0 {
+            ((value / max_value) * (LEVELS.len() - 1) as f64).round() as usize
+        } else {
+            0
+        };
+        graph.push(LEVELS[level.min(LEVELS.len() - 1)]);
+    }
+    lead + &graph
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0}{}", value, UNITS[unit])
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+// counts and rates without a unit, like 950, 12.3K, 1.2M
+pub fn format_count(value: f64) -> String {
+    if value >= 1e6 {
+        format!("{:.1}M", value / 1e6)
+    } else if value >= 1e3 {
+        format!("{:.1}K", value / 1e3)
+    } else {
+        format!("{:.0}", value)
+    }
+}
+
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d{}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h{}m", hours, minutes)
+    } else {
+        format!("{}m{}s", minutes, seconds % 60)
+    }
+}
+
+// year, month, day, hour, minute and second in the local time zone
+#[cfg(unix)]
+fn get_local_time(time: SystemTime) -> [i64; 6] {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as libc::time_t;
+    let mut local: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&seconds, &mut local) }.is_null() {
+        return [1970, 1, 1, 0, 0, 0];
+    }
+    [
+        local.tm_year as i64 + 1900,
+        local.tm_mon as i64 + 1,
+        local.tm_mday as i64,
+        local.tm_hour as i64,
+        local.tm_min as i64,
+        local.tm_sec as i64,
+    ]
+}
+
+// UTC without a time zone database, days to civil date from Howard Hinnant's algorithm
+#[cfg(not(unix))]
+fn get_local_time(time: SystemTime) -> [i64; 6] {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let days = seconds / 86400 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    [year, month, day, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60]
+}
+
+// "14:03:27"
+pub fn format_local_time(time: SystemTime) -> String {
+    let [_, _, _, hour, minute, second] = get_local_time(time);
+    format!("{hour:02}:{minute:02}:{second:02}")
+}
+
+// "2024-03-18 14:03:27"
+pub fn format_local_date_time(time: SystemTime) -> String {
+    let [year, month, day, ..] = get_local_time(time);
+    format!("{year}-{month:02}-{day:02} {}", format_local_time(time))
+}
+
+pub fn truncate_to_width(text: &str, width: u16) -> String {
+    let mut truncated = String::new();
+    for symbol in text.chars() {
+        if truncated.as_str().width() + symbol.to_string().as_str().width() > width as usize {
+            break;
+        }
+        truncated.push(symbol);
+    }
+    truncated
+}
+
+#[derive(PartialEq)]
 pub enum LayoutType {
-    #[default]
-    Cpu,
-    Gpu,
+    Cpu(CpuTileOptions),
+    Gpu(GpuTileOptions),
+    Network(NetworkTileOptions),
+    DiskIo(DiskIoTileOptions),
+    Filesystems(FilesystemsTileOptions),
+    Sensors(SensorsTileOptions),
+    Battery(BatteryTileOptions),
+    System(SystemTileOptions),
+    Cgroups(CgroupsTileOptions),
+    Containers(ContainersTileOptions),
+    Numa(NumaTileOptions),
+    Sockets(SocketsTileOptions),
+    Interrupts(InterruptsTileOptions),
+    Power(PowerTileOptions),
+    Command(CommandTileOptions),
+    Plugin(PluginTileOptions),
+    Prometheus(PrometheusTileOptions),
+    Log(LogTileOptions),
+    Events(EventsTileOptions),
+    Watch(WatchTileOptions),
 }
 
 pub struct LayoutBbox {
@@ -78,29 +310,175 @@ pub struct LayoutBbox {
     pub height: u16,
 }
 
+// a tile on the screen, placed and drawn by `Ui` every update
+pub trait Layout {
+    fn get_tile_index(&self) -> usize;
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox);
+
+    fn show_data(&mut self, stdout: &mut Stdout);
+
+    // Tab moves the focus between tiles that take keys, keys go to the focused one
+    fn takes_keys(&self) -> bool {
+        false
+    }
+
+    fn set_focused(&mut self, _focused: bool) {}
+
+    fn handle_key(&mut self, _key: KeyEvent) {}
+
+    // shown on the status line even when the tile is too small to notice
+    fn get_banner(&self) -> Option<String> {
+        None
+    }
+
+    // collectors the recorder takes its values from, so they match what is shown
+    fn add_record_sources<'a>(&'a self, _sources: &mut Sources<'a>) {}
+
+    // values of a recorded sample instead of this machine, tiles keep `replayed: Option<usize>` for it:
+    // the index of the tile among the replayed tiles of its type, None when showing this machine
+    fn replay(&mut self, _sample: &RecordedSample) {}
+}
+
 
 pub struct LayoutCPU {
+    tile_index: usize,
     layout_header: String,
     layout_bbox: LayoutBbox,
     layout_device: CpuInfo,
+    group_by: CpuGrouping,
+    replayed: Option<usize>,
+}
+
+// one line of the cpu list, group header is drawn above the core
+struct CpuRow {
+    header: Option<String>,
+    // position of the core in the collector, its kernel number is `core.cpu`
+    index: usize,
+    core: CoreTopology,
+    // marks SMT siblings of one physical core
+    bracket: &'static str,
+}
+
+// rows of the per-core layout, shared by cpu and interrupts tiles
+fn get_cpu_rows(mut cores: Vec<(usize, CoreTopology)>, group_by: CpuGrouping) -> Vec<CpuRow> {
+    if group_by == CpuGrouping::None {
+        return cores
+            .into_iter()
+            .map(|(index, core)| CpuRow { header: None, index, core, bracket: "" })
+            .collect();
+    }
+
+    let group_key = |core: &CoreTopology| match group_by {
+        CpuGrouping::Socket => Some(core.socket),
+        CpuGrouping::Numa => Some(core.numa_node.unwrap_or(0)),
+        _ => None,
+    };
+    cores.sort_by_key(|(_, core)| (group_key(core), core.socket, core.core_id, core.cpu));
+
+    let mut rows = Vec::new();
+    for (ind, (index, core)) in cores.iter().enumerate() {
+        let previous = ind.checked_sub(1).map(|previous| &cores[previous].1);
+        let next = cores.get(ind + 1).map(|(_, next)| next);
+
+        let header = match (group_key(core), group_by) {
+            (Some(key), group_by) if previous.map(group_key) != Some(Some(key)) => match group_by {
+                CpuGrouping::Socket => Some(format!("Socket {key}")),
+                _ => Some(format!("Node {key}")),
+            },
+            _ => None,
+        };
+
+        let is_sibling = |other: Option<&CoreTopology>| {
+            other.is_some_and(|other| {
+                other.socket == core.socket && other.core_id == core.core_id && group_key(other) == group_key(core)
+            })
+        };
+        let bracket = match (is_sibling(previous), is_sibling(next)) {
+            (false, false) => " ",
+            (false, true) => "┌",
+            (true, true) => "│",
+            (true, false) => "└",
+        };
+        rows.push(CpuRow { header, index: *index, core: *core, bracket });
+    }
+    rows
 }
 
 
 impl LayoutCPU {
-    fn new(layout_header: String, layout_bbox: LayoutBbox) -> Self {
+    const CPU_TIMES_LEGEND: [(&'static str, Color); 7] = [
+        ("usr", Color::Green),
+        ("nice", Color::Blue),
+        ("sys", Color::Red),
+        ("iowait", Color::DarkGrey),
+        ("irq", Color::Yellow),
+        ("steal", Color::Cyan),
+        ("guest", Color::Magenta),
+    ];
+
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &CpuTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
         LayoutCPU {
+            tile_index,
             layout_header: layout_header.bold().to_string(),
             layout_bbox,
-            layout_device: CpuInfo::new(),
+            layout_device: if replayed.is_some() { CpuInfo::for_replay() } else { CpuInfo::new(options) },
+            group_by: options.group_by,
+            replayed,
         }
     }
 
+    fn get_cpu_rows(&self) -> Vec<CpuRow> {
+        let cores: Vec<(usize, CoreTopology)> = self
+            .layout_device
+            .topology
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(index, _)| self.layout_device.is_cpu_visible(*index))
+            .collect();
+        get_cpu_rows(cores, self.group_by)
+    }
+
+    fn calculate_cpu_times_bar(width: u16, lead: String, times: &CpuTimes, frequency: &str) -> String {
+        let shares = [
+            times.user,
+            times.nice,
+            times.system,
+            times.iowait,
+            times.irq,
+            times.steal,
+            times.guest,
+        ];
+        let segments: Vec<(f64, Color)> = shares
+            .iter()
+            .zip(Self::CPU_TIMES_LEGEND.iter())
+            .map(|(share, (_, color))| (*share, *color))
+            .collect();
+        let total: f64 = shares.iter().sum();
+        calculate_stacked_bar(width, lead, &segments, format!("{:.2}%{}]", total * 100.0, frequency))
+    }
+}
+
+impl Layout for LayoutCPU {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
     fn set_position(&mut self, layout_bbox: LayoutBbox) {
         self.layout_bbox = layout_bbox;
     }
 
     fn show_data(&mut self, stdout: &mut Stdout) {
-        self.layout_device.update();
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
 
         execute!(
             stdout,
@@ -109,25 +487,63 @@ impl LayoutCPU {
         )
         .unwrap();
 
-        // cpu usage
-        for i in 0..self.layout_device.cpu_count {
-            let cpu_usage = self.layout_device.get_cpu_usage(i);
+        // cpu usage, lines below the tile are left out
+        let bottom = self.layout_bbox.top + self.layout_bbox.height;
+        let mut new_top = self.layout_bbox.top + 1;
+        for row in self.get_cpu_rows() {
+            if let Some(header) = row.header {
+                if new_top >= bottom {
+                    return;
+                }
+                execute!(
+                    stdout,
+                    MoveTo(self.layout_bbox.left, new_top),
+                    Print(header.underlined())
+                )
+                .unwrap();
+                new_top += 1;
+            }
 
-            let cpu_bar = calculate_progress_bar(
-                self.layout_bbox.width,
-                format!("{:3}[", i),
-                cpu_usage / 100.0,
-                format!("{:.2}%]", cpu_usage),
-            );
+            if new_top >= bottom {
+                return;
+            }
+            let i = row.index;
+            let kind = match row.core.kind {
+                Some(CoreKind::Performance) => "P",
+                Some(CoreKind::Efficient) => "E",
+                None => "",
+            };
+            let lead = format!("{}{:3}{}[", row.bracket, row.core.cpu, kind);
+            let frequency = match self.layout_device.get_frequency(i) {
+                Some(frequency) => format!(" {:.2}GHz", frequency / 1000.0),
+                None => String::new(),
+            };
+
+            let cpu_bar = match self.layout_device.get_cpu_times(i) {
+                Some(times) => Self::calculate_cpu_times_bar(self.layout_bbox.width, lead, &times, &frequency),
+                None => {
+                    let cpu_usage = self.layout_device.get_cpu_usage(i);
+                    calculate_progress_bar(
+                        self.layout_bbox.width,
+                        lead,
+                        cpu_usage / 100.0,
+                        format!("{:.2}%{}]", cpu_usage, frequency),
+                    )
+                }
+            };
             execute!(
                 stdout,
-                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + i as u16),
+                MoveTo(self.layout_bbox.left, new_top),
                 Print(cpu_bar)
             )
             .unwrap();
+            new_top += 1;
         }
 
         //memory usage
+        if new_top >= bottom {
+            return;
+        }
         let ram_usage = self.layout_device.get_ram_usage();
 
         let ram_bar = calculate_progress_bar(
@@ -138,14 +554,14 @@ impl LayoutCPU {
         );
         execute!(
             stdout,
-            MoveTo(
-                self.layout_bbox.left,
-                self.layout_bbox.top + 1 + self.layout_device.cpu_count as u16
-            ),
+            MoveTo(self.layout_bbox.left, new_top),
             Print(ram_bar)
         )
         .unwrap();
 
+        if new_top + 1 >= bottom {
+            return;
+        }
         let swap_usage = self.layout_device.get_swap_usage();
         let swap_bar = calculate_progress_bar(
             self.layout_bbox.width,
@@ -155,39 +571,124 @@ impl LayoutCPU {
         );
         execute!(
             stdout,
-            MoveTo(
-                self.layout_bbox.left,
-                self.layout_bbox.top + 2 + self.layout_device.cpu_count as u16
-            ),
+            MoveTo(self.layout_bbox.left, new_top + 1),
             Print(swap_bar)
         )
         .unwrap();
+        new_top += 2;
+
+        if let Some(cgroup) = &self.layout_device.cgroup {
+            if new_top + 2 > bottom {
+                return;
+            }
+            // without quota cgroup can use every cpu of its cpuset
+            let visible_cpus = (0..self.layout_device.cpu_count)
+                .filter(|cpu| self.layout_device.is_cpu_visible(*cpu))
+                .count();
+            let cpu_limit = cgroup.cpu_limit.unwrap_or(visible_cpus as f64);
+            let quota_bar = calculate_progress_bar(
+                self.layout_bbox.width,
+                String::from("CGR["),
+                (cgroup.cpu_usage / cpu_limit).min(1.0),
+                format!("{:.2}/{:.2} cores]", cgroup.cpu_usage, cpu_limit),
+            );
+            let throttled_line = truncate_to_width(
+                &format!(
+                    "throttled {:.0}% of periods, {} times, {:.1}s total",
+                    cgroup.throttled * 100.0,
+                    cgroup.nr_throttled,
+                    cgroup.throttled_time
+                ),
+                self.layout_bbox.width,
+            );
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, new_top),
+                Print(quota_bar),
+                MoveTo(self.layout_bbox.left, new_top + 1),
+                Print(throttled_line),
+            )
+            .unwrap();
+            new_top += 2;
+
+            for io_limit in &cgroup.io_limits {
+                if new_top >= bottom {
+                    return;
+                }
+                execute!(
+                    stdout,
+                    MoveTo(self.layout_bbox.left, new_top),
+                    Print(truncate_to_width(&format!("io.max {io_limit}"), self.layout_bbox.width)),
+                )
+                .unwrap();
+                new_top += 1;
+            }
+        }
+
+        if self.layout_device.get_cpu_times(0).is_some() && new_top < bottom {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, new_top),
+            )
+            .unwrap();
+            for (name, color) in Self::CPU_TIMES_LEGEND {
+                execute!(stdout, Print(format!("{name} ").with(color))).unwrap();
+            }
+        }
+    }
+
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.cpu.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
+        }
     }
 }
 
 
 pub struct LayoutGpu {
+    tile_index: usize,
     layout_header: String,
     layout_bbox: LayoutBbox,
     layout_device: GpuAll,
+    replayed: Option<usize>,
 }
 
 impl LayoutGpu {
-    fn new(layout_header: String, layout_bbox: LayoutBbox) -> Self {
-        let device = GpuAll::new();
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &GpuTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        let device = if replayed.is_some() { GpuAll::for_replay() } else { GpuAll::new(options) };
         LayoutGpu {
+            tile_index,
             layout_header: layout_header.bold().to_string(),
             layout_bbox,
             layout_device: device,
+            replayed,
         }
     }
+}
+
+impl Layout for LayoutGpu {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
 
     fn set_position(&mut self, layout_bbox: LayoutBbox) {
         self.layout_bbox = layout_bbox;
     }
 
     fn show_data(&mut self, stdout: &mut Stdout) {
-        self.layout_device.update();
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
 
         execute!(
             stdout,
@@ -197,147 +698,2331 @@ impl LayoutGpu {
         .unwrap();
 
         let mut new_top = self.layout_bbox.top + 1;
+        let bottom = self.layout_bbox.top + self.layout_bbox.height;
 
         for device_index in 0..self.layout_device.device_count {
-            let mut device_info = self.layout_device.get_info(device_index);
-            if device_info.len() > self.layout_bbox.width as usize {
-                device_info = String::from(&device_info[..self.layout_bbox.width as usize]);
+            // MIG instances are drawn under their parent gpu
+            let (left, width) = if self.layout_device.is_mig(device_index) {
+                (self.layout_bbox.left + 2, self.layout_bbox.width.saturating_sub(2))
+            } else {
+                (self.layout_bbox.left, self.layout_bbox.width)
+            };
+            let util_rate = self.layout_device.get_utilization_rate_info(device_index);
+            // MIG instances without GPM show n/a instead of hiding the line
+            let show_util_rate = util_rate.is_some() || self.layout_device.is_mig(device_index);
+            let device_height = if show_util_rate { 3 } else { 2 };
+            if new_top + device_height > bottom {
+                break;
             }
 
+            let device_info = truncate_to_width(&self.layout_device.get_info(device_index), width);
+
             // calculate memory used progress string
             let memory_data = self.layout_device.get_memory_info(device_index);
             let memory_bar = calculate_progress_bar(
-                self.layout_bbox.width,
+                width,
                 String::from("Mem["),
                 memory_data.0 / memory_data.1,
                 format!("{}/{}Mb]", memory_data.0, memory_data.1),
             );
 
-            // calculate utilization_rate string
-            let util_rate = self.layout_device.get_utilization_rate_info(device_index);
-            let util_rate_bar = calculate_progress_bar(
-                self.layout_bbox.width,
-                String::from("GPU["),
-                util_rate / 100.0,
-                format!("{}%]", util_rate),
-            );
-
             execute!(
                 stdout,
-                MoveTo(self.layout_bbox.left, new_top),
+                MoveTo(left, new_top),
                 Print(device_info),
-                MoveTo(self.layout_bbox.left, new_top + 1),
+                MoveTo(left, new_top + 1),
                 Print(memory_bar),
-                MoveTo(self.layout_bbox.left, new_top + 2),
-                Print(util_rate_bar),
             )
             .unwrap();
-            new_top += 4;
+
+            // calculate utilization_rate string
+            if show_util_rate {
+                let util_rate_bar = calculate_progress_bar(
+                    width,
+                    String::from("GPU["),
+                    util_rate.unwrap_or(0.0) / 100.0,
+                    match util_rate {
+                        Some(util_rate) => format!("{:.0}%]", util_rate),
+                        None => "n/a]".to_string(),
+                    },
+                );
+                execute!(stdout, MoveTo(left, new_top + 2), Print(util_rate_bar)).unwrap();
+            }
+            new_top += device_height + 1;
         }
     }
-}
 
-pub struct Ui {
-    layouts_cpu: Vec<LayoutCPU>,
-    layouts_gpu: Vec<LayoutGpu>,
-    stdout: Stdout,
-    pub width: u16,
-    pub height: u16,
-}
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.gpus.push(&self.layout_device);
+    }
 
-impl Ui {
-    pub fn new(cols: u16, rows: u16) -> Self {
-        Self {
-            layouts_cpu: Vec::new(),
-            layouts_gpu: Vec::new(),
-            stdout: stdout(),
-            width: cols,
-            height: rows,
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
         }
     }
+}
 
-    pub fn create_layout(
-        &mut self,
+pub struct LayoutNetwork {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: NetInfo,
+    show_history: bool,
+    replayed: Option<usize>,
+}
+
+impl LayoutNetwork {
+    fn new(
+        tile_index: usize,
         layout_header: String,
         layout_bbox: LayoutBbox,
-        layout_type: LayoutType,
-    ) {
-        match layout_type {
-            LayoutType::Gpu => {
-                let device = LayoutGpu::new(layout_header, layout_bbox);
-                self.layouts_gpu.push(device);
-            }
-            LayoutType::Cpu => {
-                let device = LayoutCPU::new(layout_header, layout_bbox);
-                self.layouts_cpu.push(device);
-            }
-        };
+        options: &NetworkTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        LayoutNetwork {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: if replayed.is_some() { NetInfo::for_replay(options) } else { NetInfo::new(options) },
+            show_history: options.history,
+            replayed,
+        }
     }
+}
 
-    pub fn update_all(&mut self, devices: &Vec<DeviceTile>) {
-        self.clear_screen();
-        //self.update_screen();
-        
-        for device in &mut self.layouts_cpu[..] {
-            device.set_position(LayoutBbox {
-                top: devices[0].row ,
-                left: devices[0].col,
-                width: devices[0].width,
-                height: devices[0].height,
-            });
-        }
+impl Layout for LayoutNetwork {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
 
-        for device in &mut self.layouts_gpu[..] {
-            device.set_position(LayoutBbox {
-                top: devices[1].row,
-                left: devices[1].col,
-                width: devices[1].width,
-                height: devices[1].height,
-            });
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        if self.replayed.is_none() {
+            self.layout_device.update();
         }
 
-        for device in &mut self.layouts_cpu[..] {
-            device.show_data(&mut self.stdout);
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let bottom = self.layout_bbox.top + self.layout_bbox.height;
+        let mut new_top = self.layout_bbox.top + 1;
+
+        if self.layout_device.interfaces.is_empty() {
+            execute!(stdout, MoveTo(self.layout_bbox.left, new_top), Print("No interfaces")).unwrap();
+            return;
         }
-        for device in &mut self.layouts_gpu[..] {
-            device.show_data(&mut self.stdout);
+
+        let interface_height = if self.show_history { 5 } else { 3 };
+        for interface in &self.layout_device.interfaces {
+            if new_top + interface_height > bottom {
+                break;
+            }
+
+            let state_color = match interface.link_state.as_str() {
+                "up" => Color::Green,
+                "down" => Color::Red,
+                _ => Color::Yellow,
+            };
+            let title = truncate_to_width(
+                &format!(
+                    "{} [{}] err: {} drop: {}",
+                    interface.name, interface.link_state, interface.errors, interface.dropped
+                ),
+                width,
+            );
+            let rx_line = truncate_to_width(
+                &format!(
+                    "RX {:>8}/s {:>8.0} pkt/s",
+                    format_bytes(interface.rx_bytes),
+                    interface.rx_packets
+                ),
+                width,
+            );
+            let tx_line = truncate_to_width(
+                &format!(
+                    "TX {:>8}/s {:>8.0} pkt/s",
+                    format_bytes(interface.tx_bytes),
+                    interface.tx_packets
+                ),
+                width,
+            );
+
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, new_top),
+                SetForegroundColor(state_color),
+                Print(title),
+                ResetColor,
+                MoveTo(self.layout_bbox.left, new_top + 1),
+                Print(rx_line),
+                MoveTo(self.layout_bbox.left, new_top + 2),
+                Print(tx_line),
+            )
+            .unwrap();
+
+            if self.show_history {
+                // same scale for both graphs so rx and tx are comparable
+                let max_rate = interface.rx_history.max().max(interface.tx_history.max());
+                let rx_graph = calculate_sparkline(width, String::from("RX "), &interface.rx_history, max_rate);
+                let tx_graph = calculate_sparkline(width, String::from("TX "), &interface.tx_history, max_rate);
+                execute!(
+                    stdout,
+                    MoveTo(self.layout_bbox.left, new_top + 3),
+                    Print(rx_graph.green()),
+                    MoveTo(self.layout_bbox.left, new_top + 4),
+                    Print(tx_graph.blue()),
+                )
+                .unwrap();
+            }
+            new_top += interface_height;
         }
-        self.show_status_line();
     }
 
-    fn update_screen(&mut self) {
-        //(self.width, self.height) = terminal::size().expect("Can't get terminal size");
-        for device in &mut self.layouts_cpu[..] {
-            device.set_position(LayoutBbox {
-                top: 0,
-                left: 0,
-                width: self.width / 2,
-                height: self.height / 2,
-            });
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.network.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
         }
+    }
+}
 
-        for device in &mut self.layouts_gpu[..] {
-            device.set_position(LayoutBbox {
-                top: 0,
-                left: self.width / 2 + 1,
-                width: self.width / 2,
-                height: self.height / 2,
-            });
+pub struct LayoutDiskIo {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: DiskInfo,
+    show_history: bool,
+    replayed: Option<usize>,
+}
+
+impl LayoutDiskIo {
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &DiskIoTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        LayoutDiskIo {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: if replayed.is_some() { DiskInfo::for_replay(options) } else { DiskInfo::new(options) },
+            show_history: options.history,
+            replayed,
         }
     }
+}
 
-    fn clear_screen(&mut self) {
-        execute!(&mut self.stdout, terminal::Clear(terminal::ClearType::All)).unwrap();
+impl Layout for LayoutDiskIo {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
     }
 
-    fn show_status_line(&mut self) {
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
+
         execute!(
-            self.stdout,
-            MoveTo(0, self.height - 1),
-            SetForegroundColor(Color::Green),
-            Print("Press q for exit...".to_string()),
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let bottom = self.layout_bbox.top + self.layout_bbox.height;
+        let mut new_top = self.layout_bbox.top + 1;
+
+        if self.layout_device.devices.is_empty() {
+            execute!(stdout, MoveTo(self.layout_bbox.left, new_top), Print("No block devices")).unwrap();
+            return;
+        }
+
+        let device_height = if self.show_history { 5 } else { 3 };
+        for device in &self.layout_device.devices {
+            if new_top + device_height > bottom {
+                break;
+            }
+
+            // partitions are drawn under their parent device
+            let (left, width) = if device.parent.is_some() {
+                (self.layout_bbox.left + 2, self.layout_bbox.width.saturating_sub(2))
+            } else {
+                (self.layout_bbox.left, self.layout_bbox.width)
+            };
+
+            let busy_bar = calculate_progress_bar(
+                width,
+                format!("{}[", device.name),
+                device.busy,
+                format!("{:.0}%]", device.busy * 100.0),
+            );
+            let rates_line = truncate_to_width(
+                &format!(
+                    "R {:>7}/s {:>6.0}io/s  W {:>7}/s {:>6.0}io/s",
+                    format_bytes(device.read_bytes),
+                    device.read_iops,
+                    format_bytes(device.write_bytes),
+                    device.write_iops
+                ),
+                width,
+            );
+            let latency_line = truncate_to_width(
+                &format!("lat {:.2}ms  queue {:.2}", device.latency, device.queue_depth),
+                width,
+            );
+
+            execute!(
+                stdout,
+                MoveTo(left, new_top),
+                Print(busy_bar),
+                MoveTo(left, new_top + 1),
+                Print(rates_line),
+                MoveTo(left, new_top + 2),
+                Print(latency_line),
+            )
+            .unwrap();
+
+            if self.show_history {
+                let max_rate = device.read_history.max().max(device.write_history.max());
+                let read_graph = calculate_sparkline(width, String::from("R "), &device.read_history, max_rate);
+                let write_graph = calculate_sparkline(width, String::from("W "), &device.write_history, max_rate);
+                execute!(
+                    stdout,
+                    MoveTo(left, new_top + 3),
+                    Print(read_graph.green()),
+                    MoveTo(left, new_top + 4),
+                    Print(write_graph.blue()),
+                )
+                .unwrap();
+            }
+            new_top += device_height;
+        }
+    }
+
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.disks.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
+        }
+    }
+}
+
+pub struct LayoutFilesystems {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: FsInfo,
+    replayed: Option<usize>,
+}
+
+impl LayoutFilesystems {
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &FilesystemsTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        LayoutFilesystems {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: if replayed.is_some() { FsInfo::for_replay(options) } else { FsInfo::new(options) },
+            replayed,
+        }
+    }
+}
+
+impl Layout for LayoutFilesystems {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let bottom = self.layout_bbox.top + self.layout_bbox.height;
+        let mut new_top = self.layout_bbox.top + 1;
+
+        for filesystem in &self.layout_device.filesystems {
+            if new_top + 2 > bottom {
+                break;
+            }
+
+            // long mount points would leave no space for the bar
+            let mount_point = truncate_to_width(&filesystem.mount_point, width / 3);
+            let usage_bar = calculate_progress_bar(
+                width,
+                format!("{}[", mount_point),
+                filesystem.used as f64 / filesystem.total as f64,
+                format!(
+                    "{}/{}]",
+                    format_bytes(filesystem.used as f64),
+                    format_bytes(filesystem.total as f64)
+                ),
+            );
+
+            let inodes_usage = if filesystem.inodes_total > 0 {
+                format!("{:.0}%", filesystem.inodes_used as f64 / filesystem.inodes_total as f64 * 100.0)
+            } else {
+                "-".to_string()
+            };
+            let forecast = match filesystem.time_to_full {
+                _ if filesystem.stale => "stale, not responding".to_string(),
+                // not recorded
+                _ if self.replayed.is_some() => String::new(),
+                Some(seconds) => format!("full in {}", format_duration(seconds)),
+                None => "not growing".to_string(),
+            };
+            let details_line = truncate_to_width(
+                &format!(
+                    "  {} {} inodes: {} {}",
+                    filesystem.fs_type, filesystem.device, inodes_usage, forecast
+                ),
+                width,
+            );
+
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, new_top),
+                Print(usage_bar),
+                MoveTo(self.layout_bbox.left, new_top + 1),
+                Print(details_line),
+            )
+            .unwrap();
+            new_top += 2;
+        }
+    }
+
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.filesystems.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
+        }
+    }
+}
+
+pub struct LayoutSensors {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: SensorsInfo,
+    replayed: Option<usize>,
+}
+
+impl LayoutSensors {
+    // used as the bar scale when kernel doesn't provide thresholds
+    const DEFAULT_MAX_TEMPERATURE: f64 = 100.0;
+
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &SensorsTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        LayoutSensors {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: match replayed {
+                Some(_) => SensorsInfo::for_replay(options),
+                None => SensorsInfo::new(options),
+            },
+            replayed,
+        }
+    }
+}
+
+impl Layout for LayoutSensors {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let bottom = self.layout_bbox.top + self.layout_bbox.height;
+        let mut new_top = self.layout_bbox.top + 1;
+
+        if self.layout_device.chips.is_empty() {
+            execute!(stdout, MoveTo(self.layout_bbox.left, new_top), Print("No sensors")).unwrap();
+            return;
+        }
+
+        // label column is shared by all readings to keep bars aligned
+        let label_width = self
+            .layout_device
+            .chips
+            .iter()
+            .flat_map(|chip| chip.readings.iter())
+            .map(|reading| reading.label.as_str().width())
+            .max()
+            .unwrap_or(0)
+            .min(width as usize / 3);
+
+        'chips: for chip in &self.layout_device.chips {
+            if new_top >= bottom {
+                break;
+            }
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, new_top),
+                Print(truncate_to_width(&chip.name, width).underlined()),
+            )
+            .unwrap();
+            new_top += 1;
+
+            for reading in &chip.readings {
+                if new_top >= bottom {
+                    break 'chips;
+                }
+                let label = format!("{:<label_width$}", truncate_to_width(&reading.label, label_width as u16));
+
+                let line = match reading.kind {
+                    SensorKind::Temperature => {
+                        let scale = reading.crit.or(reading.max).unwrap_or(Self::DEFAULT_MAX_TEMPERATURE);
+                        calculate_progress_bar(
+                            width,
+                            format!("{label}["),
+                            (reading.value / scale).clamp(0.0, 1.0),
+                            format!("{:>3.0}°C]", reading.value),
+                        )
+                    }
+                    SensorKind::Fan => match reading.max {
+                        Some(max) if max > 0.0 => calculate_progress_bar(
+                            width,
+                            format!("{label}["),
+                            (reading.value / max).clamp(0.0, 1.0),
+                            format!("{:.0} RPM]", reading.value),
+                        ),
+                        _ => truncate_to_width(&format!("{label} {:.0} RPM", reading.value), width),
+                    },
+                    SensorKind::Voltage => truncate_to_width(&format!("{label} {:.3} V", reading.value), width),
+                };
+                execute!(stdout, MoveTo(self.layout_bbox.left, new_top), Print(line)).unwrap();
+                new_top += 1;
+            }
+        }
+    }
+
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.sensors.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
+        }
+    }
+}
+
+pub struct LayoutBattery {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: PowerSupplyInfo,
+    replayed: Option<usize>,
+}
+
+impl LayoutBattery {
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &BatteryTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        LayoutBattery {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: match replayed {
+                Some(_) => PowerSupplyInfo::for_replay(),
+                None => PowerSupplyInfo::new(options),
+            },
+            replayed,
+        }
+    }
+}
+
+impl Layout for LayoutBattery {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let bottom = self.layout_bbox.top + self.layout_bbox.height;
+        let mut new_top = self.layout_bbox.top + 1;
+
+        if self.layout_device.batteries.is_empty() && self.layout_device.adapters.is_empty() {
+            execute!(stdout, MoveTo(self.layout_bbox.left, new_top), Print("No power supplies")).unwrap();
+            return;
+        }
+
+        for battery in &self.layout_device.batteries {
+            if new_top + 2 > bottom {
+                return;
+            }
+            // unlike usage bars, low charge is the bad state
+            let charge_color = if battery.capacity > 0.5 {
+                Color::Green
+            } else if battery.capacity > 0.2 {
+                Color::Yellow
+            } else {
+                Color::Red
+            };
+            let charge_line = truncate_to_width(
+                &format!("{} {:.0}% {}", battery.name, battery.capacity * 100.0, battery.status),
+                width,
+            );
+
+            let power = match battery.power {
+                Some(power) => format!("{:.1}W", power),
+                None => "-W".to_string(),
+            };
+            let time_remaining = match battery.time_remaining {
+                Some(seconds) if battery.status == "Charging" => format!("full in {}", format_duration(seconds)),
+                Some(seconds) => format!("{} left", format_duration(seconds)),
+                None => String::new(),
+            };
+            let health = match battery.health {
+                Some(health) => format!("health {:.0}%", health * 100.0),
+                None => String::new(),
+            };
+            let details_line = truncate_to_width(&format!("  {power} {time_remaining} {health}"), width);
+
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, new_top),
+                SetForegroundColor(charge_color),
+                Print(charge_line),
+                ResetColor,
+                MoveTo(self.layout_bbox.left, new_top + 1),
+                Print(details_line),
+            )
+            .unwrap();
+            new_top += 2;
+        }
+
+        for adapter in &self.layout_device.adapters {
+            if new_top >= bottom {
+                return;
+            }
+            let state = if adapter.online { "online" } else { "offline" };
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, new_top),
+                Print(truncate_to_width(&format!("{} [{}]", adapter.name, state), width)),
+            )
+            .unwrap();
+            new_top += 1;
+        }
+    }
+
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.batteries.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
+        }
+    }
+}
+
+pub struct LayoutSystem {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: SystemInfo,
+    replayed: Option<usize>,
+    show_pressure: bool,
+}
+
+impl LayoutSystem {
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &SystemTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        LayoutSystem {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: if replayed.is_some() { SystemInfo::for_replay() } else { SystemInfo::new(options) },
+            replayed,
+            show_pressure: options.pressure,
+        }
+    }
+}
+
+impl Layout for LayoutSystem {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let info = &self.layout_device;
+
+        let mut lines = vec![
+            truncate_to_width(
+                &format!(
+                    "up {}  tasks {} running {} blocked {}",
+                    format_duration(info.uptime),
+                    info.tasks_total,
+                    info.tasks_running,
+                    info.tasks_blocked
+                ),
+                width,
+            ),
+            truncate_to_width(
+                &format!("ctxt {:.0}/s  intr {:.0}/s", info.context_switches, info.interrupts),
+                width,
+            ),
+        ];
+
+        // load is shown relative to the number of cores, 1.0 means all cores are busy
+        for (name, load) in ["1m", "5m", "15m"].iter().zip(info.load.iter()) {
+            lines.push(calculate_progress_bar(
+                width,
+                format!("{:>3}[", name),
+                (load / info.cpu_count as f64).min(1.0),
+                format!("{:.2}]", load),
+            ));
+        }
+
+        if self.show_pressure {
+            for pressure in &info.pressure {
+                for (kind, pressure_line) in [("some", pressure.some), ("full", pressure.full)] {
+                    let Some(pressure_line) = pressure_line else {
+                        continue;
+                    };
+                    for (window, value) in [("10", pressure_line.avg10), ("60", pressure_line.avg60)] {
+                        lines.push(calculate_progress_bar(
+                            width,
+                            format!("{:<4.3}{} {}[", pressure.resource, kind, window),
+                            value / 100.0,
+                            format!("{:.2}%]", value),
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.system.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
+        }
+    }
+}
+
+pub struct LayoutCgroups {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: CgroupsInfo,
+    replayed: Option<usize>,
+}
+
+impl LayoutCgroups {
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &CgroupsTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        LayoutCgroups {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: if replayed.is_some() { CgroupsInfo::for_replay(options) } else { CgroupsInfo::new(options) },
+            replayed,
+        }
+    }
+}
+
+impl Layout for LayoutCgroups {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
+
+        let width = self.layout_bbox.width;
+        let header = match &self.layout_device.parent {
+            Some(parent) => format!("{} {}", self.layout_header, parent.name),
+            None => self.layout_header.clone(),
+        };
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(header),
+        )
+        .unwrap();
+
+        if self.layout_device.parent.is_none() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1),
+                Print("No cgroup v2 hierarchy")
+            )
+            .unwrap();
+            return;
+        }
+
+        let name_width = (width / 3) as usize;
+        let visible_rows = self.layout_bbox.height.saturating_sub(1) as usize;
+        for (row, child) in self.layout_device.children.iter().take(visible_rows).enumerate() {
+            let memory = match child.memory_max {
+                Some(memory_max) => format!(
+                    "{}/{}",
+                    format_bytes(child.memory_current as f64),
+                    format_bytes(memory_max as f64)
+                ),
+                None => format_bytes(child.memory_current as f64),
+            };
+            let line = format!(
+                "{:<name_width$} cpu {:>6.1}% mem {} io {}/s",
+                truncate_to_width(&child.name, name_width as u16),
+                child.cpu_usage * 100.0,
+                memory,
+                format_bytes(child.io_read + child.io_write),
+            );
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + row as u16),
+                Print(truncate_to_width(&line, width)),
+            )
+            .unwrap();
+        }
+    }
+
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.cgroups.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
+        }
+    }
+}
+
+pub struct LayoutContainers {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: ContainersInfo,
+    selected: usize,
+    // container id, name and action waiting for y/n
+    pending_action: Option<(String, String, ContainerAction)>,
+    // result of the last action
+    message: Option<String>,
+    // keys go to one containers tile, Tab moves between them
+    focused: bool,
+}
+
+impl LayoutContainers {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &ContainersTileOptions) -> Self {
+        LayoutContainers {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: ContainersInfo::new(options),
+            selected: 0,
+            pending_action: None,
+            message: None,
+            focused: false,
+        }
+    }
+}
+
+impl Layout for LayoutContainers {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+        if let Some(result) = self.layout_device.action_result.take() {
+            self.message = Some(result);
+        }
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        if !self.layout_device.loaded {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1),
+                Print(truncate_to_width("Connecting to the engine...", width))
+            )
+            .unwrap();
+            return;
+        }
+        if let Some(err) = &self.layout_device.error {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1),
+                Print(truncate_to_width(err, width))
+            )
+            .unwrap();
+            return;
+        }
+
+        let containers = &self.layout_device.containers;
+        self.selected = self.selected.min(containers.len().saturating_sub(1));
+
+        // two lines per container, last line of the tile is for help and confirmation
+        let visible_containers = (self.layout_bbox.height.saturating_sub(2) / 2).max(1) as usize;
+        let first_visible = (self.selected + 1).saturating_sub(visible_containers);
+        let mut row = self.layout_bbox.top + 1;
+        if containers.is_empty() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, row),
+                Print(truncate_to_width("No containers", width))
+            )
+            .unwrap();
+        }
+        for (ind, container) in containers.iter().enumerate().skip(first_visible).take(visible_containers) {
+            let title = truncate_to_width(&format!("{} [{}] {}", container.name, container.state, container.image), width);
+            let title = if ind == self.selected && self.focused {
+                title.reverse().to_string()
+            } else {
+                title
+            };
+            let memory = if container.memory_limit > 0 {
+                format!(
+                    "{}/{}",
+                    format_bytes(container.memory_used as f64),
+                    format_bytes(container.memory_limit as f64)
+                )
+            } else {
+                format_bytes(container.memory_used as f64)
+            };
+            let stats = format!(
+                "  cpu {:>5.1}% mem {} net {}/{}/s blk {}/{}/s",
+                container.cpu,
+                memory,
+                format_bytes(container.net_rx),
+                format_bytes(container.net_tx),
+                format_bytes(container.block_read),
+                format_bytes(container.block_write),
+            );
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, row),
+                Print(title),
+                MoveTo(self.layout_bbox.left, row + 1),
+                Print(truncate_to_width(&stats, width)),
+            )
+            .unwrap();
+            row += 2;
+        }
+
+        let help = match (&self.pending_action, &self.message) {
+            _ if !self.focused => "Tab to select this tile".to_string(),
+            (Some((_, container_name, action)), _) => format!("{} {}? [y/n]", action.name(), container_name),
+            (None, Some(message)) => message.clone(),
+            (None, None) => "up/down select, s start, t stop, r restart, Tab next tile".to_string(),
+        };
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top + self.layout_bbox.height.saturating_sub(1)),
+            SetForegroundColor(Color::Yellow),
+            Print(truncate_to_width(&help, width)),
+            ResetColor,
+        )
+        .unwrap();
+    }
+
+    fn takes_keys(&self) -> bool {
+        true
+    }
+
+    fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if let Some((container_id, container_name, action)) = self.pending_action.take() {
+            if key.code == KeyCode::Char('y') {
+                // stop and restart can take seconds, the result is shown when the engine answers
+                self.layout_device.run_action(&container_id, &container_name, action);
+                self.message = Some(format!("{} {container_name}...", action.name()));
+            }
+            // any other key cancels the action
+            return;
+        }
+
+        let containers_count = self.layout_device.containers.len();
+        let action = match key.code {
+            KeyCode::Up => {
+                self.selected = self.selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(containers_count.saturating_sub(1));
+                None
+            }
+            KeyCode::Char('s') => Some(ContainerAction::Start),
+            KeyCode::Char('t') => Some(ContainerAction::Stop),
+            KeyCode::Char('r') => Some(ContainerAction::Restart),
+            _ => None,
+        };
+        if let (Some(action), Some(container)) = (action, self.layout_device.containers.get(self.selected)) {
+            self.message = None;
+            self.pending_action = Some((container.id.clone(), container.name.clone(), action));
+        }
+    }
+}
+
+pub struct LayoutNuma {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: NumaInfo,
+}
+
+impl LayoutNuma {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &NumaTileOptions) -> Self {
+        LayoutNuma {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: NumaInfo::new(options),
+        }
+    }
+}
+
+impl Layout for LayoutNuma {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let mut lines: Vec<String> = Vec::new();
+        if self.layout_device.nodes.is_empty() {
+            lines.push("No NUMA nodes".to_string());
+        }
+        for node in &self.layout_device.nodes {
+            lines.push(calculate_progress_bar(
+                width,
+                format!("node{} CPU[", node.id),
+                node.cpu_usage / 100.0,
+                format!("{:.2}% {}cpus]", node.cpu_usage, node.cpus.len()),
+            ));
+
+            let memory_used = node.memory_total.saturating_sub(node.memory_free);
+            let memory_share = if node.memory_total > 0 {
+                memory_used as f64 / node.memory_total as f64
+            } else {
+                0.0
+            };
+            lines.push(calculate_progress_bar(
+                width,
+                format!("{:>width$}MEM[", "", width = format!("node{} ", node.id).len()),
+                memory_share,
+                format!(
+                    "{}/{} free {}]",
+                    format_bytes(memory_used as f64),
+                    format_bytes(node.memory_total as f64),
+                    format_bytes(node.memory_free as f64)
+                ),
+            ));
+
+            // a growing miss rate means memory is allocated away from the cpus using it
+            let miss_color = if node.numa_miss > 0.0 || node.numa_foreign > 0.0 {
+                Color::Yellow
+            } else {
+                Color::Reset
+            };
+            lines.push(
+                truncate_to_width(
+                    &format!(
+                        "  pages/s hit {:.0} miss {:.0} foreign {:.0}",
+                        node.numa_hit, node.numa_miss, node.numa_foreign
+                    ),
+                    width,
+                )
+                .with(miss_color)
+                .to_string(),
+            );
+
+            if !node.gpus.is_empty() {
+                lines.push(truncate_to_width(&format!("  GPU {}", node.gpus.join(", ")), width));
+            }
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct LayoutSockets {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: SocketsInfo,
+}
+
+impl LayoutSockets {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &SocketsTileOptions) -> Self {
+        LayoutSockets {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: SocketsInfo::new(options),
+        }
+    }
+}
+
+impl Layout for LayoutSockets {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let info = &self.layout_device;
+
+        let states: Vec<String> = TCP_STATES
+            .iter()
+            .zip(info.tcp_states.iter())
+            .filter(|(_, count)| **count > 0)
+            .map(|(state, count)| format!("{state} {count}"))
+            .collect();
+        let mut lines = vec![
+            truncate_to_width(&format!("tcp {}", states.join(" ")), width),
+            truncate_to_width(&format!("udp {}", info.udp_count), width),
+            truncate_to_width(&format!("{:>7} {:<15} {:>6} {:>6} listen", "pid", "process", "tcp", "udp"), width)
+                .bold()
+                .to_string(),
+        ];
+        for owner in &info.owners {
+            let pid = owner.pid.map(|pid| pid.to_string()).unwrap_or_else(|| "-".to_string());
+            lines.push(truncate_to_width(
+                &format!(
+                    "{:>7} {:<15} {:>6} {:>6} {}",
+                    pid,
+                    truncate_to_width(&owner.name, 15),
+                    owner.tcp,
+                    owner.udp,
+                    owner.listening.join(",")
+                ),
+                width,
+            ));
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct LayoutInterrupts {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: InterruptsInfo,
+    topology: Vec<CoreTopology>,
+    group_by: CpuGrouping,
+    top: usize,
+}
+
+impl LayoutInterrupts {
+    const LEAD_WIDTH: usize = 5;
+    const CELL_WIDTH: usize = 8;
+
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &InterruptsTileOptions) -> Self {
+        let layout_device = InterruptsInfo::new(options);
+        LayoutInterrupts {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            topology: {
+                // rates are kept per cpu number, offline cpus are left out
+                let sysfs_root = std::path::PathBuf::from(&options.sysfs_root);
+                let cpus = read_online_cpus(&sysfs_root).unwrap_or_else(|| (0..layout_device.cpu_count).collect());
+                read_topology(&sysfs_root, &cpus)
+            },
+            layout_device,
+            group_by: options.group_by,
+            top: options.top,
+        }
+    }
+
+    // share of the busiest cell, like a heat map
+    fn calculate_heat_cell(rate: f64, max_rate: f64) -> String {
+        if rate == 0.0 {
+            return format!("{:>width$}", "·", width = Self::CELL_WIDTH).dark_grey().to_string();
+        }
+        let cell = format!("{:>width$}", format_count(rate), width = Self::CELL_WIDTH);
+        let heat = rate / max_rate;
+        if heat < 0.33 {
+            cell.green().to_string()
+        } else if heat < 0.66 {
+            cell.yellow().to_string()
+        } else {
+            cell.red().to_string()
+        }
+    }
+}
+
+impl Layout for LayoutInterrupts {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let columns = self
+            .top
+            .min((self.layout_bbox.width as usize).saturating_sub(Self::LEAD_WIDTH) / Self::CELL_WIDTH);
+        let sources = &self.layout_device.sources[..columns.min(self.layout_device.sources.len())];
+        let max_rate = sources
+            .iter()
+            .flat_map(|source| source.rates.iter())
+            .fold(0.0, |max: f64, rate| max.max(*rate));
+
+        let mut header = " ".repeat(Self::LEAD_WIDTH);
+        for source in sources {
+            let label = truncate_to_width(&source.label, Self::CELL_WIDTH as u16 - 1);
+            header += &format!("{:>width$}", label, width = Self::CELL_WIDTH);
+        }
+        let mut lines = vec![header.bold().to_string()];
+
+        for row in get_cpu_rows(self.topology.iter().copied().enumerate().collect(), self.group_by) {
+            if let Some(header) = row.header {
+                lines.push(header.underlined().to_string());
+            }
+            let kind = match row.core.kind {
+                Some(CoreKind::Performance) => "P",
+                Some(CoreKind::Efficient) => "E",
+                None => "",
+            };
+            let lead = format!("{}{:3}{}", row.bracket, row.core.cpu, kind);
+            let mut line = format!("{:<width$}", lead, width = Self::LEAD_WIDTH);
+            for source in sources {
+                let rate = source.rates.get(row.core.cpu).copied().unwrap_or(0.0);
+                line += &Self::calculate_heat_cell(rate, max_rate);
+            }
+            lines.push(line);
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct LayoutPower {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: PowerInfo,
+    replayed: Option<usize>,
+}
+
+impl LayoutPower {
+    fn new(
+        tile_index: usize,
+        layout_header: String,
+        layout_bbox: LayoutBbox,
+        options: &PowerTileOptions,
+        replayed: Option<usize>,
+    ) -> Self {
+        LayoutPower {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: if replayed.is_some() { PowerInfo::for_replay() } else { PowerInfo::new(options) },
+            replayed,
+        }
+    }
+
+    // energy is shown in Wh, which is what electricity is billed in
+    fn format_energy(joules: f64) -> String {
+        let watt_hours = joules / 3600.0;
+        if watt_hours >= 1000.0 {
+            format!("{:.2}kWh", watt_hours / 1000.0)
+        } else {
+            format!("{:.2}Wh", watt_hours)
+        }
+    }
+}
+
+impl Layout for LayoutPower {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        if self.replayed.is_none() {
+            self.layout_device.update();
+        }
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let info = &self.layout_device;
+        let mut lines: Vec<String> = Vec::new();
+        if info.components.is_empty() {
+            lines.push(truncate_to_width("No RAPL zones or gpus (RAPL needs root)", width));
+        }
+        for component in &info.components {
+            let indent = if component.is_subzone { "  " } else { "" };
+            let lead = format!("{}{:<width$}[", indent, component.name, width = 16 - indent.len());
+            let trail = format!("{:.1}W {}]", component.watts, Self::format_energy(component.energy));
+            lines.push(match component.limit {
+                Some(limit) if limit > 0.0 => {
+                    calculate_progress_bar(width, lead, (component.watts / limit).min(1.0), trail)
+                }
+                _ => truncate_to_width(&format!("{lead}{trail}"), width),
+            });
+        }
+        lines.push(
+            truncate_to_width(
+                &format!(
+                    "Total {:.1}W, {} since start",
+                    info.total_watts,
+                    Self::format_energy(info.total_energy)
+                ),
+                width,
+            )
+            .bold()
+            .to_string(),
+        );
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+
+    fn add_record_sources<'a>(&'a self, sources: &mut Sources<'a>) {
+        sources.power.push(&self.layout_device);
+    }
+
+    fn replay(&mut self, sample: &RecordedSample) {
+        if let Some(tile) = self.replayed {
+            self.layout_device.replay(sample, tile);
+        }
+    }
+}
+
+pub struct LayoutCommand {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: CommandInfo,
+    format: CommandFormat,
+    label: String,
+    unit: String,
+    show_history: bool,
+}
+
+impl LayoutCommand {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &CommandTileOptions) -> Self {
+        LayoutCommand {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: CommandInfo::new(options),
+            format: options.format,
+            label: options.label.clone(),
+            unit: options.unit.clone(),
+            show_history: options.history,
+        }
+    }
+
+    fn format_value(&self, value: f64) -> String {
+        // integers are printed without fraction, like seats or queue length
+        if value.fract() == 0.0 {
+            format!("{:.0}{}", value, self.unit)
+        } else {
+            format!("{:.2}{}", value, self.unit)
+        }
+    }
+}
+
+impl Layout for LayoutCommand {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let mut lines: Vec<String> = Vec::new();
+        match &self.layout_device.output {
+            CommandOutput::Pending => lines.push("...".to_string()),
+            CommandOutput::Text(text) => {
+                lines.extend(text.lines().map(|line| truncate_to_width(line, width)));
+            }
+            CommandOutput::Value { value, total, share } => {
+                let text = match (total, self.format) {
+                    (Some(total), _) => format!("{}/{}", self.format_value(*value), self.format_value(*total)),
+                    (None, CommandFormat::Percent) => format!("{:.2}%", value),
+                    (None, _) => self.format_value(*value),
+                };
+                lines.push(match share {
+                    Some(share) => calculate_progress_bar(
+                        width,
+                        format!("{}[", self.label),
+                        share.clamp(0.0, 1.0),
+                        format!("{}]", text),
+                    ),
+                    None => truncate_to_width(&format!("{} {}", self.label, text), width),
+                });
+
+                if self.show_history {
+                    // shares are drawn against the full bar, plain numbers against their own peak
+                    let history = &self.layout_device.history;
+                    let max_value = if share.is_some() { 1.0 } else { history.max() };
+                    lines.push(calculate_sparkline(width, String::new(), history, max_value));
+                }
+            }
+            CommandOutput::Error(err) => {
+                lines.push(truncate_to_width(err, width).red().to_string());
+            }
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct LayoutPlugin {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: PluginInfo,
+}
+
+impl LayoutPlugin {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &PluginTileOptions) -> Self {
+        LayoutPlugin {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: PluginInfo::new(options),
+        }
+    }
+
+    // columns are as wide as their widest cell
+    fn calculate_table(width: u16, columns: &[String], rows: &[Vec<String>]) -> Vec<String> {
+        let column_count = rows.iter().map(|row| row.len()).chain([columns.len()]).max().unwrap_or(0);
+        let mut column_widths = vec![0; column_count];
+        for row in rows.iter().chain([columns.to_vec()].iter()) {
+            for (ind, cell) in row.iter().enumerate() {
+                column_widths[ind] = column_widths[ind].max(cell.as_str().width());
+            }
+        }
+
+        let format_row = |row: &[String]| {
+            let cells: Vec<String> = column_widths
+                .iter()
+                .enumerate()
+                .map(|(ind, column_width)| {
+                    let cell = row.get(ind).map(|cell| cell.as_str()).unwrap_or_default();
+                    format!("{}{}", cell, " ".repeat(column_width - cell.width()))
+                })
+                .collect();
+            truncate_to_width(&cells.join(" "), width)
+        };
+
+        let mut lines = Vec::new();
+        if !columns.is_empty() {
+            lines.push(format_row(columns).bold().to_string());
+        }
+        lines.extend(rows.iter().map(|row| format_row(row)));
+        lines
+    }
+}
+
+impl Layout for LayoutPlugin {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let info = &self.layout_device;
+        let mut lines: Vec<String> = Vec::new();
+        if let Some(err) = &info.error {
+            lines.push(truncate_to_width(err, width).red().to_string());
+        }
+
+        for metric in &info.metrics {
+            let label = if metric.label.is_empty() { &metric.name } else { &metric.label };
+            let Some(value) = info.values.get(&metric.name) else {
+                lines.push(truncate_to_width(&format!("{label} ..."), width));
+                continue;
+            };
+            let number = value.number.unwrap_or(0.0);
+            match metric.kind {
+                MetricKind::Bar => lines.push(calculate_progress_bar(
+                    width,
+                    format!("{label}["),
+                    metric.max.filter(|max| *max > 0.0).map(|max| number / max).unwrap_or(0.0).clamp(0.0, 1.0),
+                    format!("{number:.2}{}]", metric.unit),
+                )),
+                MetricKind::Number => {
+                    lines.push(truncate_to_width(&format!("{label} {number:.2}{}", metric.unit), width));
+                }
+                MetricKind::Graph => {
+                    let max_value = metric.max.unwrap_or_else(|| value.history.max());
+                    lines.push(truncate_to_width(&format!("{label} {number:.2}{}", metric.unit), width));
+                    lines.push(calculate_sparkline(width, String::new(), &value.history, max_value));
+                }
+                MetricKind::Table => {
+                    lines.push(truncate_to_width(label, width).underlined().to_string());
+                    lines.extend(Self::calculate_table(width, &metric.columns, &value.rows));
+                }
+            }
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct LayoutPrometheus {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: PrometheusInfo,
+}
+
+impl LayoutPrometheus {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &PrometheusTileOptions) -> Self {
+        LayoutPrometheus {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: PrometheusInfo::new(options),
+        }
+    }
+
+    // byte counters and request rates can get large, ratios and latencies are small
+    fn format_value(value: f64, unit: &str, is_rate: bool) -> String {
+        let rate = if is_rate { "/s" } else { "" };
+        if value.abs() >= 1e3 {
+            format!("{}{unit}{rate}", format_count(value))
+        } else {
+            format!("{value:.2}{unit}{rate}")
+        }
+    }
+}
+
+impl Layout for LayoutPrometheus {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let info = &self.layout_device;
+        let mut lines: Vec<String> = Vec::new();
+        if !info.loaded {
+            lines.push(truncate_to_width("Scraping...", width));
+        } else if let Some(err) = &info.error {
+            lines.push(truncate_to_width(err, width).red().to_string());
+        } else if info.rows.is_empty() {
+            lines.push(truncate_to_width("No matching series", width));
+        }
+
+        for row in &info.rows {
+            // counters need two scrapes for a rate
+            let Some(value) = row.value else {
+                lines.push(truncate_to_width(&format!("{} ...", row.title), width));
+                continue;
+            };
+            let text = Self::format_value(value, &row.unit, row.is_rate);
+            match row.display {
+                PrometheusDisplay::Bar => lines.push(calculate_progress_bar(
+                    width,
+                    format!("{}[", row.title),
+                    row.max.filter(|max| *max > 0.0).map(|max| value / max).unwrap_or(0.0).clamp(0.0, 1.0),
+                    format!("{text}]"),
+                )),
+                PrometheusDisplay::Number => {
+                    lines.push(truncate_to_width(&format!("{} {text}", row.title), width));
+                }
+                PrometheusDisplay::Graph => {
+                    let max_value = row.max.unwrap_or_else(|| row.history.max());
+                    lines.push(truncate_to_width(&format!("{} {text}", row.title), width));
+                    lines.push(calculate_sparkline(width, String::new(), &row.history, max_value));
+                }
+            }
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct LayoutLog {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: LogInfo,
+}
+
+impl LayoutLog {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &LogTileOptions) -> Self {
+        LayoutLog {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: LogInfo::new(options),
+        }
+    }
+
+    // matches of earlier rules win when rules overlap
+    fn highlight_line(highlights: &[(Regex, Color)], line: &str) -> String {
+        let mut spans: Vec<(usize, usize, Color)> = Vec::new();
+        for (regex, color) in highlights {
+            for found in regex.find_iter(line).filter(|found| !found.is_empty()) {
+                if spans.iter().all(|(start, end, _)| found.end() <= *start || found.start() >= *end) {
+                    spans.push((found.start(), found.end(), *color));
+                }
+            }
+        }
+        spans.sort_by_key(|(start, _, _)| *start);
+
+        let mut highlighted = String::new();
+        let mut position = 0;
+        for (start, end, color) in spans {
+            highlighted.push_str(&line[position..start]);
+            highlighted.push_str(&line[start..end].with(color).to_string());
+            position = end;
+        }
+        highlighted.push_str(&line[position..]);
+        highlighted
+    }
+}
+
+impl Layout for LayoutLog {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let info = &self.layout_device;
+        let mut lines: Vec<String> = Vec::new();
+        if let Some(err) = &info.error {
+            lines.push(truncate_to_width(err, width).red().to_string());
+        }
+        // newest lines at the bottom, highlighted after clipping so colors are not cut
+        let free_lines = (self.layout_bbox.height.saturating_sub(1) as usize).saturating_sub(lines.len());
+        let skipped = info.lines.len().saturating_sub(free_lines);
+        lines.extend(
+            info.lines
+                .iter()
+                .skip(skipped)
+                .map(|line| Self::highlight_line(&info.highlights, &truncate_to_width(line, width))),
+        );
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct LayoutEvents {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: EventsInfo,
+}
+
+impl LayoutEvents {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &EventsTileOptions) -> Self {
+        LayoutEvents {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: EventsInfo::new(options),
+        }
+    }
+}
+
+impl Layout for LayoutEvents {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let info = &self.layout_device;
+        let mut lines: Vec<String> = Vec::new();
+        if let Some(err) = &info.kmsg_error {
+            lines.push(truncate_to_width(&format!("OOM victims unknown, {err}"), width).dark_grey().to_string());
+        }
+        if info.events.is_empty() {
+            lines.push(truncate_to_width("No events since start", width));
+        }
+
+        // newest first
+        for event in info.events.iter().rev() {
+            let color = match event.kind {
+                EventKind::Oom => Color::Red,
+                EventKind::Mce | EventKind::Edac => Color::Magenta,
+                EventKind::Thermal => Color::Yellow,
+            };
+            let lead = format!("{} {:<5} ", event.time, event.kind.name());
+            let text = truncate_to_width(&format!("{lead}{}", info.describe(event)), width);
+            // kind is colored, the rest is plain text
+            let colored_len = text.len().min(lead.len());
+            lines.push(format!("{}{}", text[..colored_len].with(color), &text[colored_len..]));
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+
+    fn get_banner(&self) -> Option<String> {
+        self.layout_device.get_banner()
+    }
+}
+
+pub struct LayoutWatch {
+    tile_index: usize,
+    layout_header: String,
+    layout_bbox: LayoutBbox,
+    layout_device: WatchInfo,
+    show_history: bool,
+}
+
+impl LayoutWatch {
+    fn new(tile_index: usize, layout_header: String, layout_bbox: LayoutBbox, options: &WatchTileOptions) -> Self {
+        LayoutWatch {
+            tile_index,
+            layout_header: layout_header.bold().to_string(),
+            layout_bbox,
+            layout_device: WatchInfo::new(options),
+            show_history: options.history,
+        }
+    }
+
+    // graphs of all metrics start at the same column
+    fn calculate_metric(&self, name: &str, value: String, history: &History, max_value: f64) -> String {
+        let width = self.layout_bbox.width;
+        let lead = format!("{:<8}{:<20}", name, value);
+        if self.show_history {
+            calculate_sparkline(width, truncate_to_width(&lead, width), history, max_value)
+        } else {
+            truncate_to_width(lead.trim_end(), width)
+        }
+    }
+}
+
+impl Layout for LayoutWatch {
+    fn get_tile_index(&self) -> usize {
+        self.tile_index
+    }
+
+    fn set_position(&mut self, layout_bbox: LayoutBbox) {
+        self.layout_bbox = layout_bbox;
+    }
+
+    fn show_data(&mut self, stdout: &mut Stdout) {
+        self.layout_device.update();
+
+        execute!(
+            stdout,
+            MoveTo(self.layout_bbox.left, self.layout_bbox.top),
+            Print(&self.layout_header),
+        )
+        .unwrap();
+
+        let width = self.layout_bbox.width;
+        let info = &self.layout_device;
+        let mut lines: Vec<String> = Vec::new();
+        let restarts = match info.restarts {
+            0 => String::new(),
+            1 => ", restarted once".to_string(),
+            count => format!(", restarted {count} times"),
+        };
+        if !info.is_running() {
+            let waiting = format!("Waiting for {}{restarts}", info.get_waiting_for());
+            lines.push(truncate_to_width(&waiting, width).yellow().to_string());
+        } else {
+            lines.push(truncate_to_width(&format!("{}{restarts}", info.description), width).bold().to_string());
+        }
+
+        // histories keep the last run visible while waiting for a restart
+        lines.push(self.calculate_metric(
+            "CPU",
+            format!("{:.1}%", info.cpu),
+            &info.cpu_history,
+            info.cpu_history.max().max(100.0),
+        ));
+        lines.push(self.calculate_metric("RSS", format_bytes(info.rss), &info.rss_history, info.rss_history.max()));
+        if let Some(pss) = info.pss {
+            lines.push(self.calculate_metric("PSS", format_bytes(pss), &info.pss_history, info.pss_history.max()));
+        }
+        lines.push(self.calculate_metric(
+            "Threads",
+            info.threads.to_string(),
+            &info.threads_history,
+            info.threads_history.max(),
+        ));
+        lines.push(self.calculate_metric("FDs", info.fds.to_string(), &info.fds_history, info.fds_history.max()));
+        lines.push(self.calculate_metric(
+            "I/O",
+            format!("r {}/s w {}/s", format_bytes(info.read_rate), format_bytes(info.write_rate)),
+            &info.io_history,
+            info.io_history.max(),
+        ));
+        if let Some(gpu_memory) = info.gpu_memory {
+            lines.push(self.calculate_metric(
+                "GPU mem",
+                format_bytes(gpu_memory),
+                &info.gpu_history,
+                info.gpu_history.max(),
+            ));
+        }
+
+        for (ind, line) in lines.iter().take(self.layout_bbox.height.saturating_sub(1) as usize).enumerate() {
+            execute!(
+                stdout,
+                MoveTo(self.layout_bbox.left, self.layout_bbox.top + 1 + ind as u16),
+                Print(line)
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct Ui {
+    layouts: Vec<Box<dyn Layout>>,
+    // type of every layout, replayed tiles are numbered per type like the recorder does
+    layout_kinds: Vec<Discriminant<LayoutType>>,
+    // tile in layouts that gets the keys
+    focused: Option<usize>,
+    stdout: Stdout,
+    pub width: u16,
+    pub height: u16,
+    // right end of the status line, like the recording file
+    pub status: Option<(String, Color)>,
+    // recording shown by the recorded tiles, set before the layouts are created
+    pub replay: Option<Replay>,
+}
+
+impl Ui {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            layouts: Vec::new(),
+            layout_kinds: Vec::new(),
+            focused: None,
+            stdout: stdout(),
+            width: cols,
+            height: rows,
+            status: None,
+            replay: None,
+        }
+    }
+
+    pub fn create_layout(
+        &mut self,
+        tile_index: usize,
+        header: String,
+        bbox: LayoutBbox,
+        layout_type: LayoutType,
+    ) {
+        let layout_kind = discriminant(&layout_type);
+        let replayed = self
+            .replay
+            .as_ref()
+            .map(|_| self.layout_kinds.iter().filter(|kind| **kind == layout_kind).count());
+        self.layout_kinds.push(layout_kind);
+        let device: Box<dyn Layout> = match layout_type {
+            LayoutType::Gpu(options) => Box::new(LayoutGpu::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::Cpu(options) => Box::new(LayoutCPU::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::Network(options) => Box::new(LayoutNetwork::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::DiskIo(options) => Box::new(LayoutDiskIo::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::Filesystems(options) => {
+                Box::new(LayoutFilesystems::new(tile_index, header, bbox, &options, replayed))
+            }
+            LayoutType::Sensors(options) => Box::new(LayoutSensors::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::Battery(options) => Box::new(LayoutBattery::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::System(options) => Box::new(LayoutSystem::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::Cgroups(options) => Box::new(LayoutCgroups::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::Containers(options) => Box::new(LayoutContainers::new(tile_index, header, bbox, &options)),
+            LayoutType::Numa(options) => Box::new(LayoutNuma::new(tile_index, header, bbox, &options)),
+            LayoutType::Sockets(options) => Box::new(LayoutSockets::new(tile_index, header, bbox, &options)),
+            LayoutType::Interrupts(options) => Box::new(LayoutInterrupts::new(tile_index, header, bbox, &options)),
+            LayoutType::Power(options) => Box::new(LayoutPower::new(tile_index, header, bbox, &options, replayed)),
+            LayoutType::Command(options) => Box::new(LayoutCommand::new(tile_index, header, bbox, &options)),
+            LayoutType::Plugin(options) => Box::new(LayoutPlugin::new(tile_index, header, bbox, &options)),
+            LayoutType::Prometheus(options) => Box::new(LayoutPrometheus::new(tile_index, header, bbox, &options)),
+            LayoutType::Log(options) => Box::new(LayoutLog::new(tile_index, header, bbox, &options)),
+            LayoutType::Events(options) => Box::new(LayoutEvents::new(tile_index, header, bbox, &options)),
+            LayoutType::Watch(options) => Box::new(LayoutWatch::new(tile_index, header, bbox, &options)),
+        };
+        if self.focused.is_none() && device.takes_keys() {
+            self.focused = Some(self.layouts.len());
+        }
+        self.layouts.push(device);
+    }
+
+    pub fn update_all(&mut self, devices: &[DeviceTile]) {
+        self.clear_screen();
+
+        if let Some(replay) = &mut self.replay {
+            for sample in replay.advance() {
+                for device in &mut self.layouts {
+                    device.replay(&sample);
+                }
+            }
+            self.status = Some((replay.get_status(), Color::Cyan));
+        }
+
+        for device in &mut self.layouts {
+            device.set_position(devices[device.get_tile_index()].bbox());
+        }
+        for (ind, device) in self.layouts.iter_mut().enumerate() {
+            device.set_focused(self.focused == Some(ind));
+            device.show_data(&mut self.stdout);
+        }
+        self.show_status_line();
+    }
+
+    // collectors of every recorded tile, the recorder samples what is shown
+    pub fn get_record_sources(&self) -> Sources<'_> {
+        let mut sources = Sources::default();
+        for device in &self.layouts {
+            device.add_record_sources(&mut sources);
+        }
+        sources
+    }
+
+    // keys go to the replay or to the focused tile
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if let Some(replay) = &mut self.replay {
+            replay.handle_key(key);
+        } else if let (KeyCode::Tab, Some(focused)) = (key.code, self.focused) {
+            let count = self.layouts.len();
+            self.focused = (1..=count)
+                .map(|step| (focused + step) % count)
+                .find(|ind| self.layouts[*ind].takes_keys());
+        } else if let Some(device) = self.focused.and_then(|focused| self.layouts.get_mut(focused)) {
+            device.handle_key(key);
+        }
+    }
+
+    fn clear_screen(&mut self) {
+        execute!(&mut self.stdout, terminal::Clear(terminal::ClearType::All)).unwrap();
+    }
+
+    fn show_status_line(&mut self) {
+        execute!(
+            self.stdout,
+            MoveTo(0, self.height - 1),
+            SetForegroundColor(Color::Green),
+            Print("Press q for exit...".to_string()),
             ResetColor,
         )
         .unwrap();
+
+        let left = "Press q for exit... ".len() as u16;
+        if let Some((status, color)) = &self.status {
+            let status = truncate_to_width(status, self.width.saturating_sub(left));
+            execute!(
+                self.stdout,
+                MoveTo(self.width - status.as_str().width() as u16, self.height - 1),
+                Print(status.with(*color)),
+            )
+            .unwrap();
+        }
+
+        // fresh kernel events are shown even when their tile is too small to notice
+        let banner = self.layouts.iter().find_map(|device| device.get_banner());
+        if let Some(banner) = banner {
+            execute!(
+                self.stdout,
+                MoveTo(left, self.height - 1),
+                Print(truncate_to_width(&banner, self.width.saturating_sub(left)).red().bold()),
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // text as shown on the terminal, without color escapes
+    fn visible(text: &str) -> String {
+        let mut visible = String::new();
+        let mut escape = false;
+        for symbol in text.chars() {
+            match symbol {
+                '\x1b' => escape = true,
+                'm' if escape => escape = false,
+                _ if escape => {}
+                symbol => visible.push(symbol),
+            }
+        }
+        visible
+    }
+
+    #[test]
+    fn progress_bar_fills_the_share_of_the_width() {
+        let bar = visible(&calculate_progress_bar(20, "RAM[".to_string(), 0.5, "1/2]".to_string()));
+        assert_eq!(bar, "RAM[||||||      1/2]");
+        let full = visible(&calculate_progress_bar(20, "RAM[".to_string(), 1.0, "2/2]".to_string()));
+        assert_eq!(full, "RAM[||||||||||||2/2]");
+    }
+
+    #[test]
+    fn progress_bar_saturates_progress() {
+        let over = visible(&calculate_progress_bar(20, "RAM[".to_string(), 7.0, "9/2]".to_string()));
+        assert_eq!(over, "RAM[||||||||||||9/2]");
+        let under = visible(&calculate_progress_bar(20, "RAM[".to_string(), -1.0, "0/2]".to_string()));
+        assert_eq!(under, "RAM[            0/2]");
+        let unknown = visible(&calculate_progress_bar(20, "RAM[".to_string(), f64::NAN, "0/0]".to_string()));
+        assert_eq!(unknown, "RAM[            0/0]");
+    }
+
+    #[test]
+    fn progress_bar_cuts_text_wider_than_the_tile() {
+        let bar = calculate_progress_bar(8, "/very/long/mount/point[".to_string(), 0.5, "1G/2G]".to_string());
+        assert_eq!(bar, "/very/lo");
+        assert_eq!(calculate_progress_bar(0, "Mem[".to_string(), 0.5, "]".to_string()), "");
+    }
+
+    #[test]
+    fn stacked_bar_draws_segments_in_order() {
+        let segments = [(0.25, Color::Green), (0.5, Color::Red)];
+        let bar = visible(&calculate_stacked_bar(14, "C[".to_string(), &segments, "75%]".to_string()));
+        assert_eq!(bar, "C[||||||  75%]");
+    }
+
+    #[test]
+    fn stacked_bar_saturates_shares() {
+        let over = [(0.75, Color::Green), (0.75, Color::Red), (f64::NAN, Color::Blue), (-0.5, Color::Grey)];
+        let bar = visible(&calculate_stacked_bar(14, "C[".to_string(), &over, "150%]".to_string()));
+        assert_eq!(bar, "C[|||||||150%]");
+        let bar = calculate_stacked_bar(6, "cpu12[".to_string(), &over, "150%]".to_string());
+        assert_eq!(bar, "cpu12[");
+    }
+
+    #[test]
+    fn cpu_rows_keep_the_collector_index_and_the_cpu_number() {
+        // cpus 2 and 3 are offline, SMT siblings share the core id
+        let core = |cpu: usize, core_id: u32| CoreTopology {
+            cpu,
+            socket: (cpu / 4) as u32,
+            numa_node: None,
+            core_id,
+            kind: None,
+        };
+        let cores = vec![(0, core(0, 0)), (1, core(1, 1)), (2, core(4, 0)), (3, core(5, 0)), (4, core(6, 1))];
+
+        let rows = get_cpu_rows(cores.clone(), CpuGrouping::Socket);
+        let lines: Vec<(Option<&str>, usize, usize, &str)> = rows
+            .iter()
+            .map(|row| (row.header.as_deref(), row.index, row.core.cpu, row.bracket))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (Some("Socket 0"), 0, 0, " "),
+                (None, 1, 1, " "),
+                (Some("Socket 1"), 2, 4, "┌"),
+                (None, 3, 5, "└"),
+                (None, 4, 6, " "),
+            ]
+        );
+
+        let rows = get_cpu_rows(cores, CpuGrouping::None);
+        assert!(rows.iter().all(|row| row.header.is_none() && row.bracket.is_empty()));
+        assert_eq!(rows.iter().map(|row| row.core.cpu).collect::<Vec<usize>>(), [0, 1, 4, 5, 6]);
     }
 }
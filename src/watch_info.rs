@@ -0,0 +1,487 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::Instant;
+
+use nvml_wrapper::enums::device::UsedGpuMemory;
+use nvml_wrapper::struct_wrappers::device::ProcessInfo;
+use nvml_wrapper::Nvml;
+use regex::Regex;
+
+use crate::file_config::WatchTileOptions;
+use crate::history::History;
+
+pub enum WatchTarget {
+    Pid(u32),
+    // regex against name and command line
+    Pattern(Regex),
+    // exact name of a watched pid that exited
+    Name(String),
+}
+
+// one line of /proc/<pid>/stat
+struct ProcessEntry {
+    pid: u32,
+    ppid: u32,
+    name: String,
+    start_time: u64,
+    // user and system time in clock ticks
+    ticks: u64,
+    threads: u64,
+}
+
+// counters of a process from the previous update
+struct ProcessCounters {
+    ticks: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+pub struct WatchInfo {
+    procfs_root: PathBuf,
+    target: WatchTarget,
+    children: bool,
+    read_pss: bool,
+    nvml: Option<Nvml>,
+    clock_ticks: f64,
+    last_update: Instant,
+    // by pid and start time, a reused pid is another process
+    previous: HashMap<(u32, u64), ProcessCounters>,
+    root_name: String,
+    // like "python3 train.py --epochs 10 (4242)"
+    pub description: String,
+    pub pids: Vec<u32>,
+    // the group disappeared and came back this many times
+    pub restarts: u32,
+    // percent of one cpu
+    pub cpu: f64,
+    // bytes
+    pub rss: f64,
+    pub pss: Option<f64>,
+    pub threads: u64,
+    pub fds: u64,
+    // bytes per second
+    pub read_rate: f64,
+    pub write_rate: f64,
+    pub gpu_memory: Option<f64>,
+    pub cpu_history: History,
+    pub rss_history: History,
+    pub pss_history: History,
+    pub threads_history: History,
+    pub fds_history: History,
+    pub io_history: History,
+    pub gpu_history: History,
+}
+
+impl WatchInfo {
+    pub fn new(options: &WatchTileOptions) -> Self {
+        let target = match options.pid {
+            Some(pid) => WatchTarget::Pid(pid),
+            None if options.name.is_empty() => {
+                eprintln!("ERROR: watch tile needs a pid or a name");
+                exit(1)
+            }
+            None => WatchTarget::Pattern(Regex::new(&options.name).unwrap_or_else(|err| {
+                eprintln!("ERROR: Invalid watch name \"{}\": {err}", options.name);
+                exit(1)
+            })),
+        };
+
+        let mut watch_info = WatchInfo {
+            procfs_root: PathBuf::from(&options.procfs_root),
+            target,
+            children: options.children,
+            read_pss: options.pss,
+            nvml: if options.gpus { Nvml::init().ok() } else { None },
+            clock_ticks: get_clock_ticks(),
+            last_update: Instant::now(),
+            previous: HashMap::new(),
+            root_name: String::new(),
+            description: String::new(),
+            pids: Vec::new(),
+            restarts: 0,
+            cpu: 0.0,
+            rss: 0.0,
+            pss: None,
+            threads: 0,
+            fds: 0,
+            read_rate: 0.0,
+            write_rate: 0.0,
+            gpu_memory: None,
+            cpu_history: History::default(),
+            rss_history: History::default(),
+            pss_history: History::default(),
+            threads_history: History::default(),
+            fds_history: History::default(),
+            io_history: History::default(),
+            gpu_history: History::default(),
+        };
+        watch_info.update();
+        watch_info
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.pids.is_empty()
+    }
+
+    // shown while nothing matches
+    pub fn get_waiting_for(&self) -> String {
+        match &self.target {
+            WatchTarget::Pid(pid) => format!("pid {pid}"),
+            WatchTarget::Pattern(regex) => regex.as_str().to_string(),
+            WatchTarget::Name(name) => name.clone(),
+        }
+    }
+
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        let processes = read_processes(&self.procfs_root);
+        let was_running = self.is_running();
+        let roots = self.find_roots(&processes);
+
+        // a watched pid that exited is replaced by the next process with its name
+        if let (WatchTarget::Pid(_), true) = (&self.target, roots.is_empty()) {
+            if !self.root_name.is_empty() {
+                self.target = WatchTarget::Name(self.root_name.clone());
+            }
+        }
+
+        let group = self.collect_group(&processes, &roots);
+        self.pids = group.iter().map(|process| process.pid).collect();
+        if !self.is_running() {
+            self.previous.clear();
+            return;
+        }
+        if !was_running && !self.description.is_empty() {
+            self.restarts += 1;
+        }
+        self.description = self.describe(&roots, group.len());
+        self.root_name = roots.first().map(|root| root.name.clone()).unwrap_or_default();
+
+        let mut previous = HashMap::new();
+        let (mut ticks, mut read_bytes, mut write_bytes) = (0, 0, 0);
+        let (mut rss, mut pss, mut fds, mut threads) = (0.0, None, 0, 0);
+        for process in &group {
+            let process_dir = self.procfs_root.join(process.pid.to_string());
+            let (process_read, process_write) = read_io(&process_dir).unwrap_or((0, 0));
+            // processes that appeared since the last update count from now
+            if let Some(counters) = self.previous.get(&(process.pid, process.start_time)) {
+                ticks += process.ticks.saturating_sub(counters.ticks);
+                read_bytes += process_read.saturating_sub(counters.read_bytes);
+                write_bytes += process_write.saturating_sub(counters.write_bytes);
+            }
+            previous.insert(
+                (process.pid, process.start_time),
+                ProcessCounters {
+                    ticks: process.ticks,
+                    read_bytes: process_read,
+                    write_bytes: process_write,
+                },
+            );
+
+            rss += read_status_kib(&process_dir, "VmRSS:").unwrap_or(0) as f64 * 1024.0;
+            if self.read_pss {
+                // smaps_rollup of other users needs ptrace access
+                if let Some(process_pss) = read_pss_kib(&process_dir) {
+                    pss = Some(pss.unwrap_or(0.0) + process_pss as f64 * 1024.0);
+                }
+            }
+            threads += process.threads;
+            fds += fs::read_dir(process_dir.join("fd")).map(|entries| entries.count() as u64).unwrap_or(0);
+        }
+        self.previous = previous;
+
+        if elapsed > 0.0 {
+            self.cpu = ticks as f64 / self.clock_ticks / elapsed * 100.0;
+            self.read_rate = read_bytes as f64 / elapsed;
+            self.write_rate = write_bytes as f64 / elapsed;
+        }
+        self.rss = rss;
+        self.pss = pss;
+        self.threads = threads;
+        self.fds = fds;
+        self.gpu_memory = self.read_gpu_memory();
+
+        self.cpu_history.push(self.cpu);
+        self.rss_history.push(self.rss);
+        self.pss_history.push(self.pss.unwrap_or(0.0));
+        self.threads_history.push(self.threads as f64);
+        self.fds_history.push(self.fds as f64);
+        self.io_history.push(self.read_rate + self.write_rate);
+        self.gpu_history.push(self.gpu_memory.unwrap_or(0.0));
+    }
+
+    // matched processes whose parent is not matched too, like the launcher of a worker pool
+    fn find_roots<'a>(&self, processes: &'a [ProcessEntry]) -> Vec<&'a ProcessEntry> {
+        let own_pid = std::process::id();
+        let matched: Vec<&ProcessEntry> = processes
+            .iter()
+            .filter(|process| process.pid != own_pid)
+            .filter(|process| match &self.target {
+                WatchTarget::Pid(pid) => process.pid == *pid,
+                WatchTarget::Name(name) => &process.name == name,
+                WatchTarget::Pattern(regex) => {
+                    regex.is_match(&process.name) || regex.is_match(&read_cmdline(&self.procfs_root, process.pid))
+                }
+            })
+            .collect();
+        if !self.children {
+            return matched;
+        }
+        let matched_pids: HashSet<u32> = matched.iter().map(|process| process.pid).collect();
+        matched
+            .into_iter()
+            .filter(|process| !matched_pids.contains(&process.ppid))
+            .collect()
+    }
+
+    fn collect_group<'a>(&self, processes: &'a [ProcessEntry], roots: &[&'a ProcessEntry]) -> Vec<&'a ProcessEntry> {
+        let mut group: Vec<&ProcessEntry> = roots.to_vec();
+        if !self.children {
+            return group;
+        }
+        let mut children: HashMap<u32, Vec<&ProcessEntry>> = HashMap::new();
+        for process in processes {
+            children.entry(process.ppid).or_default().push(process);
+        }
+        // a root can be a descendant of another one, like python in python -> sh -> python
+        let mut seen: HashSet<u32> = group.iter().map(|process| process.pid).collect();
+        let mut ind = 0;
+        while ind < group.len() {
+            if let Some(process_children) = children.get(&group[ind].pid) {
+                for child in process_children {
+                    if seen.insert(child.pid) {
+                        group.push(child);
+                    }
+                }
+            }
+            ind += 1;
+        }
+        group
+    }
+
+    fn describe(&self, roots: &[&ProcessEntry], process_count: usize) -> String {
+        let Some(root) = roots.first() else {
+            return String::new();
+        };
+        let mut command_line = read_cmdline(&self.procfs_root, root.pid);
+        if command_line.is_empty() {
+            command_line = root.name.clone();
+        }
+        // name instead of the path of the executable
+        let command_line = match command_line.split_once(' ') {
+            Some((_, arguments)) => format!("{} {}", root.name, arguments),
+            None => root.name.clone(),
+        };
+        match process_count {
+            1 => format!("{command_line} ({})", root.pid),
+            count => format!("{command_line} ({}), {count} processes", root.pid),
+        }
+    }
+
+    // memory of the group on all gpus, None without NVML
+    fn read_gpu_memory(&self) -> Option<f64> {
+        let nvml = self.nvml.as_ref()?;
+        let pids: HashSet<u32> = self.pids.iter().copied().collect();
+        let mut used = 0;
+        for index in 0..nvml.device_count().ok()? {
+            let Ok(device) = nvml.device_by_index(index) else {
+                continue;
+            };
+            let mut processes = device.running_compute_processes().unwrap_or_default();
+            processes.extend(device.running_graphics_processes().unwrap_or_default());
+            used += get_device_memory(&processes, &pids);
+        }
+        Some(used as f64)
+    }
+}
+
+// a process using the device for compute and graphics is in both lists with the same memory
+fn get_device_memory(processes: &[ProcessInfo], pids: &HashSet<u32>) -> u64 {
+    let mut counted: HashSet<u32> = HashSet::new();
+    processes
+        .iter()
+        .filter(|process| pids.contains(&process.pid) && counted.insert(process.pid))
+        .map(|process| match process.used_gpu_memory {
+            UsedGpuMemory::Used(bytes) => bytes,
+            UsedGpuMemory::Unavailable => 0,
+        })
+        .sum()
+}
+
+fn read_processes(procfs_root: &Path) -> Vec<ProcessEntry> {
+    let Ok(entries) = fs::read_dir(procfs_root) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_string_lossy().parse::<u32>().ok()?;
+            let stat = fs::read_to_string(entry.path().join("stat")).ok()?;
+            // name may contain spaces and parentheses, fields after it are numbers
+            let (head, fields) = stat.rsplit_once(')')?;
+            let name = head.split_once('(')?.1.to_string();
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            // exited processes waiting for their parent
+            if fields.first() == Some(&"Z") {
+                return None;
+            }
+            let field = |ind: usize| fields.get(ind).and_then(|field| field.parse::<u64>().ok());
+            Some(ProcessEntry {
+                pid,
+                ppid: field(1)? as u32,
+                name,
+                ticks: field(11)? + field(12)?,
+                threads: field(17)?,
+                start_time: field(19)?,
+            })
+        })
+        .collect()
+}
+
+fn read_cmdline(procfs_root: &Path, pid: u32) -> String {
+    let cmdline = fs::read(procfs_root.join(pid.to_string()).join("cmdline")).unwrap_or_default();
+    String::from_utf8_lossy(&cmdline)
+        .split('\0')
+        .filter(|argument| !argument.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// "VmRSS:     1234 kB"
+fn read_status_kib(process_dir: &Path, field: &str) -> Option<u64> {
+    let status = fs::read_to_string(process_dir.join("status")).ok()?;
+    let line = status.lines().find(|line| line.starts_with(field))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn read_pss_kib(process_dir: &Path) -> Option<u64> {
+    let smaps = fs::read_to_string(process_dir.join("smaps_rollup")).ok()?;
+    let line = smaps.lines().find(|line| line.starts_with("Pss:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+// storage bytes, needs the same user or ptrace access
+fn read_io(process_dir: &Path) -> Option<(u64, u64)> {
+    let io = fs::read_to_string(process_dir.join("io")).ok()?;
+    let field = |name: &str| {
+        io.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    Some((field("read_bytes:")?, field("write_bytes:")?))
+}
+
+#[cfg(unix)]
+fn get_clock_ticks() -> f64 {
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 {
+        ticks as f64
+    } else {
+        100.0
+    }
+}
+
+#[cfg(not(unix))]
+fn get_clock_ticks() -> f64 {
+    100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixture::Fixture;
+    use std::time::Duration;
+
+    fn watch(fixture: &Fixture, name: &str) -> WatchInfo {
+        WatchInfo::new(&WatchTileOptions {
+            name: name.to_string(),
+            pss: false,
+            gpus: false,
+            procfs_root: fixture.root(),
+            ..WatchTileOptions::default()
+        })
+    }
+
+    #[test]
+    fn read_processes_parses_names_with_spaces_and_parentheses() {
+        let fixture = Fixture::new();
+        fixture.write_process(10, 1, "my (worker) 1", 100);
+        let processes = read_processes(fixture.path());
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].name, "my (worker) 1");
+        assert_eq!(processes[0].ppid, 1);
+        assert_eq!(processes[0].ticks, 10);
+        assert_eq!(processes[0].threads, 2);
+    }
+
+    #[test]
+    fn nested_matches_are_counted_once() {
+        let fixture = Fixture::new();
+        // python -> sh -> python -> worker
+        fixture.write_process(10, 1, "python", 100);
+        fixture.write_process(11, 10, "sh", 10);
+        fixture.write_process(12, 11, "python", 100);
+        fixture.write_process(13, 12, "worker", 1);
+        fixture.write_process(20, 1, "other", 1000);
+
+        let watch_info = watch(&fixture, "^python$");
+        let mut pids = watch_info.pids.clone();
+        pids.sort();
+        assert_eq!(pids, vec![10, 11, 12, 13]);
+        assert_eq!(watch_info.rss, 211.0 * 1024.0);
+        assert_eq!(watch_info.threads, 8);
+    }
+
+    #[test]
+    fn follows_the_name_of_a_watched_pid_that_exited() {
+        let fixture = Fixture::new();
+        fixture
+            .write_process(4242, 1, "trainer", 100)
+            .write("4242/smaps_rollup", "Rss:    100 kB\nPss:     60 kB\n")
+            .write("4242/io", "rchar: 1\nread_bytes: 1000\nwrite_bytes: 0\n");
+        let mut watch_info = WatchInfo::new(&WatchTileOptions {
+            pid: Some(4242),
+            gpus: false,
+            procfs_root: fixture.root(),
+            ..WatchTileOptions::default()
+        });
+        assert_eq!((watch_info.pids.clone(), watch_info.pss), (vec![4242], Some(60.0 * 1024.0)));
+
+        fixture.write("4242/io", "rchar: 1\nread_bytes: 5000\nwrite_bytes: 2000\n");
+        watch_info.last_update -= Duration::from_secs(2);
+        watch_info.update();
+        assert!((watch_info.read_rate - 2000.0).abs() < 20.0, "{}", watch_info.read_rate);
+        assert!((watch_info.write_rate - 1000.0).abs() < 10.0, "{}", watch_info.write_rate);
+
+        fs::remove_dir_all(fixture.path().join("4242")).unwrap();
+        watch_info.update();
+        assert!(!watch_info.is_running());
+        assert!(matches!(&watch_info.target, WatchTarget::Name(name) if name == "trainer"));
+        assert_eq!(watch_info.restarts, 0);
+
+        fixture.write_process(4343, 1, "trainer", 200);
+        watch_info.update();
+        assert_eq!(watch_info.pids, vec![4343]);
+        assert_eq!(watch_info.restarts, 1);
+        assert_eq!(watch_info.description, "trainer (4343)");
+        assert_eq!((watch_info.rss, watch_info.pss), (200.0 * 1024.0, None));
+        // the new process counts from now
+        assert_eq!(watch_info.read_rate, 0.0);
+    }
+
+    #[test]
+    fn counts_a_process_in_both_gpu_lists_once() {
+        let process = |pid: u32, bytes: u64| ProcessInfo {
+            pid,
+            used_gpu_memory: UsedGpuMemory::Used(bytes),
+            gpu_instance_id: None,
+            compute_instance_id: None,
+        };
+        let processes = [process(10, 1000), process(11, 200), process(10, 1000), process(12, 50)];
+        assert_eq!(get_device_memory(&processes, &HashSet::from([10, 12])), 1050);
+    }
+}